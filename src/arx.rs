@@ -0,0 +1,33 @@
+#![forbid(unsafe_code)]
+
+/// Round structure shared by ChaCha and Salsa20
+///
+/// Both permute a 4x4 state of `u32` words with double rounds of eight
+/// quarter rounds, four along the columns and four along the diagonals
+/// (ChaCha) or rows (Salsa20), and add the input state to the result. Only
+/// the quarter round and the words it is applied to differ.
+pub(crate) trait Arx {
+    /// Word indices of the eight quarter rounds of one double round, in order
+    const DOUBLE_ROUND: [(usize, usize, usize, usize); 8];
+
+    /// Applies the quarter round to words `vector` of `state`
+    fn quarter_round(state: &mut [u32; 16], vector: (usize, usize, usize, usize));
+
+    /// Applies `rounds` rounds to `working_state`, without the final addition
+    ///
+    /// `rounds` must be even.
+    fn apply_rounds(working_state: &mut [u32; 16], rounds: usize) {
+        for _ in 0..(rounds / 2) {
+            for vector in Self::DOUBLE_ROUND.iter() {
+                Self::quarter_round(working_state, *vector);
+            }
+        }
+    }
+}
+
+/// Adds the input `state` to the permuted `working_state`, word by word
+pub(crate) fn add_input(working_state: &mut [u32; 16], state: &[u32; 16]) {
+    for (word, input) in working_state.iter_mut().zip(state.iter()) {
+        *word = word.wrapping_add(*input);
+    }
+}
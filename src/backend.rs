@@ -0,0 +1,119 @@
+#![forbid(unsafe_code)]
+
+use crate::arx::{add_input, Arx};
+
+/// Block function behind a `ChaCha` instance
+///
+/// A core only runs the rounds. The state layout, counter, keystream
+/// buffering and limits all stay in `ChaCha`, so swapping the core can never
+/// change which keystream a key and nonce produce, only how it is computed.
+/// Implement it to plug in a SIMD or hardware backend, or an instrumented one
+/// for testing, and select it with the second type parameter, as in
+/// `ChaCha<20, MyCore>`. `Scalar` is the default.
+pub trait ChaChaCore {
+    /// Applies `rounds` ChaCha rounds to `working_state`, without the final addition
+    ///
+    /// `rounds` is always even; the rounds alternate column and diagonal
+    /// rounds starting with the columns.
+    fn permute(working_state: &mut [u32; 16], rounds: usize);
+
+    /// Returns the ChaCha block for `state`
+    fn block(state: &[u32; 16], rounds: usize) -> [u32; 16] {
+        let mut working_state = *state;
+
+        Self::permute(&mut working_state, rounds);
+        add_input(&mut working_state, state);
+
+        working_state
+    }
+}
+
+/// Portable implementation of the ChaCha rounds on `u32` words
+#[derive(Debug)]
+pub enum Scalar {}
+
+impl ChaChaCore for Scalar {
+    fn permute(working_state: &mut [u32; 16], rounds: usize) {
+        Scalar::apply_rounds(working_state, rounds);
+    }
+}
+
+impl Arx for Scalar {
+    const DOUBLE_ROUND: [(usize, usize, usize, usize); 8] = [
+        (0, 4, 8, 12),  // col 0
+        (1, 5, 9, 13),  // col 1
+        (2, 6, 10, 14), // col 2
+        (3, 7, 11, 15), // col 3
+        (0, 5, 10, 15), // diagonal 0
+        (1, 6, 11, 12), // diagonal 1
+        (2, 7, 8, 13),  // diagonal 2
+        (3, 4, 9, 14)   // diagonal 3
+    ];
+
+    /// Single ChaCha round
+    fn quarter_round(state: &mut [u32; 16], vector: (usize, usize, usize, usize)) {
+        let (a, b, c, d) = vector;
+
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(16);
+
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(12);
+
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(8);
+
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(7);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chacha20::{ChaCha, ChaCha20};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static BLOCKS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Counts the blocks generated and otherwise defers to `Scalar`
+    enum Counting {}
+
+    impl ChaChaCore for Counting {
+        fn permute(working_state: &mut [u32; 16], rounds: usize) {
+            BLOCKS.fetch_add(1, Ordering::SeqCst);
+            Scalar::permute(working_state, rounds);
+        }
+    }
+
+    #[test]
+    fn it_swaps_the_core_without_changing_the_keystream() {
+        let key = [7u8; 32];
+        let nonce = [9u8; 12];
+
+        let mut expected = [0u8; 200];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 1).apply_keystream(&mut expected);
+
+        let mut keystream = [0u8; 200];
+        ChaCha::<20, Counting>::new_with_raw_nonce(&key, &nonce, 1).apply_keystream(&mut keystream);
+
+        assert_eq!(&keystream[..], &expected[..]);
+        assert_eq!(BLOCKS.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn it_matches_the_rfc_8439_block() {
+        let mut state = [0u32; 16];
+        state[0..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+        for (i, word) in state[4..12].iter_mut().enumerate() {
+            let i = i as u32 * 4;
+            *word = u32::from_le_bytes([i as u8, i as u8 + 1, i as u8 + 2, i as u8 + 3]);
+        }
+        state[12..16].copy_from_slice(&[1, 0x09000000, 0x4a000000, 0]);
+
+        let block = Scalar::block(&state, 20);
+
+        assert_eq!(&block[0..4], &[0xe4e7f110, 0x15593bd1, 0x1fdd0f50, 0xc47120a3]);
+        assert_eq!(&block[12..16], &[0xd19c12b5, 0xb94e16de, 0xe883d0cb, 0x4e3c50a2]);
+    }
+}
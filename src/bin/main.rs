@@ -1,12 +1,590 @@
+#![forbid(unsafe_code)]
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
 use chacha20::chacha20::ChaCha20;
+use chacha20::key::Key;
+
+const USAGE: &str = "usage: chacha20 keystream --key HEX --nonce HEX --length N [--counter N] [--dry-run]
+       chacha20 stream --key HEX --nonce HEX [--counter N]
+       chacha20 xor --key HEX --nonce HEX --offset BYTES [--length N] [--counter N] [--dry-run] FILE
+       chacha20 fingerprint --key HEX";
+
+/// Options that take no value
+const FLAGS: &[&str] = &["--dry-run"];
+
+/// Size of the buffer used when generating keystream and patching files in place
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Exit status when stdout is closed before all output was written
+///
+/// Matches what a shell reports for a process killed by SIGPIPE (128 + 13),
+/// so pipelines like `chacha20 keystream ... | head -c 16` can tell a
+/// truncated write from both success and a real error.
+const EXIT_CLOSED_PIPE: i32 = 141;
+
+/// How long to wait before retrying a read or write on a non-blocking descriptor that is not ready
+const RETRY_DELAY: Duration = Duration::from_millis(1);
 
 fn main() {
-    let key = ChaCha20::create_key("01234567890123456789012345678901");
-    let nonce = ChaCha20::create_nonce("12 length k]");
-    let mut chacha = ChaCha20::new(&key, &nonce, 0 );
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("keystream") => keystream(&args[1..]),
+        Some("stream") => stream(&args[1..]),
+        Some("xor") => xor(&args[1..]),
+        Some("fingerprint") => fingerprint(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(failure) = result {
+        if let Failure::Message(message) = &failure {
+            eprintln!("chacha20: {}", message);
+        }
+        process::exit(failure.exit_code());
+    }
+}
+
+/// Why a subcommand stopped early
+#[derive(Debug, PartialEq)]
+enum Failure {
+    /// Stdout was closed by the reader, which is routine at the end of a pipeline
+    ClosedPipe,
+    /// Any other error, reported on stderr
+    Message(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Failure::ClosedPipe => EXIT_CLOSED_PIPE,
+            Failure::Message(_) => 1,
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure::Message(message)
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Failure {
+        Failure::Message(message.to_string())
+    }
+}
+
+/// Writes `--length` bytes of raw keystream to stdout
+fn keystream(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args)?;
+    if !options.positional.is_empty() {
+        return Err(format!("unexpected argument '{}'", options.positional[0]).into());
+    }
+
+    let key = options.key()?;
+    let nonce = options.nonce()?;
+    let counter = options.counter()?;
+    let length = options.number("--length")?.ok_or("missing --length")?;
+
+    let mut chacha = ChaCha20::new_with_raw_nonce(&key, &nonce, counter);
+    if !chacha.can_encrypt(length) {
+        return Err(format!("--length exceeds the {} bytes of keystream left for this counter", chacha.remaining_keystream()).into());
+    }
+
+    if options.flag("--dry-run") {
+        let blocks = (length + 63) / 64;
+        println!("would write {} bytes of keystream from {} blocks starting at counter {}", length, blocks, counter);
+        return Ok(());
+    }
+
+    let stdout = io::stdout();
+    let mut output = io::BufWriter::new(stdout.lock());
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut remaining = length;
+    while remaining > 0 {
+        let chunk = &mut buffer[..remaining.min(CHUNK_SIZE as u64) as usize];
+        chacha.fill_keystream(chunk);
+
+        write_output(&mut output, chunk)?;
+        remaining -= chunk.len() as u64;
+    }
+
+    flush_output(&mut output)
+}
+
+/// XORs keystream over stdin and writes the result to stdout
+///
+/// Encrypts and decrypts alike, for use in the middle of a pipeline. Output
+/// is flushed before returning, so nothing buffered is lost if the process
+/// exits right after.
+fn stream(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args)?;
+    if !options.positional.is_empty() {
+        return Err(format!("unexpected argument '{}'", options.positional[0]).into());
+    }
+
+    if options.flag("--dry-run") {
+        return Err("--dry-run needs a known length, use keystream or xor".into());
+    }
+
+    let mut chacha = ChaCha20::new_with_raw_nonce(&options.key()?, &options.nonce()?, options.counter()?);
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut output = io::BufWriter::new(stdout.lock());
+
+    xor_stream(&mut chacha, &mut stdin.lock(), &mut output)
+}
+
+/// XORs keystream over everything read from `input` and writes it to `output`, then flushes `output`
+fn xor_stream(chacha: &mut ChaCha20, input: &mut impl Read, output: &mut impl Write) -> Result<(), Failure> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let length = read_chunk(input, &mut buffer).map_err(|e| format!("stdin: {}", e))?;
+        if length == 0 {
+            break;
+        }
+
+        let chunk = &mut buffer[..length];
+        chacha.try_apply_keystream(chunk).map_err(|_| "input exceeds the keystream left for this counter")?;
+        write_output(output, chunk)?;
+    }
+
+    flush_output(output)
+}
+
+/// XORs keystream over a region of a file in place
+///
+/// The keystream position matches the file position, so `--offset` selects
+/// both where in the file and where in the stream the patch starts.
+fn xor(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args)?;
+    let path = match options.positional.as_slice() {
+        [path] => *path,
+        _ => return Err("expected exactly one FILE".into()),
+    };
+
+    let key = options.key()?;
+    let nonce = options.nonce()?;
+    let counter = options.counter()?;
+    let offset = options.number("--offset")?.ok_or("missing --offset")?;
+
+    let mut file = OpenOptions::new().read(true).write(true).open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let file_length = file.metadata().map_err(|e| e.to_string())?.len();
+    if offset > file_length {
+        return Err(format!("--offset is past the end of {}", path).into());
+    }
+
+    let length = options.number("--length")?.unwrap_or(file_length - offset);
+    if length > file_length - offset {
+        return Err(format!("--length runs past the end of {}", path).into());
+    }
+
+    if length == 0 {
+        return Ok(());
+    }
+
+    let (first_block, last_block) = region_blocks(counter, offset, length)?;
+
+    if options.flag("--dry-run") {
+        let chunks = (length + CHUNK_SIZE as u64 - 1) / CHUNK_SIZE as u64;
+        println!(
+            "would patch {} bytes of {} at offset {} using blocks {} to {} in {} chunks of up to {} bytes",
+            length,
+            path,
+            offset,
+            first_block,
+            last_block,
+            chunks,
+            CHUNK_SIZE
+        );
+        return Ok(());
+    }
+
+    let mut chacha = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+    chacha.seek(keystream_position(counter, offset));
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut position = offset;
+    let end = offset + length;
+
+    while position < end {
+        let chunk = &mut buffer[..(end - position).min(CHUNK_SIZE as u64) as usize];
+
+        file.seek(SeekFrom::Start(position)).map_err(|e| e.to_string())?;
+        file.read_exact(chunk).map_err(|e| e.to_string())?;
+
+        chacha.apply_keystream(chunk);
+
+        file.seek(SeekFrom::Start(position)).map_err(|e| e.to_string())?;
+        file.write_all(chunk).map_err(|e| e.to_string())?;
+
+        position += chunk.len() as u64;
+    }
+
+    file.sync_all().map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Prints the fingerprint of `--key` as hex
+fn fingerprint(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args)?;
+    if !options.positional.is_empty() {
+        return Err(format!("unexpected argument '{}'", options.positional[0]).into());
+    }
+
+    let fingerprint = Key::new(options.key()?).fingerprint();
+    println!("{}", fingerprint.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+
+    Ok(())
+}
+
+/// Returns the blocks holding the first and last byte of a non-empty region, counted from block 0 of the stream
+///
+/// Fails if the last one lies past the end of the 32-bit counter.
+fn region_blocks(counter: u32, offset: u64, length: u64) -> Result<(u64, u64), String> {
+    let first_block = counter as u64 + offset / 64;
+    let last_block = counter as u64 + (offset + length - 1) / 64;
+    if last_block > u32::MAX as u64 {
+        return Err("region runs past the end of the 32-bit counter".to_string());
+    }
+
+    Ok((first_block, last_block))
+}
+
+/// Returns the keystream byte position of file offset `offset` for a stream starting at block `counter`
+fn keystream_position(counter: u32, offset: u64) -> u64 {
+    counter as u64 * 64 + offset
+}
+
+/// Reads from `input` until `buffer` is full or the input ends, and returns the number of bytes read
+///
+/// Short reads are continued, and reads that were interrupted by a signal or
+/// found a non-blocking descriptor empty are retried.
+fn read_chunk(input: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(length) => filled += length,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(RETRY_DELAY),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Writes all of `bytes` to stdout
+///
+/// Short writes are continued, and writes that were interrupted by a signal
+/// or found a non-blocking descriptor full are retried.
+fn write_output(output: &mut impl Write, mut bytes: &[u8]) -> Result<(), Failure> {
+    while !bytes.is_empty() {
+        match output.write(bytes) {
+            Ok(0) => return Err(check_output(io::ErrorKind::WriteZero.into())),
+            Ok(length) => bytes = &bytes[length..],
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(RETRY_DELAY),
+            Err(e) => return Err(check_output(e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Flushes stdout, retrying like `write_output`
+fn flush_output(output: &mut impl Write) -> Result<(), Failure> {
+    loop {
+        match output.flush() {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(RETRY_DELAY),
+            Err(e) => return Err(check_output(e)),
+        }
+    }
+}
+
+/// Converts an error writing to stdout, telling a closed pipe apart from other errors
+fn check_output(error: io::Error) -> Failure {
+    match error.kind() {
+        io::ErrorKind::BrokenPipe => Failure::ClosedPipe,
+        _ => Failure::Message(format!("stdout: {}", error)),
+    }
+}
+
+/// `--name value` pairs, flags and positional arguments passed to a subcommand
+struct Options<'a> {
+    pairs: Vec<(&'a str, &'a str)>,
+    flags: Vec<&'a str>,
+    positional: Vec<&'a str>,
+}
+
+impl<'a> Options<'a> {
+    fn parse(args: &'a [String]) -> Result<Options<'a>, String> {
+        let mut pairs = Vec::new();
+        let mut flags = Vec::new();
+        let mut positional = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(name) = iter.next() {
+            if !name.starts_with("--") {
+                positional.push(name.as_str());
+                continue;
+            }
+
+            if FLAGS.contains(&name.as_str()) {
+                flags.push(name.as_str());
+                continue;
+            }
+
+            let value = iter.next().ok_or(format!("missing value for {}", name))?;
+            pairs.push((name.as_str(), value.as_str()));
+        }
+
+        Ok(Options { pairs, flags, positional })
+    }
+
+    fn get(&self, name: &str) -> Option<&'a str> {
+        self.pairs.iter().rev().find(|(key, _)| *key == name).map(|(_, value)| *value)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+
+    fn key(&self) -> Result<[u8; 32], String> {
+        let mut key = [0u8; 32];
+        decode_hex(self.get("--key").ok_or("missing --key")?, &mut key).map_err(|e| format!("--key: {}", e))?;
+
+        Ok(key)
+    }
+
+    fn nonce(&self) -> Result<[u8; 12], String> {
+        let mut nonce = [0u8; 12];
+        decode_hex(self.get("--nonce").ok_or("missing --nonce")?, &mut nonce).map_err(|e| format!("--nonce: {}", e))?;
+
+        Ok(nonce)
+    }
+
+    fn counter(&self) -> Result<u32, String> {
+        match self.number("--counter")? {
+            Some(counter) if counter > u32::MAX as u64 => Err("--counter must fit in 32 bits".to_string()),
+            Some(counter) => Ok(counter as u32),
+            None => Ok(0),
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u64>, String> {
+        match self.get(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| format!("{} must be a number", name)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Decodes `hex` into `output`, which must match its decoded length exactly
+fn decode_hex(hex: &str, output: &mut [u8]) -> Result<(), String> {
+    if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err("invalid hex".to_string());
+    }
+
+    if hex.len() != output.len() * 2 {
+        return Err(format!("expected {} hex characters", output.len() * 2));
+    }
+
+    for (i, byte) in output.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[(i * 2)..(i * 2 + 2)], 16).map_err(|_| "invalid hex".to_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    /// Reader and writer that move at most 3 bytes per call and fail every other call
+    struct Trickle {
+        data: Vec<u8>,
+        position: usize,
+        calls: usize,
+        flushed: bool,
+    }
+
+    impl Trickle {
+        fn new(data: &[u8]) -> Trickle {
+            Trickle { data: data.to_vec(), position: 0, calls: 0, flushed: false }
+        }
+
+        fn interrupt(&mut self) -> io::Result<()> {
+            self.calls += 1;
+            match self.calls % 4 {
+                1 => Err(io::ErrorKind::Interrupted.into()),
+                3 => Err(io::ErrorKind::WouldBlock.into()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt()?;
+            let length = buf.len().min(3).min(self.data.len() - self.position);
+            buf[..length].copy_from_slice(&self.data[self.position..self.position + length]);
+            self.position += length;
+
+            Ok(length)
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.interrupt()?;
+            let length = buf.len().min(3);
+            self.data.extend_from_slice(&buf[..length]);
+            self.flushed = false;
+
+            Ok(length)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.interrupt()?;
+            self.flushed = true;
+
+            Ok(())
+        }
+    }
+
+    /// Writer whose reader has gone away
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn it_parses_pairs_flags_and_positional_arguments() {
+        let args = args(&["--offset", "10", "file", "--dry-run", "--offset", "20"]);
+        let options = Options::parse(&args).unwrap();
+
+        assert_eq!(options.number("--offset"), Ok(Some(20)));
+        assert_eq!(options.number("--length"), Ok(None));
+        assert!(options.flag("--dry-run"));
+        assert_eq!(options.positional, vec!["file"]);
+    }
+
+    #[test]
+    fn it_rejects_missing_and_malformed_values() {
+        assert_eq!(Options::parse(&args(&["--key"])).err(), Some("missing value for --key".to_string()));
+
+        let args = args(&["--offset", "ten", "--counter", "4294967296"]);
+        let options = Options::parse(&args).unwrap();
+        assert_eq!(options.number("--offset"), Err("--offset must be a number".to_string()));
+        assert_eq!(options.counter(), Err("--counter must fit in 32 bits".to_string()));
+        assert_eq!(options.key(), Err("missing --key".to_string()));
+    }
+
+    #[test]
+    fn it_decodes_hex() {
+        let mut output = [0u8; 3];
+
+        assert_eq!(decode_hex("00aBff", &mut output), Ok(()));
+        assert_eq!(output, [0x00, 0xab, 0xff]);
+        assert_eq!(decode_hex("00ab", &mut output), Err("expected 6 hex characters".to_string()));
+        assert_eq!(decode_hex("00abfg", &mut output), Err("invalid hex".to_string()));
+        assert_eq!(decode_hex("+0abff", &mut output), Err("invalid hex".to_string()));
+    }
+
+    #[test]
+    fn it_locates_the_blocks_of_a_region() {
+        assert_eq!(region_blocks(0, 0, 1), Ok((0, 0)));
+        assert_eq!(region_blocks(0, 63, 2), Ok((0, 1)));
+        assert_eq!(region_blocks(5, 64, 64), Ok((6, 6)));
+        assert_eq!(region_blocks(u32::MAX, 0, 64), Ok((u32::MAX as u64, u32::MAX as u64)));
+        assert!(region_blocks(u32::MAX, 0, 65).is_err());
+        assert!(region_blocks(u32::MAX - 1, 100, 100).is_err());
+    }
+
+    #[test]
+    fn it_seeks_to_the_keystream_of_the_file_offset() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+
+        let mut expected = [0u8; 100];
+        let mut skipped = [0u8; 70];
+        let mut reference = ChaCha20::new_with_raw_nonce(&key, &nonce, 3);
+        reference.fill_keystream(&mut skipped);
+        reference.fill_keystream(&mut expected);
+
+        let mut keystream = [0u8; 100];
+        let mut chacha = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        chacha.seek(keystream_position(3, 70));
+        chacha.fill_keystream(&mut keystream);
+
+        assert_eq!(&keystream[..], &expected[..]);
+    }
+
+    #[test]
+    fn it_survives_short_interrupted_and_would_block_io() {
+        let plaintext: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let mut expected = plaintext.clone();
+        ChaCha20::new_with_raw_nonce(&[1u8; 32], &[2u8; 12], 3).apply_keystream(&mut expected);
+
+        let mut input = Trickle::new(&plaintext);
+        let mut output = Trickle::new(b"");
+        let mut chacha = ChaCha20::new_with_raw_nonce(&[1u8; 32], &[2u8; 12], 3);
+        assert_eq!(xor_stream(&mut chacha, &mut input, &mut output), Ok(()));
+
+        assert_eq!(output.data, expected);
+        assert!(output.flushed);
+    }
+
+    #[test]
+    fn it_flushes_buffered_output_before_returning() {
+        let mut inner = Trickle::new(b"");
+        let mut chacha = ChaCha20::new_with_raw_nonce(&[1u8; 32], &[2u8; 12], 0);
+        let mut output = io::BufWriter::new(&mut inner);
+        assert_eq!(xor_stream(&mut chacha, &mut &[0u8; 100][..], &mut output), Ok(()));
+        assert!(output.buffer().is_empty());
+        assert!(output.get_ref().flushed);
+        drop(output);
+
+        assert_eq!(inner.data.len(), 100);
+    }
+
+    #[test]
+    fn it_exits_with_a_distinct_code_on_a_closed_pipe() {
+        let mut chacha = ChaCha20::new_with_raw_nonce(&[1u8; 32], &[2u8; 12], 0);
+        let failure = xor_stream(&mut chacha, &mut &[0u8; 100][..], &mut ClosedPipe).unwrap_err();
 
-    println!("{:?}", chacha);
-    for i in 0..10 {
-        println!("{:?}", chacha.next());
+        assert_eq!(failure, Failure::ClosedPipe);
+        assert_eq!(failure.exit_code(), EXIT_CLOSED_PIPE);
+        assert_eq!(flush_output(&mut ClosedPipe), Err(Failure::ClosedPipe));
+        assert_eq!(check_output(io::ErrorKind::PermissionDenied.into()).exit_code(), 1);
     }
 }
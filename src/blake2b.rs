@@ -0,0 +1,160 @@
+#![forbid(unsafe_code)]
+
+use crate::byte_manipulation::{read_u64_array, wipe, Endian, LittleEndian};
+
+/// Initialization vector, the same as SHA-512's
+const IV: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179
+];
+
+/// Message word schedule of each of the 12 rounds
+const SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3]
+];
+
+/// Size of the blocks the message is compressed in
+const BLOCK_LEN: usize = 128;
+
+/// Mixes two message words into four words of the working state
+fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// Compresses one block into the chain value `h`
+///
+/// `counter` is the number of message bytes hashed so far, including this
+/// block, and `last` marks the final block.
+fn compress(h: &mut [u64; 8], block: &[u8; BLOCK_LEN], counter: u128, last: bool) {
+    let mut m: [u64; 16] = read_u64_array::<LittleEndian, 16>(block);
+
+    let mut v = [0u64; 16];
+    v[0..8].copy_from_slice(h);
+    v[8..16].copy_from_slice(&IV);
+    v[12] ^= counter as u64;
+    v[13] ^= (counter >> 64) as u64;
+    if last {
+        v[14] = !v[14];
+    }
+
+    for s in SIGMA.iter() {
+        g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+    wipe(&mut m);
+    wipe(&mut v);
+}
+
+/// Unkeyed BLAKE2b (RFC 7693) of `data`, written to the whole of `out`
+///
+/// Only what the crate needs: the digest size is the length of `out`, and
+/// the message is given at once.
+///
+/// # Panics
+///
+/// The function will panic if `out` is empty or longer than 64 bytes
+pub(crate) fn blake2b(data: &[u8], out: &mut [u8]) {
+    assert!(!out.is_empty() && out.len() <= 64);
+
+    let mut h = IV;
+    h[0] ^= 0x01010000 ^ out.len() as u64;
+
+    let mut block = [0u8; BLOCK_LEN];
+    let mut hashed = 0u128;
+    let mut chunks = data.chunks(BLOCK_LEN).peekable();
+    if chunks.peek().is_none() {
+        compress(&mut h, &block, 0, true);
+    }
+    while let Some(chunk) = chunks.next() {
+        block = [0u8; BLOCK_LEN];
+        block[..chunk.len()].copy_from_slice(chunk);
+        hashed += chunk.len() as u128;
+        compress(&mut h, &block, hashed, chunks.peek().is_none());
+    }
+    wipe(&mut block);
+
+    let mut digest = [0u8; 64];
+    for (word, bytes) in h.iter().zip(digest.chunks_mut(8)) {
+        LittleEndian::write_u64(*word, bytes);
+    }
+    out.copy_from_slice(&digest[..out.len()]);
+    wipe(&mut digest);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_hashes_the_rfc_7693_vector() {
+        let expected: [u8; 64] = [
+            0xba, 0x80, 0xa5, 0x3f, 0x98, 0x1c, 0x4d, 0x0d,
+            0x6a, 0x27, 0x97, 0xb6, 0x9f, 0x12, 0xf6, 0xe9,
+            0x4c, 0x21, 0x2f, 0x14, 0x68, 0x5a, 0xc4, 0xb7,
+            0x4b, 0x12, 0xbb, 0x6f, 0xdb, 0xff, 0xa2, 0xd1,
+            0x7d, 0x87, 0xc5, 0x39, 0x2a, 0xab, 0x79, 0x2d,
+            0xc2, 0x52, 0xd5, 0xde, 0x45, 0x33, 0xcc, 0x95,
+            0x18, 0xd3, 0x8a, 0xa8, 0xdb, 0xf1, 0x92, 0x5a,
+            0xb9, 0x23, 0x86, 0xed, 0xd4, 0x00, 0x99, 0x23
+        ];
+
+        let mut digest = [0u8; 64];
+        blake2b(b"abc", &mut digest);
+
+        assert_eq!(digest, expected);
+    }
+
+    #[test]
+    fn it_hashes_short_digests() {
+        let empty: [u8; 24] = [
+            0xab, 0x3b, 0x53, 0x31, 0xa7, 0x13, 0x5e, 0xd5,
+            0x0d, 0x0f, 0x18, 0x2d, 0x02, 0x6e, 0x60, 0xab,
+            0xdb, 0x36, 0x46, 0xfd, 0x51, 0xbc, 0xf8, 0xa3
+        ];
+        let two_blocks: [u8; 24] = [
+            0xe9, 0x18, 0x3c, 0x76, 0x64, 0x51, 0xac, 0x26,
+            0xdd, 0xd0, 0x3b, 0x28, 0x0b, 0x4b, 0x60, 0x00,
+            0x80, 0x2b, 0x4b, 0x72, 0x30, 0xc7, 0x8b, 0xb3
+        ];
+
+        let mut data = [0u8; 200];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let mut digest = [0u8; 24];
+        blake2b(b"", &mut digest);
+        assert_eq!(digest, empty);
+
+        blake2b(&data, &mut digest);
+        assert_eq!(digest, two_blocks);
+    }
+}
@@ -0,0 +1,175 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{constant_time_eq, wipe};
+use crate::error::Error;
+use crate::salsa20::hsalsa20;
+use crate::secretbox;
+use crate::x25519::{x25519, x25519_base};
+
+/// Returns the public key for `secret_key`, like libsodium's `crypto_scalarmult_base`
+///
+/// Any 32 bytes from a secure random source make a valid secret key.
+///
+/// # Panics
+///
+/// The function will panic if `secret_key` is not of size 32
+pub fn public_key(secret_key: &[u8]) -> [u8; 32] {
+    assert_eq!(secret_key.len(), 32);
+
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(secret_key);
+    let public = x25519_base(&scalar);
+    wipe(&mut scalar);
+
+    public
+}
+
+/// Derives the secretbox key shared with the owner of `public_key`, like `crypto_box_beforenm`
+///
+/// The X25519 shared secret is passed through `hsalsa20` with a zero input.
+/// Both parties get the same key, so it can be computed once and used with
+/// `secretbox::seal` and `secretbox::open` for many messages.
+///
+/// Returns `Error::InvalidPublicKey` if `public_key` is of small order, since
+/// the shared secret would then be zero whatever `secret_key` is.
+///
+/// # Panics
+///
+/// The function will panic if `public_key` is not of size 32
+/// The function will panic if `secret_key` is not of size 32
+pub fn precompute(public_key: &[u8], secret_key: &[u8]) -> Result<[u8; 32], Error> {
+    assert_eq!(public_key.len(), 32);
+    assert_eq!(secret_key.len(), 32);
+
+    let mut point = [0u8; 32];
+    point.copy_from_slice(public_key);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(secret_key);
+
+    let mut shared = x25519(&scalar, &point);
+    wipe(&mut scalar);
+
+    if constant_time_eq(&shared, &[0u8; 32]) {
+        return Err(Error::InvalidPublicKey);
+    }
+
+    let key = hsalsa20(&shared, &[0u8; 16]);
+    wipe(&mut shared);
+
+    Ok(key)
+}
+
+/// Encrypts `plaintext` from the owner of `secret_key` to the owner of `public_key`
+///
+/// The output matches libsodium's `crypto_box_easy`: the 16-byte tag
+/// followed by the ciphertext. A nonce must never be reused for the same pair
+/// of keys, in either direction.
+///
+/// # Panics
+///
+/// The function will panic if `public_key` is not of size 32
+/// The function will panic if `secret_key` is not of size 32
+/// The function will panic if `nonce` is not of size 24
+pub fn seal(public_key: &[u8], secret_key: &[u8], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut key = precompute(public_key, secret_key)?;
+    let sealed = secretbox::seal(&key, nonce, plaintext);
+    wipe(&mut key);
+
+    Ok(sealed)
+}
+
+/// Verifies and decrypts a box sent by the owner of `public_key` to the owner of `secret_key`
+///
+/// Returns `Error::InvalidTag` if the box was not sealed by that sender for
+/// this recipient and nonce, or has been modified.
+///
+/// # Panics
+///
+/// The function will panic if `public_key` is not of size 32
+/// The function will panic if `secret_key` is not of size 32
+/// The function will panic if `nonce` is not of size 24
+pub fn open(public_key: &[u8], secret_key: &[u8], nonce: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut key = precompute(public_key, secret_key)?;
+    let opened = secretbox::open(&key, nonce, sealed);
+    wipe(&mut key);
+
+    opened
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALICE_SECRET: [u8; 32] = [
+        0x77, 0x07, 0x6d, 0x0a, 0x73, 0x18, 0xa5, 0x7d,
+        0x3c, 0x16, 0xc1, 0x72, 0x51, 0xb2, 0x66, 0x45,
+        0xdf, 0x4c, 0x2f, 0x87, 0xeb, 0xc0, 0x99, 0x2a,
+        0xb1, 0x77, 0xfb, 0xa5, 0x1d, 0xb9, 0x2c, 0x2a
+    ];
+
+    const BOB_SECRET: [u8; 32] = [
+        0x5d, 0xab, 0x08, 0x7e, 0x62, 0x4a, 0x8a, 0x4b,
+        0x79, 0xe1, 0x7f, 0x8b, 0x83, 0x80, 0x0e, 0xe6,
+        0x6f, 0x3b, 0xb1, 0x29, 0x26, 0x18, 0xb6, 0xfd,
+        0x1c, 0x2f, 0x8b, 0x27, 0xff, 0x88, 0xe0, 0xeb
+    ];
+
+    const NONCE: [u8; 24] = [
+        0x69, 0x69, 0x6e, 0xe9, 0x55, 0xb6, 0x2b, 0x73,
+        0xcd, 0x62, 0xbd, 0xa8, 0x75, 0xfc, 0x73, 0xd6,
+        0x82, 0x19, 0xe0, 0x03, 0x6b, 0x7a, 0x0b, 0x37
+    ];
+
+    #[test]
+    fn it_derives_the_libsodium_shared_key() {
+        let expected: [u8; 32] = [
+            0x1b, 0x27, 0x55, 0x64, 0x73, 0xe9, 0x85, 0xd4,
+            0x62, 0xcd, 0x51, 0x19, 0x7a, 0x9a, 0x46, 0xc7,
+            0x60, 0x09, 0x54, 0x9e, 0xac, 0x64, 0x74, 0xf2,
+            0x06, 0xc4, 0xee, 0x08, 0x44, 0xf6, 0x83, 0x89
+        ];
+
+        let bob_public = public_key(&BOB_SECRET);
+        let alice_public = public_key(&ALICE_SECRET);
+
+        assert_eq!(precompute(&bob_public, &ALICE_SECRET), Ok(expected));
+        assert_eq!(precompute(&alice_public, &BOB_SECRET), Ok(expected));
+    }
+
+    #[test]
+    fn it_matches_libsodium() {
+        let expected: [u8; 45] = [
+            0x60, 0xc4, 0xd7, 0x8d, 0xb9, 0xd0, 0x68, 0x7b,
+            0x76, 0x9b, 0xf6, 0xf9, 0xea, 0xa3, 0x36, 0x92,
+            0x71, 0xf2, 0x0d, 0x39, 0x11, 0xc9, 0x94, 0xc9,
+            0x2d, 0xc0, 0x2c, 0xce, 0xf5, 0x37, 0x15, 0xc3,
+            0x7f, 0x69, 0xcb, 0xce, 0x2f, 0x5d, 0x2b, 0x15,
+            0x3e, 0x3c, 0x09, 0x6f, 0x6c
+        ];
+
+        let plaintext = b"Alice to Bob, over Curve25519";
+        let bob_public = public_key(&BOB_SECRET);
+        let alice_public = public_key(&ALICE_SECRET);
+
+        let sealed = seal(&bob_public, &ALICE_SECRET, &NONCE, plaintext).unwrap();
+        assert_eq!(&sealed[..], &expected[..]);
+        assert_eq!(open(&alice_public, &BOB_SECRET, &NONCE, &sealed), Ok(plaintext.to_vec()));
+    }
+
+    #[test]
+    fn it_rejects_the_wrong_sender() {
+        let bob_public = public_key(&BOB_SECRET);
+        let sealed = seal(&bob_public, &ALICE_SECRET, &NONCE, b"hello").unwrap();
+
+        let mallory_public = public_key(&[7u8; 32]);
+        assert_eq!(open(&mallory_public, &BOB_SECRET, &NONCE, &sealed), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_rejects_small_order_public_keys() {
+        assert_eq!(precompute(&[0u8; 32], &ALICE_SECRET), Err(Error::InvalidPublicKey));
+        assert_eq!(seal(&[0u8; 32], &ALICE_SECRET, &NONCE, b"hello"), Err(Error::InvalidPublicKey));
+    }
+}
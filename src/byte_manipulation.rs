@@ -1,51 +1,352 @@
-/// Return a u32 from a str in little endian format
-///
-/// # Panics
-///
-/// This code will panic if the string is not 4 bytes long
-#[allow(dead_code)]
-pub fn string_to_u32_le(string: &str) -> u32 {
-    assert_eq!(string.bytes().len(), 4);
-
-    let mut output = 0u32;
-    for (i, byte) in string.bytes().enumerate() {
-        output |= (0x000000FF << (i * 8)) & ((byte as u32) << (i * 8));
-    }
-
-    output
-}
-
-/// Returns a u32 from a u8 array in little endian format
-///
-/// # Panics
-///
-/// This code will panic if the u8 array is not of length 4
-#[allow(dead_code)]
-pub fn u8_array_to_u32_le(arr: &[u8]) -> u32 {
-    assert_eq!(arr.len(), 4);
-
-    let mut output = 0u32;
-    output |= 0x000000FF & (arr[0] as u32);
-    output |= 0x0000FF00 & ((arr[1] as u32) << 8);
-    output |= 0x00FF0000 & ((arr[2] as u32) << 16);
-    output |= 0xFF000000 & ((arr[3] as u32) << 24);
-
-    output
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn it_converts_u8_array_to_u32() {
-        let arr = [1u8, 1u8, 1u8, 1u8];
-        assert_eq!(16843009, u8_array_to_u32_le(&arr));
-    }
-
-    #[test]
-    fn it_converts_string_to_u32() {
-        let str = "AAAA";
-        assert_eq!(1094795585, string_to_u32_le(str));
-    }
-}
\ No newline at end of file
+#![forbid(unsafe_code)]
+
+pub use crate::mem::wipe;
+
+/// Byte order used to convert between integers and bytes
+///
+/// Implemented by `LittleEndian` and `BigEndian`, so conversions can be
+/// written once and parameterized over the byte order.
+pub trait Endian {
+    /// Returns a u32 from a u8 slice
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if the slice is not of length 4
+    fn read_u32(bytes: &[u8]) -> u32;
+
+    /// Writes a u32 into a u8 slice
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if the slice is not of length 4
+    fn write_u32(value: u32, bytes: &mut [u8]);
+
+    /// Returns a u64 from a u8 slice
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if the slice is not of length 8
+    fn read_u64(bytes: &[u8]) -> u64;
+
+    /// Writes a u64 into a u8 slice
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if the slice is not of length 8
+    fn write_u64(value: u64, bytes: &mut [u8]);
+
+    /// Reads consecutive u32 values from `src` into `dst`
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if `src` is not exactly 4 times the length of `dst`
+    fn read_u32_into(src: &[u8], dst: &mut [u32]) {
+        assert_eq!(src.len(), dst.len() * 4);
+
+        for (word, chunk) in dst.iter_mut().zip(src.chunks_exact(4)) {
+            *word = Self::read_u32(chunk);
+        }
+    }
+
+    /// Writes consecutive u32 values from `src` into `dst`
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if `dst` is not exactly 4 times the length of `src`
+    fn write_u32_into(src: &[u32], dst: &mut [u8]) {
+        assert_eq!(dst.len(), src.len() * 4);
+
+        for (word, chunk) in src.iter().zip(dst.chunks_exact_mut(4)) {
+            Self::write_u32(*word, chunk);
+        }
+    }
+
+    /// Reads consecutive u64 values from `src` into `dst`
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if `src` is not exactly 8 times the length of `dst`
+    fn read_u64_into(src: &[u8], dst: &mut [u64]) {
+        assert_eq!(src.len(), dst.len() * 8);
+
+        for (word, chunk) in dst.iter_mut().zip(src.chunks_exact(8)) {
+            *word = Self::read_u64(chunk);
+        }
+    }
+
+    /// Writes consecutive u64 values from `src` into `dst`
+    ///
+    /// # Panics
+    ///
+    /// This code will panic if `dst` is not exactly 8 times the length of `src`
+    fn write_u64_into(src: &[u64], dst: &mut [u8]) {
+        assert_eq!(dst.len(), src.len() * 8);
+
+        for (word, chunk) in src.iter().zip(dst.chunks_exact_mut(8)) {
+            Self::write_u64(*word, chunk);
+        }
+    }
+}
+
+/// Least significant byte first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LittleEndian {}
+
+/// Most significant byte first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BigEndian {}
+
+/// Shorthand for `LittleEndian`
+pub type LE = LittleEndian;
+
+/// Shorthand for `BigEndian`
+pub type BE = BigEndian;
+
+impl Endian for LittleEndian {
+    fn read_u32(bytes: &[u8]) -> u32 {
+        assert_eq!(bytes.len(), 4);
+
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write_u32(value: u32, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn read_u64(bytes: &[u8]) -> u64 {
+        assert_eq!(bytes.len(), 8);
+
+        let mut arr = [0u8; 8];
+        arr.copy_from_slice(bytes);
+        u64::from_le_bytes(arr)
+    }
+
+    fn write_u64(value: u64, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+impl Endian for BigEndian {
+    fn read_u32(bytes: &[u8]) -> u32 {
+        assert_eq!(bytes.len(), 4);
+
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write_u32(value: u32, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn read_u64(bytes: &[u8]) -> u64 {
+        assert_eq!(bytes.len(), 8);
+
+        let mut arr = [0u8; 8];
+        arr.copy_from_slice(bytes);
+        u64::from_be_bytes(arr)
+    }
+
+    fn write_u64(value: u64, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Returns an array of `N` u32 values read from a u8 slice in `E` byte order
+///
+/// # Panics
+///
+/// This code will panic if the slice is not of length `4 * N`
+pub fn read_u32_array<E: Endian, const N: usize>(bytes: &[u8]) -> [u32; N] {
+    let mut output = [0u32; N];
+    E::read_u32_into(bytes, &mut output);
+
+    output
+}
+
+/// Returns an array of `N` u64 values read from a u8 slice in `E` byte order
+///
+/// # Panics
+///
+/// This code will panic if the slice is not of length `8 * N`
+pub fn read_u64_array<E: Endian, const N: usize>(bytes: &[u8]) -> [u64; N] {
+    let mut output = [0u64; N];
+    E::read_u64_into(bytes, &mut output);
+
+    output
+}
+
+/// Return a u32 from a str in little endian format
+///
+/// # Panics
+///
+/// This code will panic if the string is not 4 bytes long
+pub fn string_to_u32_le(string: &str) -> u32 {
+    LittleEndian::read_u32(string.as_bytes())
+}
+
+/// Returns a u32 from a u8 array in little endian format
+///
+/// # Panics
+///
+/// This code will panic if the u8 array is not of length 4
+pub fn u8_array_to_u32_le(arr: &[u8]) -> u32 {
+    LittleEndian::read_u32(arr)
+}
+
+/// Writes a u32 into a u8 array in little endian format
+///
+/// # Panics
+///
+/// This code will panic if the u8 array is not of length 4
+pub fn u32_to_u8_array_le(value: u32, arr: &mut [u8]) {
+    LittleEndian::write_u32(value, arr)
+}
+
+/// XORs `src` into `dst` in place
+///
+/// Works on 16 byte chunks at a time, which the compiler lowers to vector
+/// instructions where the target supports them.
+///
+/// # Panics
+///
+/// This code will panic if `dst` and `src` are not of the same length
+pub fn xor_in_place(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len());
+
+    let mut dst_chunks = dst.chunks_exact_mut(16);
+    let mut src_chunks = src.chunks_exact(16);
+
+    for (dst_chunk, src_chunk) in (&mut dst_chunks).zip(&mut src_chunks) {
+        let mut dst_word = [0u8; 16];
+        let mut src_word = [0u8; 16];
+        dst_word.copy_from_slice(dst_chunk);
+        src_word.copy_from_slice(src_chunk);
+
+        let xored = u128::from_ne_bytes(dst_word) ^ u128::from_ne_bytes(src_word);
+        dst_chunk.copy_from_slice(&xored.to_ne_bytes());
+    }
+
+    for (dst_byte, src_byte) in dst_chunks.into_remainder().iter_mut().zip(src_chunks.remainder()) {
+        *dst_byte ^= *src_byte;
+    }
+}
+
+/// Returns whether `a` and `b` are equal without branching on their content
+///
+/// Slices of different lengths are never equal; the length itself is not secret.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut difference = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        difference |= x ^ y;
+    }
+
+    difference == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_converts_u8_array_to_u32() {
+        let arr = [1u8, 1u8, 1u8, 1u8];
+        assert_eq!(16843009, u8_array_to_u32_le(&arr));
+    }
+
+    #[test]
+    fn it_converts_u32_to_u8_array() {
+        let mut arr = [0u8; 4];
+        u32_to_u8_array_le(0x04030201, &mut arr);
+        assert_eq!([1u8, 2u8, 3u8, 4u8], arr);
+    }
+
+    #[test]
+    fn it_converts_string_to_u32() {
+        let str = "AAAA";
+        assert_eq!(1094795585, string_to_u32_le(str));
+    }
+
+    #[test]
+    fn it_converts_u32_in_both_byte_orders() {
+        let bytes = [1u8, 2u8, 3u8, 4u8];
+        assert_eq!(0x04030201, LittleEndian::read_u32(&bytes));
+        assert_eq!(0x01020304, BigEndian::read_u32(&bytes));
+
+        let mut arr = [0u8; 4];
+        BigEndian::write_u32(0x01020304, &mut arr);
+        assert_eq!(bytes, arr);
+    }
+
+    #[test]
+    fn it_converts_u64_in_both_byte_orders() {
+        let bytes = [1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8];
+        assert_eq!(0x0807060504030201, LE::read_u64(&bytes));
+        assert_eq!(0x0102030405060708, BE::read_u64(&bytes));
+
+        let mut arr = [0u8; 8];
+        LE::write_u64(0x0807060504030201, &mut arr);
+        assert_eq!(bytes, arr);
+    }
+
+    #[test]
+    fn it_converts_slices_and_arrays() {
+        let bytes = [1u8, 0u8, 0u8, 0u8, 2u8, 0u8, 0u8, 0u8];
+        let words: [u32; 2] = read_u32_array::<LE, 2>(&bytes);
+        assert_eq!([1, 2], words);
+
+        let mut output = [0u8; 8];
+        LE::write_u32_into(&words, &mut output);
+        assert_eq!(bytes, output);
+
+        let wide: [u64; 1] = read_u64_array::<BE, 1>(&bytes);
+        let mut output = [0u8; 8];
+        BE::write_u64_into(&wide, &mut output);
+        assert_eq!(bytes, output);
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_rejects_mismatched_slice_lengths() {
+        let mut words = [0u32; 2];
+        LE::read_u32_into(&[0u8; 7], &mut words);
+    }
+
+    #[test]
+    fn it_xors_in_place() {
+        for len in 0..70 {
+            let mut src = [0u8; 70];
+            let mut dst = [0u8; 70];
+            let mut expected = [0u8; 70];
+
+            for i in 0..len {
+                src[i] = (i * 7) as u8;
+                dst[i] = (i * 13 + 1) as u8;
+                expected[i] = src[i] ^ dst[i];
+            }
+
+            xor_in_place(&mut dst[..len], &src[..len]);
+            assert_eq!(expected, dst);
+        }
+    }
+
+    #[test]
+    fn it_compares_in_constant_time() {
+        assert!(constant_time_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2]));
+    }
+
+    #[test]
+    fn it_wipes() {
+        let mut bytes = [0xAAu8; 33];
+        wipe(&mut bytes);
+        assert_eq!([0u8; 33], bytes);
+
+        let mut words = [0xAAAAAAAAu32; 16];
+        wipe(&mut words);
+        assert_eq!([0u32; 16], words);
+    }
+}
@@ -1,231 +1,1748 @@
-use crate::byte_manipulation::u8_array_to_u32_le;
-
-#[derive(Debug)]
-pub struct ChaCha20 {
-    state: [u32; 16],
-}
-
-impl ChaCha20 {
-    /// Returns a new instance of ChaCha20
-    ///
-    /// # Panics
-    ///
-    /// The function will panic if `key` is not of size 32
-    /// The function will panic if `nonce` is not of size 12
-    pub fn new(key: &[u8], nonce: &[u8], counter: u32) -> ChaCha20 {
-        assert_eq!(key.len(), 32);
-        assert_eq!(nonce.len(), 12);
-
-        let mut state = [0u32; 16];
-
-        // Set constant
-        state[0] = 0x61707865;
-        state[1] = 0x3320646e;
-        state[2] = 0x79622d32;
-        state[3] = 0x6b206574;
-
-        // Set key
-        for i in 0..2 {
-            for j in 0..4 {
-                let array_start_offset = (i * 16) + (j * 4);
-                let array_end_offset = array_start_offset + 4;
-
-                state[4 + (i * 4) + j] = u8_array_to_u32_le(&key[array_start_offset..array_end_offset]);
-            }
-        }
-
-        state[12] = counter;
-
-        for i in 0..3 {
-            let array_start_offset = i * 4;
-            let array_end_offset = array_start_offset + 4;
-
-            state[13 + i] = u8_array_to_u32_le(&nonce[array_start_offset..array_end_offset])
-        }
-
-        ChaCha20 { state }
-    }
-
-    /// Returns a length 32 array of `u8` from a `str`.
-    ///
-    /// If the key is smaller than 32 bytes it will append null bytes.
-    /// If the key is over 32 bytes long it will trim it to 32 bytes.
-    pub fn create_key(key: &str) -> [u8; 32] {
-        let input_bytes = key.as_bytes();
-        let mut key = [0u8; 32];
-
-        for i in 0..32 {
-            if input_bytes.len() > i {
-                key[i] = input_bytes[i];
-            }
-        }
-
-        key
-    }
-
-    /// Returns a length 12 array of `u8` from a `str`.
-    ///
-    /// If the key is smaller than 12 bytes it will append null bytes.
-    /// If the key is over 12 bytes long it will trim it to 12 bytes.
-    pub fn create_nonce(nonce: &str) -> [u8; 12] {
-        let input_bytes = nonce.as_bytes();
-        let mut nonce = [0u8; 12];
-
-        for i in 0..12 {
-            if input_bytes.len() > i {
-                nonce[i] = input_bytes[i];
-            }
-        }
-
-        nonce
-    }
-
-    /// Computes and returns the next ChaCha20 state
-    pub fn next(&mut self) -> [u32; 16] {
-        let next_state = self.block();
-
-        // Update counter
-        self.state[12] = self.state[12].wrapping_add(1);
-
-        next_state
-    }
-
-    /// Single ChaCha20 round
-    fn round(state: &mut [u32; 16], vector: (usize, usize, usize, usize)) {
-        let (a, b, c, d) = vector;
-
-        state[a] = state[a].wrapping_add(state[b]);
-        state[d] = ChaCha20::rotate_left(state[d] ^ state[a], 16);
-
-        state[c] = state[c].wrapping_add(state[d]);
-        state[b] = ChaCha20::rotate_left(state[b] ^ state[c], 12);
-
-        state[a] = state[a].wrapping_add(state[b]);
-        state[d] = ChaCha20::rotate_left(state[d] ^ state[a], 8);
-
-        state[c] = state[c].wrapping_add(state[d]);
-        state[b] = ChaCha20::rotate_left(state[b] ^ state[c], 7);
-    }
-
-    /// ChaCha20 block function
-    fn block(&mut self) -> [u32; 16] {
-        let mut working_state = self.state.clone();
-
-        for _ in 0..10 {
-            ChaCha20::round(&mut working_state, (0, 4, 8, 12));  // col 0
-            ChaCha20::round(&mut working_state, (1, 5, 9, 13));  // col 1
-            ChaCha20::round(&mut working_state, (2, 6, 10, 14)); // col 2
-            ChaCha20::round(&mut working_state, (3, 7, 11, 15)); // col 3
-
-            ChaCha20::round(&mut working_state, (0, 5, 10, 15)); // diagonal 0
-            ChaCha20::round(&mut working_state, (1, 6, 11, 12)); // diagonal 1
-            ChaCha20::round(&mut working_state, (2, 7, 8, 13));  // diagonal 2
-            ChaCha20::round(&mut working_state, (3, 4, 9, 14));  // diagonal 3
-        }
-
-        for (i, value) in self.state.iter().enumerate() {
-            working_state[i] = working_state[i].wrapping_add(*value);
-        }
-
-        working_state
-    }
-
-    /// Safe rotate left
-    fn rotate_left(value: u32, shift: u32) -> u32 {
-        (value << shift) | (value >> (32 - shift))
-    }
-}
-
-/// Tests for ChaCha20
-///
-/// For more information about the tests see:
-/// https://datatracker.ietf.org/doc/html/rfc7539
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    fn format_expected(expected: [u8; 64]) -> [u32; 16] {
-        let mut expected_formatted = [0u32; 16];
-
-        for i in 0..16 {
-            let array_start_offset = i * 4;
-            let array_end_offset = array_start_offset + 4;
-
-            expected_formatted[i] = u8_array_to_u32_le(&expected[array_start_offset..array_end_offset]);
-        }
-
-        expected_formatted
-    }
-
-    #[test]
-    fn test_state() {
-        let key = [
-            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
-            0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
-            0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
-            0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
-        ];
-
-        let nonce = [
-            0x00, 0x00, 0x00, 0x09,
-            0x00, 0x00, 0x00, 0x4a,
-            0x00, 0x00, 0x00, 0x00
-        ];
-
-        let expected: [u8; 64] = [
-            0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15,
-            0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71, 0xc4,
-            0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03,
-            0x04, 0x22, 0xaa, 0x9a, 0xc3, 0xd4, 0x6c, 0x4e,
-            0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09,
-            0x14, 0xc2, 0xd7, 0x05, 0xd9, 0x8b, 0x02, 0xa2,
-            0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9,
-            0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50, 0x3c, 0x4e
-        ];
-
-        let mut expected_formatted = format_expected(expected);
-        let mut chacha20 = ChaCha20::new(&key, &nonce, 1);
-
-        assert_eq!(chacha20.next(), expected_formatted);
-    }
-
-    #[test]
-    fn test_multiple_states() {
-        let mut state = ChaCha20::new(&[0; 32], &[0; 12], 0);
-
-        let expected_state: [u8; 64] = [
-            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90,
-            0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86, 0xbd, 0x28,
-            0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a,
-            0xa8, 0x36, 0xef, 0xcc, 0x8b, 0x77, 0x0d, 0xc7,
-            0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d,
-            0x77, 0x24, 0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37,
-            0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
-            0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86
-        ];
-        let expected_state = format_expected(expected_state);
-
-
-        assert_eq!(
-            state.next(),
-            expected_state
-        );
-
-        let expected_state: [u8; 64] = [
-            0x9f, 0x07, 0xe7, 0xbe, 0x55, 0x51, 0x38, 0x7a,
-            0x98, 0xba, 0x97, 0x7c, 0x73, 0x2d, 0x08, 0x0d,
-            0xcb, 0x0f, 0x29, 0xa0, 0x48, 0xe3, 0x65, 0x69,
-            0x12, 0xc6, 0x53, 0x3e, 0x32, 0xee, 0x7a, 0xed,
-            0x29, 0xb7, 0x21, 0x76, 0x9c, 0xe6, 0x4e, 0x43,
-            0xd5, 0x71, 0x33, 0xb0, 0x74, 0xd8, 0x39, 0xd5,
-            0x31, 0xed, 0x1f, 0x28, 0x51, 0x0a, 0xfb, 0x45,
-            0xac, 0xe1, 0x0a, 0x1f, 0x4b, 0x79, 0x4d, 0x6f
-        ];
-        let expected_state = format_expected(expected_state);
-
-        assert_eq!(
-            state.next(),
-            expected_state
-        );
-    }
-}
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+#[cfg(feature = "std")]
+use std::io::IoSliceMut;
+
+use crate::backend::{ChaChaCore, Scalar};
+use crate::byte_manipulation::{u32_to_u8_array_le, u8_array_to_u32_le, wipe, xor_in_place, Endian, LittleEndian};
+use crate::error::Error;
+use crate::mem::copy_to_uninit;
+use crate::nonce::OneTimeNonce;
+
+/// Number of rounds used by the standard ChaCha20 cipher
+const ROUNDS: usize = 20;
+
+/// Replaces the "expand 32-byte k" constant when computing key fingerprints,
+/// so a fingerprint block can never coincide with a keystream block
+const FINGERPRINT_CONTEXT: &[u8; 16] = b"key fingerprint!";
+
+/// Constant of the original specification for 128-bit keys
+const TAU: &[u8; 16] = b"expand 16-byte k";
+
+/// ChaCha stream cipher applying `R` rounds per block
+///
+/// Use the `ChaCha20` alias for encryption; `ChaCha8` and `ChaCha12` share all
+/// the code and only exist for non-cryptographic uses and benchmarks. The
+/// block function comes from the `ChaChaCore` `C`, the portable `Scalar` core
+/// unless another one is selected.
+pub struct ChaCha<const R: usize, C: ChaChaCore = Scalar> {
+    state: [u32; 16],
+    exhausted: bool,
+    /// Keystream block generated ahead of use by `apply_keystream` or `seek`
+    buffer: [u8; 64],
+    /// Bytes of `buffer` already consumed, 64 when it is empty
+    used: usize,
+    core: PhantomData<C>,
+}
+
+/// ChaCha reduced to 8 rounds
+pub type ChaCha8 = ChaCha<8>;
+
+/// ChaCha reduced to 12 rounds
+pub type ChaCha12 = ChaCha<12>;
+
+/// The standard 20-round ChaCha20 cipher
+pub type ChaCha20 = ChaCha<ROUNDS>;
+
+impl<const R: usize, C: ChaChaCore> ChaCha<R, C> {
+    /// Rejects round counts the block function cannot apply when `new` is instantiated
+    const VALID_ROUNDS: () = assert!(R >= 2 && R % 2 == 0, "ChaCha rounds must be even and at least 2");
+
+    /// Returns a new instance of ChaCha that consumes `nonce`
+    ///
+    /// Since `OneTimeNonce` can be neither copied nor cloned, the compiler
+    /// rejects building a second cipher from the same nonce value.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8], nonce: OneTimeNonce, counter: u32) -> ChaCha<R, C> {
+        ChaCha::new_with_raw_nonce(key, nonce.as_bytes(), counter)
+    }
+
+    /// Returns a new instance of ChaCha from raw nonce bytes
+    ///
+    /// Nothing stops the same nonce from being passed twice, which reveals
+    /// the XOR of both messages. Meant for nonces fixed by a protocol or a
+    /// test vector, and for those derived where uniqueness holds by
+    /// construction; everything else should go through `new`.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `nonce` is not of size 12
+    pub fn new_with_raw_nonce(key: &[u8], nonce: &[u8], counter: u32) -> ChaCha<R, C> {
+        let () = Self::VALID_ROUNDS;
+        assert_eq!(key.len(), 32);
+        assert_eq!(nonce.len(), 12);
+
+        let mut state = [0u32; 16];
+
+        // Set constant
+        state[0] = 0x61707865;
+        state[1] = 0x3320646e;
+        state[2] = 0x79622d32;
+        state[3] = 0x6b206574;
+
+        // Set key
+        for i in 0..2 {
+            for j in 0..4 {
+                let array_start_offset = (i * 16) + (j * 4);
+                let array_end_offset = array_start_offset + 4;
+
+                state[4 + (i * 4) + j] = u8_array_to_u32_le(&key[array_start_offset..array_end_offset]);
+            }
+        }
+
+        state[12] = counter;
+
+        for i in 0..3 {
+            let array_start_offset = i * 4;
+            let array_end_offset = array_start_offset + 4;
+
+            state[13 + i] = u8_array_to_u32_le(&nonce[array_start_offset..array_end_offset])
+        }
+
+        ChaCha { state, exhausted: false, buffer: [0u8; 64], used: 64, core: PhantomData }
+    }
+
+    /// Returns a new instance of ChaCha keyed with a 128-bit key
+    ///
+    /// Follows the original specification: the key fills both halves of the
+    /// key words and "expand 16-byte k" replaces the usual constant. Only
+    /// meant for decrypting data from implementations that used short keys.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 16
+    /// The function will panic if `nonce` is not of size 12
+    pub fn with_128_bit_key(key: &[u8], nonce: &[u8], counter: u32) -> ChaCha<R, C> {
+        assert_eq!(key.len(), 16);
+
+        let mut doubled = [0u8; 32];
+        doubled[0..16].copy_from_slice(key);
+        doubled[16..32].copy_from_slice(key);
+
+        let mut chacha = ChaCha::new_with_raw_nonce(&doubled, nonce, counter);
+        LittleEndian::read_u32_into(TAU, &mut chacha.state[0..4]);
+        wipe(&mut doubled);
+
+        chacha
+    }
+
+    /// Returns a new instance of ChaCha running from an arbitrary initial state
+    ///
+    /// Nothing is checked: the constants, key, counter and nonce words are
+    /// taken as given, with word 12 as the block counter. Meant for research
+    /// and for comparing states with other implementations.
+    #[cfg(feature = "hazmat")]
+    pub fn from_raw_state(state: [u32; 16]) -> ChaCha<R, C> {
+        let mut chacha = ChaCha::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+        chacha.state = state;
+
+        chacha
+    }
+
+    /// Returns the current state words, with the counter of the next block in word 12
+    #[cfg(feature = "hazmat")]
+    pub fn raw_state(&self) -> [u32; 16] {
+        self.state
+    }
+
+    /// Returns the working state after each double round of the next block
+    ///
+    /// The block for the current counter is traced without advancing the
+    /// cipher. The last entry is the state before the final addition of the
+    /// input state, so `trace[i] + state` gives the block after `2 * (i + 1)`
+    /// rounds. Meant for studying diffusion round by round.
+    #[cfg(all(feature = "hazmat", feature = "alloc"))]
+    pub fn trace_block(&self) -> Vec<[u32; 16]> {
+        let mut working_state = self.state;
+        let mut trace = Vec::with_capacity(R / 2);
+
+        for _ in 0..(R / 2) {
+            C::permute(&mut working_state, 2);
+            trace.push(working_state);
+        }
+
+        trace
+    }
+
+    /// Returns an independent copy of the cipher at the same byte position
+    ///
+    /// The copy shares the key, nonce, counter and any keystream buffered
+    /// from a partial block, so both instances produce the same keystream from
+    /// here on while advancing separately. Both copies wipe their state on drop.
+    pub fn fork(&self) -> ChaCha<R, C> {
+        ChaCha {
+            state: self.state,
+            exhausted: self.exhausted,
+            buffer: self.buffer,
+            used: self.used,
+            core: PhantomData,
+        }
+    }
+
+    /// Returns the number of rounds applied by the block function, `R`
+    pub fn rounds(&self) -> usize {
+        R
+    }
+
+    /// Returns a length 32 array of `u8` from a `str`.
+    ///
+    /// If the key is smaller than 32 bytes it will append null bytes.
+    /// If the key is over 32 bytes long it will trim it to 32 bytes.
+    pub fn create_key(key: &str) -> [u8; 32] {
+        let input_bytes = key.as_bytes();
+        let mut key = [0u8; 32];
+
+        for i in 0..32 {
+            if input_bytes.len() > i {
+                key[i] = input_bytes[i];
+            }
+        }
+
+        key
+    }
+
+    /// Returns a length 12 array of `u8` from a `str`.
+    ///
+    /// If the key is smaller than 12 bytes it will append null bytes.
+    /// If the key is over 12 bytes long it will trim it to 12 bytes.
+    pub fn create_nonce(nonce: &str) -> [u8; 12] {
+        let input_bytes = nonce.as_bytes();
+        let mut nonce = [0u8; 12];
+
+        for i in 0..12 {
+            if input_bytes.len() > i {
+                nonce[i] = input_bytes[i];
+            }
+        }
+
+        nonce
+    }
+
+    /// Computes and returns the next ChaCha state
+    ///
+    /// Any keystream still buffered by `apply_keystream` is discarded, so the
+    /// stream continues at the start of the returned block.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> [u32; 16] {
+        self.discard_buffer();
+
+        let next_state = self.block();
+        self.advance_counter();
+
+        next_state
+    }
+
+    /// Computes the next ChaCha block and returns it as 64 keystream bytes
+    ///
+    /// This is `next` serialized in little endian order, the form used by all
+    /// the encryption methods.
+    pub fn next_block_bytes(&mut self) -> [u8; 64] {
+        let mut block = [0u8; 64];
+        LittleEndian::write_u32_into(&self.next(), &mut block);
+
+        block
+    }
+
+    /// Returns the number of keystream bytes left before the 32-bit block counter wraps
+    pub fn remaining_keystream(&self) -> u64 {
+        (64 << 32) - self.position()
+    }
+
+    /// Returns the absolute keystream byte position, `counter * 64` plus the
+    /// bytes already used from the current block
+    ///
+    /// Passing the result to `seek` on a cipher with the same key and nonce
+    /// resumes the stream where this one stands.
+    pub fn position(&self) -> u64 {
+        let generated = if self.exhausted { 64 << 32 } else { self.state[12] as u64 * 64 };
+
+        generated - (64 - self.used) as u64
+    }
+
+    /// Moves to byte `offset` of the keystream, counted from block counter 0
+    ///
+    /// The block counter becomes `offset / 64` and the next call to
+    /// `apply_keystream` starts `offset % 64` bytes into that block.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `offset` is not below 256 GiB, the end of the 32-bit counter
+    pub fn seek(&mut self, offset: u64) {
+        assert!(offset < 64 << 32, "offset past the end of the keystream");
+
+        self.discard_buffer();
+        self.state[12] = (offset / 64) as u32;
+        self.exhausted = false;
+
+        let skip = (offset % 64) as usize;
+        if skip > 0 {
+            self.refill();
+            self.used = skip;
+        }
+    }
+
+    /// Returns whether `len` bytes can be processed without the block counter wrapping
+    pub fn can_encrypt(&self, len: u64) -> bool {
+        len <= self.remaining_keystream()
+    }
+
+    /// Encrypts or decrypts `buf` in place
+    ///
+    /// The unused tail of the last block is kept, so successive calls of any
+    /// length consume one contiguous keystream, exactly as a single call over
+    /// the concatenated buffers would.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `buf` is longer than `remaining_keystream`,
+    /// since the counter would wrap and reuse keystream
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+        assert!(self.can_encrypt(buf.len() as u64), "keystream exhausted");
+
+        let mut done = 0;
+
+        while done < buf.len() {
+            if self.used == 64 {
+                self.refill();
+            }
+
+            let take = (buf.len() - done).min(64 - self.used);
+            xor_in_place(&mut buf[done..(done + take)], &self.buffer[self.used..(self.used + take)]);
+
+            self.used += take;
+            done += take;
+        }
+    }
+
+    /// Encrypts or decrypts `buf` in place with the keystream starting at byte
+    /// `offset`, leaving this cipher's position untouched
+    ///
+    /// `offset` is counted from block counter 0 as in `seek`. Since only `&self`
+    /// is needed, one cipher can serve many threads working on different parts
+    /// of a message in any order.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `offset + buf.len()` is past the end of the 256 GiB keystream
+    pub fn apply_keystream_at(&self, offset: u64, buf: &mut [u8]) {
+        let mut chacha = self.fork();
+        chacha.seek(offset);
+        chacha.apply_keystream(buf);
+    }
+
+    /// Encrypts or decrypts `buf` in place, like `apply_keystream`
+    ///
+    /// Returns `Error::KeystreamExhausted` without touching the state if `buf`
+    /// is longer than `remaining_keystream`.
+    pub fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if !self.can_encrypt(buf.len() as u64) {
+            return Err(Error::KeystreamExhausted);
+        }
+
+        self.apply_keystream(buf);
+
+        Ok(())
+    }
+
+    /// Writes the next `out.len()` keystream bytes into `out`
+    ///
+    /// Uses the keystream exactly like `apply_keystream` over a zeroed buffer.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `out` is longer than `remaining_keystream`
+    pub fn fill_keystream(&mut self, out: &mut [u8]) {
+        out.fill(0);
+        self.apply_keystream(out);
+    }
+
+    /// Encrypts or decrypts `bufs` in place as one contiguous message
+    ///
+    /// The keystream carries over buffer boundaries, so the result is the same
+    /// as calling `apply_keystream` on the concatenation of all buffers.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if the buffers together are longer than
+    /// `remaining_keystream`, before any of them is modified
+    #[cfg(feature = "std")]
+    pub fn apply_keystream_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) {
+        let total: u64 = bufs.iter().map(|buf| buf.len() as u64).sum();
+        assert!(self.can_encrypt(total), "keystream exhausted");
+
+        for buf in bufs.iter_mut() {
+            self.apply_keystream(buf);
+        }
+    }
+
+    /// Encrypts or decrypts `input` into `output`
+    ///
+    /// Advances the counter exactly like `apply_keystream` on a buffer of the
+    /// same length. Returns `Error::LengthMismatch` if the two buffers differ in
+    /// length and `Error::KeystreamExhausted` if `input` is longer than
+    /// `remaining_keystream`, in both cases without touching the state.
+    pub fn encrypt_b2b(&mut self, input: &[u8], output: &mut [u8]) -> Result<(), Error> {
+        if input.len() != output.len() {
+            return Err(Error::LengthMismatch);
+        }
+
+        if !self.can_encrypt(input.len() as u64) {
+            return Err(Error::KeystreamExhausted);
+        }
+
+        output.copy_from_slice(input);
+        self.apply_keystream(output);
+
+        Ok(())
+    }
+
+    /// Encrypts or decrypts `input` into an uninitialized `output` and returns
+    /// it as initialized bytes
+    ///
+    /// Lets callers skip zero-filling freshly allocated buffers, for example the
+    /// spare capacity of a `Vec`. Otherwise behaves like `encrypt_b2b`.
+    pub fn encrypt_b2b_uninit<'a>(&mut self, input: &[u8], output: &'a mut [MaybeUninit<u8>]) -> Result<&'a mut [u8], Error> {
+        if input.len() != output.len() {
+            return Err(Error::LengthMismatch);
+        }
+
+        if !self.can_encrypt(input.len() as u64) {
+            return Err(Error::KeystreamExhausted);
+        }
+
+        let output = copy_to_uninit(input, output);
+        self.apply_keystream(output);
+
+        Ok(output)
+    }
+
+    /// XORs one full keystream block into each of `blocks`
+    ///
+    /// Block-aligned buffers, such as storage pages, can be stored as
+    /// `[[u8; 64]]` and skip all partial block handling: each block is XORed with the block function output for the
+    /// next counter, and the counter advances by one per block. Like `next`,
+    /// any keystream still buffered by `apply_keystream` is discarded first, so
+    /// the first block always starts at a block boundary.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `blocks` hold more keystream than is left
+    /// before the 32-bit block counter wraps
+    pub fn process_blocks(&mut self, blocks: &mut [[u8; 64]]) {
+        self.discard_buffer();
+        assert!(self.can_encrypt(blocks.len() as u64 * 64), "keystream exhausted");
+
+        let mut keystream = [0u8; 64];
+        for block in blocks.iter_mut() {
+            LittleEndian::write_u32_into(&self.block(), &mut keystream);
+            self.advance_counter();
+
+            xor_in_place(block, &keystream);
+        }
+        wipe(&mut keystream);
+    }
+
+    /// Generates the next block into the keystream buffer
+    fn refill(&mut self) {
+        self.buffer = self.next_block_bytes();
+        self.used = 0;
+    }
+
+    /// Moves the block counter to the next block, marking the cipher exhausted when it wraps
+    fn advance_counter(&mut self) {
+        self.state[12] = self.state[12].wrapping_add(1);
+        if self.state[12] == 0 {
+            self.exhausted = true;
+        }
+    }
+
+    /// Wipes any buffered keystream and marks the buffer empty
+    fn discard_buffer(&mut self) {
+        wipe(&mut self.buffer);
+        self.used = 64;
+    }
+
+    /// ChaCha block function
+    fn block(&mut self) -> [u32; 16] {
+        C::block(&self.state, R)
+    }
+}
+
+impl ChaCha20 {
+    /// Returns a short identifier for `key`
+    ///
+    /// The fingerprint is the first 8 bytes of a ChaCha20 block computed over the
+    /// key with a dedicated constant in place of "expand 32-byte k" and a zero
+    /// counter and nonce. It identifies which key a message needs without
+    /// revealing anything usable about the key or its keystream.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn fingerprint(key: &[u8]) -> [u8; 8] {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(key, &[0u8; 12], 0);
+        LittleEndian::read_u32_into(FINGERPRINT_CONTEXT, &mut chacha20.state[0..4]);
+
+        let block = chacha20.block();
+
+        let mut fingerprint = [0u8; 8];
+        LittleEndian::write_u32_into(&block[0..2], &mut fingerprint);
+
+        fingerprint
+    }
+
+    /// HChaCha20 function
+    ///
+    /// Runs the ChaCha20 rounds over the key and a 16 byte input without the
+    /// final addition and returns words 0..4 and 12..16 as 32 bytes.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `input` is not of size 16
+    pub(crate) fn hchacha20(key: &[u8], input: &[u8]) -> [u8; 32] {
+        assert_eq!(input.len(), 16);
+
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(key, &input[4..16], u8_array_to_u32_le(&input[0..4]));
+        Scalar::permute(&mut chacha20.state, ROUNDS);
+
+        let mut output = [0u8; 32];
+        for i in 0..4 {
+            u32_to_u8_array_le(chacha20.state[i], &mut output[(i * 4)..(i * 4 + 4)]);
+            u32_to_u8_array_le(chacha20.state[12 + i], &mut output[(16 + i * 4)..(16 + i * 4 + 4)]);
+        }
+
+        output
+    }
+}
+
+impl<const R: usize, C: ChaChaCore> fmt::Debug for ChaCha<R, C> {
+    /// Shows the key fingerprint and the block of the next keystream byte, never the state
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut key = [0u8; 32];
+        LittleEndian::write_u32_into(&self.state[4..12], &mut key);
+        let fingerprint = ChaCha20::fingerprint(&key);
+        wipe(&mut key);
+
+        f.debug_struct("ChaCha").field("fingerprint", &fingerprint).field("counter", &(self.position() / 64)).finish()
+    }
+}
+
+impl<const R: usize, C: ChaChaCore> Drop for ChaCha<R, C> {
+    /// Wipes the key, counter and nonce from memory
+    fn drop(&mut self) {
+        wipe(&mut self.state);
+        wipe(&mut self.buffer);
+    }
+}
+
+/// ChaCha with the number of double rounds chosen at runtime
+///
+/// Meant for research and benchmarks comparing the security margin of round
+/// counts, and kept apart from `ChaCha` so production code only ever runs the
+/// round count fixed by its type. A double round is one column round followed
+/// by one diagonal round, so ChaCha20 applies 10 of them and `double_rounds`
+/// of `R / 2` gives the keystream of `ChaCha<R>`. The block counter wraps
+/// after block `u32::MAX` without any check.
+#[cfg(feature = "hazmat")]
+pub struct ChaChaVar<C: ChaChaCore = Scalar> {
+    state: [u32; 16],
+    double_rounds: usize,
+    /// Keystream block generated ahead of use by `apply_keystream`
+    buffer: [u8; 64],
+    /// Bytes of `buffer` already consumed, 64 when it is empty
+    used: usize,
+    core: PhantomData<C>,
+}
+
+#[cfg(feature = "hazmat")]
+impl<C: ChaChaCore> ChaChaVar<C> {
+    /// Returns a new instance of ChaChaVar applying `double_rounds` double rounds per block
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `nonce` is not of size 12
+    /// The function will panic if `double_rounds` is zero
+    pub fn new(key: &[u8], nonce: &[u8], counter: u32, double_rounds: usize) -> ChaChaVar<C> {
+        assert!(double_rounds >= 1, "ChaCha needs at least one double round");
+
+        let state = ChaCha20::new_with_raw_nonce(key, nonce, counter).state;
+
+        ChaChaVar { state, double_rounds, buffer: [0u8; 64], used: 64, core: PhantomData }
+    }
+
+    /// Returns the number of double rounds applied by the block function
+    pub fn double_rounds(&self) -> usize {
+        self.double_rounds
+    }
+
+    /// Computes and returns the next state, discarding any buffered keystream like `ChaCha::next`
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> [u32; 16] {
+        wipe(&mut self.buffer);
+        self.used = 64;
+
+        let next_state = C::block(&self.state, self.double_rounds * 2);
+        self.state[12] = self.state[12].wrapping_add(1);
+
+        next_state
+    }
+
+    /// Computes the next block and returns it as 64 keystream bytes
+    pub fn next_block_bytes(&mut self) -> [u8; 64] {
+        let mut block = [0u8; 64];
+        LittleEndian::write_u32_into(&self.next(), &mut block);
+
+        block
+    }
+
+    /// Encrypts or decrypts `buf` in place, continuing the keystream across calls like `ChaCha::apply_keystream`
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+        let mut done = 0;
+
+        while done < buf.len() {
+            if self.used == 64 {
+                self.buffer = self.next_block_bytes();
+                self.used = 0;
+            }
+
+            let take = (buf.len() - done).min(64 - self.used);
+            xor_in_place(&mut buf[done..(done + take)], &self.buffer[self.used..(self.used + take)]);
+
+            self.used += take;
+            done += take;
+        }
+    }
+}
+
+#[cfg(feature = "hazmat")]
+impl<C: ChaChaCore> fmt::Debug for ChaChaVar<C> {
+    /// Shows the key fingerprint, the block of the next keystream byte and the round count, never the state
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut key = [0u8; 32];
+        LittleEndian::write_u32_into(&self.state[4..12], &mut key);
+        let fingerprint = ChaCha20::fingerprint(&key);
+        wipe(&mut key);
+
+        let counter = self.state[12].wrapping_sub((self.used < 64) as u32);
+        f.debug_struct("ChaChaVar")
+            .field("fingerprint", &fingerprint)
+            .field("counter", &counter)
+            .field("double_rounds", &self.double_rounds)
+            .finish()
+    }
+}
+
+#[cfg(feature = "hazmat")]
+impl<C: ChaChaCore> Drop for ChaChaVar<C> {
+    /// Wipes the key, counter and nonce from memory
+    fn drop(&mut self) {
+        wipe(&mut self.state);
+        wipe(&mut self.buffer);
+    }
+}
+
+/// Iterator over the keystream bytes of a `ChaCha` instance
+///
+/// Starts at the cipher's current position and yields bytes in the same
+/// order `apply_keystream` uses them. There is no fixed length; iteration only
+/// ends when the 32-bit block counter runs out, since continuing past it would
+/// repeat the keystream from block 0.
+#[derive(Debug)]
+pub struct Keystream<const R: usize = ROUNDS, C: ChaChaCore = Scalar> {
+    chacha: ChaCha<R, C>,
+}
+
+impl<const R: usize, C: ChaChaCore> Keystream<R, C> {
+    /// Returns a new instance of Keystream starting at the position of `chacha`
+    pub fn new(chacha: ChaCha<R, C>) -> Keystream<R, C> {
+        Keystream { chacha }
+    }
+
+    /// Returns the underlying cipher, positioned after the last yielded byte
+    pub fn into_inner(self) -> ChaCha<R, C> {
+        self.chacha
+    }
+}
+
+impl<const R: usize, C: ChaChaCore> Iterator for Keystream<R, C> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.chacha.remaining_keystream() == 0 {
+            return None;
+        }
+
+        let mut byte = [0u8];
+        self.chacha.apply_keystream(&mut byte);
+
+        Some(byte[0])
+    }
+}
+
+/// Iterator over whole 64-byte keystream blocks of a `ChaCha` instance
+///
+/// Each item is `next_block_bytes`, so the counter advances by one per block
+/// and keystream buffered by `apply_keystream` is discarded.
+#[derive(Debug)]
+pub struct Blocks<const R: usize = ROUNDS, C: ChaChaCore = Scalar> {
+    chacha: ChaCha<R, C>,
+    checked: bool,
+}
+
+impl<const R: usize, C: ChaChaCore> Blocks<R, C> {
+    /// Returns a new instance of Blocks that never ends
+    ///
+    /// Like `next`, the counter wraps after block `u32::MAX` and the keystream
+    /// starts over from block 0.
+    pub fn new(chacha: ChaCha<R, C>) -> Blocks<R, C> {
+        Blocks { chacha, checked: false }
+    }
+
+    /// Returns a new instance of Blocks that ends after block `u32::MAX`
+    /// instead of wrapping the counter
+    pub fn checked(chacha: ChaCha<R, C>) -> Blocks<R, C> {
+        Blocks { chacha, checked: true }
+    }
+
+    /// Returns the underlying cipher, positioned after the last yielded block
+    pub fn into_inner(self) -> ChaCha<R, C> {
+        self.chacha
+    }
+}
+
+impl<const R: usize, C: ChaChaCore> Iterator for Blocks<R, C> {
+    type Item = [u8; 64];
+
+    fn next(&mut self) -> Option<[u8; 64]> {
+        if self.checked && self.chacha.exhausted {
+            return None;
+        }
+
+        Some(self.chacha.next_block_bytes())
+    }
+}
+
+/// Adds `encrypted_with` to every iterator over bytes
+pub trait EncryptedWith: Iterator<Item = u8> + Sized {
+    /// Returns an iterator that lazily encrypts or decrypts each byte with `cipher`
+    ///
+    /// Bytes consume the cipher's keystream one at a time, in the same order
+    /// as `apply_keystream`, so the cipher can be used for more data afterwards.
+    fn encrypted_with<const R: usize, C: ChaChaCore>(self, cipher: &mut ChaCha<R, C>) -> Encrypted<'_, Self, R, C>;
+}
+
+impl<I: Iterator<Item = u8>> EncryptedWith for I {
+    fn encrypted_with<const R: usize, C: ChaChaCore>(self, cipher: &mut ChaCha<R, C>) -> Encrypted<'_, I, R, C> {
+        Encrypted { inner: self, cipher }
+    }
+}
+
+/// Iterator returned by `EncryptedWith::encrypted_with`
+///
+/// # Panics
+///
+/// Iterating panics if the cipher runs out of keystream, like `apply_keystream`
+#[derive(Debug)]
+pub struct Encrypted<'a, I, const R: usize, C: ChaChaCore = Scalar> {
+    inner: I,
+    cipher: &'a mut ChaCha<R, C>,
+}
+
+impl<I: Iterator<Item = u8>, const R: usize, C: ChaChaCore> Iterator for Encrypted<'_, I, R, C> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let mut byte = [self.inner.next()?];
+        self.cipher.apply_keystream(&mut byte);
+
+        Some(byte[0])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// HChaCha20 over a 32-byte key and a 16-byte input
+///
+/// Returns words 0..4 and 12..16 of the ChaCha20 permutation without the final
+/// addition, as specified by draft-irtf-cfrg-xchacha. The output is a uniform
+/// subkey, which makes this a cheap way to derive keys from a master key and
+/// a unique 16-byte input.
+pub fn hchacha20(key: &[u8; 32], input: &[u8; 16]) -> [u8; 32] {
+    ChaCha20::hchacha20(key, input)
+}
+
+/// Encrypts or decrypts `data` and returns the result
+///
+/// The keystream starts at block `counter`. Since ChaCha20 is a stream cipher
+/// the same call decrypts a ciphertext produced with the same parameters.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+/// The function will panic if `nonce` is not of size 12
+/// The function will panic if `data` needs blocks past the end of the 32-bit counter
+#[cfg(feature = "alloc")]
+pub fn encrypt(key: &[u8], nonce: &[u8], counter: u32, data: &[u8]) -> Vec<u8> {
+    let mut output = data.to_vec();
+    ChaCha20::new_with_raw_nonce(key, nonce, counter).apply_keystream(&mut output);
+
+    output
+}
+
+/// Decrypts `data` and returns the plaintext
+///
+/// This is the same operation as `encrypt`, provided so call sites read naturally.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+/// The function will panic if `nonce` is not of size 12
+/// The function will panic if `data` needs blocks past the end of the 32-bit counter
+#[cfg(feature = "alloc")]
+pub fn decrypt(key: &[u8], nonce: &[u8], counter: u32, data: &[u8]) -> Vec<u8> {
+    encrypt(key, nonce, counter, data)
+}
+
+/// Tests for ChaCha20
+///
+/// For more information about the tests see:
+/// https://datatracker.ietf.org/doc/html/rfc7539
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format_expected(expected: [u8; 64]) -> [u32; 16] {
+        let mut expected_formatted = [0u32; 16];
+
+        for (i, word) in expected_formatted.iter_mut().enumerate() {
+            let array_start_offset = i * 4;
+            let array_end_offset = array_start_offset + 4;
+
+            *word = u8_array_to_u32_le(&expected[array_start_offset..array_end_offset]);
+        }
+
+        expected_formatted
+    }
+
+    #[test]
+    fn test_state() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+            0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+            0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+            0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+        ];
+
+        let nonce = [
+            0x00, 0x00, 0x00, 0x09,
+            0x00, 0x00, 0x00, 0x4a,
+            0x00, 0x00, 0x00, 0x00
+        ];
+
+        let expected: [u8; 64] = [
+            0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15,
+            0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71, 0xc4,
+            0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03,
+            0x04, 0x22, 0xaa, 0x9a, 0xc3, 0xd4, 0x6c, 0x4e,
+            0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09,
+            0x14, 0xc2, 0xd7, 0x05, 0xd9, 0x8b, 0x02, 0xa2,
+            0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9,
+            0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50, 0x3c, 0x4e
+        ];
+
+        let expected_formatted = format_expected(expected);
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 1);
+
+        assert_eq!(chacha20.next(), expected_formatted);
+    }
+
+    #[test]
+    #[cfg(feature = "hazmat")]
+    fn test_chacha_var() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut ten: ChaChaVar = ChaChaVar::new(&key, &nonce, 3, 10);
+        let mut four: ChaChaVar = ChaChaVar::new(&key, &nonce, 3, 4);
+        let mut six: ChaChaVar = ChaChaVar::new(&key, &nonce, 3, 6);
+
+        assert_eq!(four.double_rounds(), 4);
+        assert_eq!(ten.next(), ChaCha20::new_with_raw_nonce(&key, &nonce, 3).next());
+        assert_eq!(four.next(), ChaCha8::new_with_raw_nonce(&key, &nonce, 3).next());
+        assert_eq!(six.next_block_bytes(), ChaCha12::new_with_raw_nonce(&key, &nonce, 3).next_block_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "hazmat")]
+    fn test_chacha_var_apply_keystream() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut expected = [0x33u8; 150];
+        ChaCha12::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut expected);
+
+        let mut buffer = [0x33u8; 150];
+        let mut chacha: ChaChaVar = ChaChaVar::new(&key, &nonce, 0, 6);
+        chacha.apply_keystream(&mut buffer[..10]);
+        chacha.apply_keystream(&mut buffer[10..100]);
+        chacha.apply_keystream(&mut buffer[100..]);
+
+        assert_eq!(&buffer[..], &expected[..]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "hazmat", feature = "std"))]
+    fn test_chacha_var_debug() {
+        let mut chacha: ChaChaVar = ChaChaVar::new(&[0xABu8; 32], &[0u8; 12], 1, 4);
+        chacha.apply_keystream(&mut [0u8; 70]);
+
+        let expected = format!("ChaChaVar {{ fingerprint: {:?}, counter: 2, double_rounds: 4 }}", ChaCha20::fingerprint(&[0xABu8; 32]));
+        assert_eq!(format!("{:?}", chacha), expected);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(feature = "hazmat")]
+    fn test_chacha_var_without_rounds() {
+        let _: ChaChaVar = ChaChaVar::new(&[0u8; 32], &[0u8; 12], 0, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "hazmat")]
+    fn test_raw_state() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0x11u8; 32], &[0x22u8; 12], 7);
+        let state = chacha20.raw_state();
+        assert_eq!(state[12], 7);
+        assert_eq!(state[13], 0x22222222);
+
+        let mut imported = ChaCha20::from_raw_state(state);
+        assert_eq!(imported.next(), chacha20.next());
+        assert_eq!(imported.raw_state(), chacha20.raw_state());
+
+        let mut rotated = state;
+        for word in rotated.iter_mut() {
+            *word = word.rotate_left(1);
+        }
+        assert_eq!(ChaCha8::from_raw_state(rotated).raw_state(), rotated);
+    }
+
+    #[test]
+    fn test_128_bit_key() {
+        let expected: [u8; 64] = [
+            0x89, 0x67, 0x09, 0x52, 0x60, 0x83, 0x64, 0xfd,
+            0x00, 0xb2, 0xf9, 0x09, 0x36, 0xf0, 0x31, 0xc8,
+            0xe7, 0x56, 0xe1, 0x5d, 0xba, 0x04, 0xb8, 0x49,
+            0x3d, 0x00, 0x42, 0x92, 0x59, 0xb2, 0x0f, 0x46,
+            0xcc, 0x04, 0xf1, 0x11, 0x24, 0x6b, 0x6c, 0x2c,
+            0xe0, 0x66, 0xbe, 0x3b, 0xfb, 0x32, 0xd9, 0xaa,
+            0x0f, 0xdd, 0xfb, 0xc1, 0x21, 0x23, 0xd4, 0xb9,
+            0xe4, 0x4f, 0x34, 0xdc, 0xa0, 0x5a, 0x10, 0x3f
+        ];
+
+        let mut chacha20 = ChaCha20::with_128_bit_key(&[0u8; 16], &[0u8; 12], 0);
+        assert_eq!(&chacha20.next_block_bytes()[..], &expected[..]);
+
+        let mut full = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+        assert_ne!(&full.next_block_bytes()[..], &expected[..]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "hazmat", feature = "alloc"))]
+    fn test_trace_block() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+        let state = chacha20.raw_state();
+        let trace = chacha20.trace_block();
+
+        assert_eq!(trace.len(), 10);
+        assert_eq!(ChaCha8::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0).trace_block()[..], trace[..4]);
+        assert_eq!(chacha20.position(), 0);
+
+        let mut block = trace[9];
+        for (word, input) in block.iter_mut().zip(state.iter()) {
+            *word = word.wrapping_add(*input);
+        }
+        assert_eq!(block, chacha20.next());
+    }
+
+    #[test]
+    fn test_reduced_round_variants() {
+        let key = [0u8; 32];
+        let nonce = [0u8; 12];
+
+        let eight: [u8; 64] = [
+            0x3e, 0x00, 0xef, 0x2f, 0x89, 0x5f, 0x40, 0xd6,
+            0x7f, 0x5b, 0xb8, 0xe8, 0x1f, 0x09, 0xa5, 0xa1,
+            0x2c, 0x84, 0x0e, 0xc3, 0xce, 0x9a, 0x7f, 0x3b,
+            0x18, 0x1b, 0xe1, 0x88, 0xef, 0x71, 0x1a, 0x1e,
+            0x98, 0x4c, 0xe1, 0x72, 0xb9, 0x21, 0x6f, 0x41,
+            0x9f, 0x44, 0x53, 0x67, 0x45, 0x6d, 0x56, 0x19,
+            0x31, 0x4a, 0x42, 0xa3, 0xda, 0x86, 0xb0, 0x01,
+            0x38, 0x7b, 0xfd, 0xb8, 0x0e, 0x0c, 0xfe, 0x42
+        ];
+        let twelve: [u8; 64] = [
+            0x9b, 0xf4, 0x9a, 0x6a, 0x07, 0x55, 0xf9, 0x53,
+            0x81, 0x1f, 0xce, 0x12, 0x5f, 0x26, 0x83, 0xd5,
+            0x04, 0x29, 0xc3, 0xbb, 0x49, 0xe0, 0x74, 0x14,
+            0x7e, 0x00, 0x89, 0xa5, 0x2e, 0xae, 0x15, 0x5f,
+            0x05, 0x64, 0xf8, 0x79, 0xd2, 0x7a, 0xe3, 0xc0,
+            0x2c, 0xe8, 0x28, 0x34, 0xac, 0xfa, 0x8c, 0x79,
+            0x3a, 0x62, 0x9f, 0x2c, 0xa0, 0xde, 0x69, 0x19,
+            0x61, 0x0b, 0xe8, 0x2f, 0x41, 0x13, 0x26, 0xbe
+        ];
+
+        assert_eq!(&ChaCha8::new_with_raw_nonce(&key, &nonce, 0).next_block_bytes()[..], &eight[..]);
+        assert_eq!(&ChaCha12::new_with_raw_nonce(&key, &nonce, 0).next_block_bytes()[..], &twelve[..]);
+        assert_eq!(ChaCha8::new_with_raw_nonce(&key, &nonce, 0).rounds(), 8);
+    }
+
+    #[test]
+    fn test_counter_wraps_at_32_bits() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut last = ChaCha20::new_with_raw_nonce(&key, &nonce, u32::MAX);
+        let mut first = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+
+        assert_ne!(last.next(), first.next());
+        assert_eq!(last.state[12], 0);
+        assert_eq!(last.next(), ChaCha20::new_with_raw_nonce(&key, &nonce, 0).next());
+    }
+
+    #[test]
+    fn test_next_block_bytes() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+
+        let expected: [u8; 64] = [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90,
+            0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86, 0xbd, 0x28,
+            0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a,
+            0xa8, 0x36, 0xef, 0xcc, 0x8b, 0x77, 0x0d, 0xc7,
+            0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d,
+            0x77, 0x24, 0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37,
+            0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
+            0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86
+        ];
+
+        assert_eq!(&chacha20.next_block_bytes()[..], &expected[..]);
+        assert_eq!(chacha20.state[12], 1);
+    }
+
+    #[test]
+    fn test_apply_keystream() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut buf = [0x33u8; 150];
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 7);
+        chacha20.apply_keystream(&mut buf);
+
+        assert_eq!(chacha20.state[12], 10);
+
+        let mut expected = ChaCha20::new_with_raw_nonce(&key, &nonce, 7);
+        for chunk in buf.chunks(64) {
+            let keystream = expected.next_block_bytes();
+
+            for (byte, key_byte) in chunk.iter().zip(keystream.iter()) {
+                assert_eq!(*byte, 0x33 ^ key_byte);
+            }
+        }
+
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 7).apply_keystream(&mut buf);
+        assert_eq!(buf, [0x33u8; 150]);
+    }
+
+    #[test]
+    fn test_apply_keystream_continues_within_a_block() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut whole = [0x33u8; 200];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 1).apply_keystream(&mut whole);
+
+        let mut pieces = [0x33u8; 200];
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 1);
+        for range in &[0..10, 10..20, 20..20, 20..64, 64..65, 65..200] {
+            chacha20.apply_keystream(&mut pieces[range.clone()]);
+        }
+
+        assert_eq!(&pieces[..], &whole[..]);
+        assert_eq!(chacha20.position(), 64 + 200);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_apply_keystream_vectored() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut whole = [0x33u8; 150];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut whole);
+
+        let mut header = [0x33u8; 7];
+        let mut empty = [0u8; 0];
+        let mut body = [0x33u8; 100];
+        let mut trailer = [0x33u8; 43];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream_vectored(&mut [
+            IoSliceMut::new(&mut header),
+            IoSliceMut::new(&mut empty),
+            IoSliceMut::new(&mut body),
+            IoSliceMut::new(&mut trailer),
+        ]);
+
+        assert_eq!(&header[..], &whole[0..7]);
+        assert_eq!(&body[..], &whole[7..107]);
+        assert_eq!(&trailer[..], &whole[107..150]);
+    }
+
+    #[test]
+    fn test_encrypt_b2b_uninit() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+        let input = [0x33u8; 100];
+
+        let mut expected = input;
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut expected);
+
+        let mut output = [MaybeUninit::<u8>::uninit(); 100];
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        assert_eq!(chacha20.encrypt_b2b_uninit(&input[..30], &mut output[..30]).unwrap(), &expected[..30]);
+        assert_eq!(chacha20.encrypt_b2b_uninit(&input[30..], &mut output[30..]).unwrap(), &expected[30..]);
+
+        assert_eq!(chacha20.encrypt_b2b_uninit(&input, &mut output[..99]), Err(Error::LengthMismatch));
+        assert_eq!(chacha20.position(), 100);
+    }
+
+    #[test]
+    fn test_apply_keystream_at() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut sequential = [0x33u8; 300];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut sequential);
+
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        chacha20.apply_keystream(&mut [0u8; 5]);
+
+        let mut random_access = [0x33u8; 300];
+        for &(start, end) in &[(200, 300), (0, 10), (130, 200), (10, 130)] {
+            chacha20.apply_keystream_at(start as u64, &mut random_access[start..end]);
+        }
+
+        assert_eq!(&random_access[..], &sequential[..]);
+        assert_eq!(chacha20.position(), 5);
+    }
+
+    #[test]
+    fn test_keystream_limit() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX);
+        let mut buf = [0u8; 65];
+
+        assert_eq!(chacha20.try_apply_keystream(&mut buf), Err(Error::KeystreamExhausted));
+        assert_eq!(chacha20.encrypt_b2b(&[0u8; 65], &mut buf), Err(Error::KeystreamExhausted));
+        assert_eq!(chacha20.position(), (64 << 32) - 64);
+        assert_eq!(buf, [0u8; 65]);
+
+        assert_eq!(chacha20.try_apply_keystream(&mut buf[..63]), Ok(()));
+        assert_eq!(chacha20.try_apply_keystream(&mut buf[63..64]), Ok(()));
+        assert_eq!(chacha20.remaining_keystream(), 0);
+        assert_eq!(chacha20.try_apply_keystream(&mut buf[64..]), Err(Error::KeystreamExhausted));
+        assert_eq!(chacha20.try_apply_keystream(&mut []), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "keystream exhausted")]
+    fn test_apply_keystream_past_the_limit() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX);
+        chacha20.apply_keystream(&mut [0u8; 65]);
+    }
+
+    #[test]
+    fn test_fill_keystream() {
+        let mut expected = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+
+        let mut keystream = [0xffu8; 130];
+        chacha20.fill_keystream(&mut keystream[..3]);
+        chacha20.fill_keystream(&mut keystream[3..]);
+
+        assert_eq!(&keystream[0..64], &expected.next_block_bytes()[..]);
+        assert_eq!(&keystream[64..128], &expected.next_block_bytes()[..]);
+        assert_eq!(&keystream[128..130], &expected.next_block_bytes()[0..2]);
+        assert_eq!(&keystream[0..4], &[0x76, 0xb8, 0xe0, 0xad]);
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut chacha20 = ChaCha12::new_with_raw_nonce(&[0x11u8; 32], &[0x22u8; 12], 0);
+        chacha20.apply_keystream(&mut [0u8; 10]);
+
+        let mut fork = chacha20.fork();
+        assert_eq!(fork.position(), 10);
+        assert_eq!(fork.rounds(), 12);
+
+        let mut original = [0u8; 100];
+        chacha20.apply_keystream(&mut original);
+
+        let mut forked = [0u8; 100];
+        fork.apply_keystream(&mut forked[..50]);
+        fork.apply_keystream(&mut forked[50..]);
+        assert_eq!(&forked[..], &original[..]);
+
+        chacha20.apply_keystream(&mut [0u8; 5]);
+        assert_eq!(chacha20.position(), 115);
+        assert_eq!(fork.position(), 110);
+    }
+
+    #[test]
+    fn test_encrypt_b2b() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+        let input = [0x33u8; 100];
+
+        let mut output = [0u8; 100];
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        assert_eq!(chacha20.encrypt_b2b(&input, &mut output), Ok(()));
+
+        let mut expected = input;
+        let mut reference = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        reference.apply_keystream(&mut expected);
+
+        assert_eq!(&output[..], &expected[..]);
+        assert_eq!(chacha20.state[12], reference.state[12]);
+    }
+
+    #[test]
+    fn test_keystream_iterator() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut expected = [0u8; 100];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut expected);
+
+        let mut keystream = Keystream::new(ChaCha20::new_with_raw_nonce(&key, &nonce, 0));
+        let mut ciphertext = [0x33u8; 90];
+        for (byte, key_byte) in ciphertext.iter_mut().zip(&mut keystream) {
+            *byte ^= key_byte;
+        }
+
+        for (byte, key_byte) in ciphertext.iter().zip(expected.iter()) {
+            assert_eq!(*byte, 0x33 ^ key_byte);
+        }
+
+        assert_eq!(keystream.next(), Some(expected[90]));
+        assert_eq!(keystream.into_inner().position(), 91);
+    }
+
+    #[test]
+    fn test_keystream_iterator_stops_before_wrapping() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+        chacha20.seek((64 << 32) - 2);
+
+        assert_eq!(Keystream::new(chacha20).count(), 2);
+    }
+
+    #[test]
+    fn test_blocks_iterator() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut expected = ChaCha20::new_with_raw_nonce(&key, &nonce, 4);
+        let mut blocks = Blocks::new(ChaCha20::new_with_raw_nonce(&key, &nonce, 4));
+
+        for block in blocks.by_ref().take(3) {
+            assert_eq!(&block[..], &expected.next_block_bytes()[..]);
+        }
+
+        assert_eq!(blocks.into_inner().state[12], 7);
+    }
+
+    #[test]
+    fn test_blocks_iterator_wrapping() {
+        let mut blocks = Blocks::new(ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX - 1));
+        let first = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0).next_block_bytes();
+
+        assert_eq!(&blocks.nth(2).unwrap()[..], &first[..]);
+        assert_eq!(Blocks::checked(ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX - 1)).count(), 2);
+    }
+
+    #[test]
+    fn test_encrypted_with() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut expected = [0x33u8; 100];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut expected);
+
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        let mut ciphertext = [0u8; 100];
+        for (out, byte) in ciphertext[..70].iter_mut().zip(core::iter::repeat(0x33u8).take(70).encrypted_with(&mut chacha20)) {
+            *out = byte;
+        }
+        for (out, byte) in ciphertext[70..].iter_mut().zip([0x33u8; 30].iter().copied().encrypted_with(&mut chacha20)) {
+            *out = byte;
+        }
+
+        assert_eq!(&ciphertext[..], &expected[..]);
+        assert_eq!(chacha20.position(), 100);
+
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        assert!(expected.iter().copied().encrypted_with(&mut chacha20).all(|byte| byte == 0x33));
+    }
+
+    #[test]
+    fn test_encrypt_b2b_length_mismatch() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+        let mut output = [0u8; 10];
+
+        assert_eq!(chacha20.encrypt_b2b(&[0u8; 11], &mut output), Err(Error::LengthMismatch));
+        assert_eq!(chacha20.state[12], 0);
+    }
+
+    #[test]
+    fn test_remaining_keystream() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0);
+        assert_eq!(chacha20.remaining_keystream(), 256 * 1024 * 1024 * 1024);
+
+        chacha20.next();
+        assert_eq!(chacha20.remaining_keystream(), 256 * 1024 * 1024 * 1024 - 64);
+
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX);
+        assert_eq!(chacha20.remaining_keystream(), 64);
+        assert!(chacha20.can_encrypt(64));
+        assert!(!chacha20.can_encrypt(65));
+
+        chacha20.next();
+        assert_eq!(chacha20.remaining_keystream(), 0);
+        assert!(chacha20.can_encrypt(0));
+        assert!(!chacha20.can_encrypt(1));
+    }
+
+    #[test]
+    fn test_seek() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut sequential = [0x33u8; 300];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut sequential);
+
+        for &(start, end) in &[(0, 300), (64, 128), (10, 20), (63, 65), (100, 300), (299, 300)] {
+            let mut part = [0x33u8; 300];
+            let mut chacha20 = ChaCha20::new_with_raw_nonce(&key, &nonce, 7);
+            chacha20.seek(start as u64);
+            chacha20.apply_keystream(&mut part[start..end]);
+
+            assert_eq!(&part[start..end], &sequential[start..end]);
+            assert_eq!(chacha20.state[12] as usize, (end + 63) / 64);
+        }
+    }
+
+    #[test]
+    fn test_position() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 3);
+        assert_eq!(chacha20.position(), 192);
+
+        chacha20.seek(100);
+        assert_eq!(chacha20.position(), 100);
+
+        chacha20.apply_keystream(&mut [0u8; 10]);
+        assert_eq!(chacha20.position(), 110);
+
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX);
+        chacha20.next();
+        assert_eq!(chacha20.position(), 64 << 32);
+    }
+
+    #[test]
+    fn test_seek_to_the_last_byte() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX);
+        chacha20.next();
+        assert_eq!(chacha20.remaining_keystream(), 0);
+
+        chacha20.seek((64 << 32) - 1);
+        assert_eq!(chacha20.remaining_keystream(), 1);
+        assert_eq!(chacha20.position(), (64 << 32) - 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_seek_past_the_end() {
+        ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0).seek(64 << 32);
+    }
+
+    #[test]
+    fn test_process_blocks() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut blocks = [[0x33u8; 64]; 3];
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 5).process_blocks(&mut blocks);
+
+        let mut expected = ChaCha20::new_with_raw_nonce(&key, &nonce, 5);
+        for block in blocks.iter() {
+            let keystream = expected.next_block_bytes();
+
+            for (byte, key_byte) in block.iter().zip(keystream.iter()) {
+                assert_eq!(*byte, 0x33 ^ key_byte);
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_blocks_matches_apply_keystream() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut blocks = [[0u8; 64]; 4];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            block.fill(i as u8);
+        }
+        let mut expected = [0u8; 256];
+        for (chunk, block) in expected.chunks_mut(64).zip(blocks.iter()) {
+            chunk.copy_from_slice(block);
+        }
+
+        let mut chacha = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        chacha.process_blocks(&mut blocks);
+        ChaCha20::new_with_raw_nonce(&key, &nonce, 0).apply_keystream(&mut expected);
+
+        for (chunk, block) in expected.chunks(64).zip(blocks.iter()) {
+            assert_eq!(chunk, &block[..]);
+        }
+        assert_eq!(chacha.position(), 256);
+    }
+
+    #[test]
+    fn test_process_blocks_discards_buffered_keystream() {
+        let key = [0x11u8; 32];
+        let nonce = [0x22u8; 12];
+
+        let mut chacha = ChaCha20::new_with_raw_nonce(&key, &nonce, 0);
+        chacha.apply_keystream(&mut [0u8; 10]);
+        let mut blocks = [[0u8; 64]; 1];
+        chacha.process_blocks(&mut blocks);
+
+        assert_eq!(blocks[0], ChaCha20::new_with_raw_nonce(&key, &nonce, 1).next_block_bytes());
+        assert_eq!(chacha.position(), 128);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_process_blocks_past_the_end() {
+        ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], u32::MAX).process_blocks(&mut [[0u8; 64]; 2]);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let key = [0x42u8; 32];
+
+        assert_eq!(ChaCha20::fingerprint(&key), ChaCha20::fingerprint(&key));
+        assert_ne!(ChaCha20::fingerprint(&key), ChaCha20::fingerprint(&[0x43u8; 32]));
+
+        let block = ChaCha20::new_with_raw_nonce(&key, &[0u8; 12], 0).next();
+        let mut keystream = [0u8; 8];
+        LittleEndian::write_u32_into(&block[0..2], &mut keystream);
+        assert_ne!(ChaCha20::fingerprint(&key), keystream);
+    }
+
+    #[test]
+    fn test_hchacha20() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+            0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+            0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+            0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+        ];
+
+        let input = [
+            0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a,
+            0x00, 0x00, 0x00, 0x00, 0x31, 0x41, 0x59, 0x27
+        ];
+
+        let expected: [u8; 32] = [
+            0x82, 0x41, 0x3b, 0x42, 0x27, 0xb2, 0x7b, 0xfe,
+            0xd3, 0x0e, 0x42, 0x50, 0x8a, 0x87, 0x7d, 0x73,
+            0xa0, 0xf9, 0xe4, 0xd5, 0x8a, 0x74, 0xa8, 0x53,
+            0xc1, 0x2e, 0xc4, 0x13, 0x26, 0xd3, 0xec, 0xdc
+        ];
+
+        assert_eq!(ChaCha20::hchacha20(&key, &input), expected);
+        assert_eq!(hchacha20(&key, &input), expected);
+    }
+
+    #[test]
+    fn test_hchacha20_sequential_input() {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+
+        let mut input = [0u8; 16];
+        for (i, byte) in input.iter_mut().enumerate() {
+            *byte = 0x40 + i as u8;
+        }
+
+        let expected: [u8; 32] = [
+            0x4a, 0x8a, 0xc0, 0xc0, 0x29, 0x62, 0x22, 0xba,
+            0xfe, 0x95, 0x9f, 0xaa, 0xbe, 0x06, 0xa4, 0x5b,
+            0x89, 0xa3, 0xce, 0xe4, 0x44, 0xfe, 0xf6, 0xe3,
+            0xd7, 0x76, 0x59, 0xa5, 0x3f, 0x49, 0xee, 0x32
+        ];
+
+        assert_eq!(hchacha20(&key, &input), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_encrypt() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+            0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+            0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+            0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+        ];
+
+        let nonce = [
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x4a,
+            0x00, 0x00, 0x00, 0x00
+        ];
+
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let expected: [u8; 114] = [
+            0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80,
+            0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d, 0x69, 0x81,
+            0xe9, 0x7e, 0x7a, 0xec, 0x1d, 0x43, 0x60, 0xc2,
+            0x0a, 0x27, 0xaf, 0xcc, 0xfd, 0x9f, 0xae, 0x0b,
+            0xf9, 0x1b, 0x65, 0xc5, 0x52, 0x47, 0x33, 0xab,
+            0x8f, 0x59, 0x3d, 0xab, 0xcd, 0x62, 0xb3, 0x57,
+            0x16, 0x39, 0xd6, 0x24, 0xe6, 0x51, 0x52, 0xab,
+            0x8f, 0x53, 0x0c, 0x35, 0x9f, 0x08, 0x61, 0xd8,
+            0x07, 0xca, 0x0d, 0xbf, 0x50, 0x0d, 0x6a, 0x61,
+            0x56, 0xa3, 0x8e, 0x08, 0x8a, 0x22, 0xb6, 0x5e,
+            0x52, 0xbc, 0x51, 0x4d, 0x16, 0xcc, 0xf8, 0x06,
+            0x81, 0x8c, 0xe9, 0x1a, 0xb7, 0x79, 0x37, 0x36,
+            0x5a, 0xf9, 0x0b, 0xbf, 0x74, 0xa3, 0x5b, 0xe6,
+            0xb4, 0x0b, 0x8e, 0xed, 0xf2, 0x78, 0x5e, 0x42,
+            0x87, 0x4d
+        ];
+
+        let ciphertext = encrypt(&key, &nonce, 1, plaintext);
+        assert_eq!(&ciphertext[..], &expected[..]);
+        assert_eq!(&decrypt(&key, &nonce, 1, &ciphertext)[..], &plaintext[..]);
+    }
+
+    /// RFC 8439 appendix A.2, test vector #1
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_encrypt_zero_key() {
+        let expected: [u8; 64] = [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90,
+            0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86, 0xbd, 0x28,
+            0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a,
+            0xa8, 0x36, 0xef, 0xcc, 0x8b, 0x77, 0x0d, 0xc7,
+            0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d,
+            0x77, 0x24, 0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37,
+            0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
+            0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86
+        ];
+
+        let ciphertext = encrypt(&[0u8; 32], &[0u8; 12], 0, &[0u8; 64]);
+        assert_eq!(&ciphertext[..], &expected[..]);
+        assert_eq!(decrypt(&[0u8; 32], &[0u8; 12], 0, &ciphertext), [0u8; 64]);
+    }
+
+    /// RFC 8439 appendix A.2, test vector #3
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_encrypt_jabberwocky() {
+        let key = [
+            0x1c, 0x92, 0x40, 0xa5, 0xeb, 0x55, 0xd3, 0x8a,
+            0xf3, 0x33, 0x88, 0x86, 0x04, 0xf6, 0xb5, 0xf0,
+            0x47, 0x39, 0x17, 0xc1, 0x40, 0x2b, 0x80, 0x09,
+            0x9d, 0xca, 0x5c, 0xbc, 0x20, 0x70, 0x75, 0xc0
+        ];
+
+        let nonce = [
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x02
+        ];
+
+        let plaintext = b"'Twas brillig, and the slithy toves\nDid gyre and gimble in the wabe:\nAll mimsy were the borogoves,\nAnd the mome raths outgrabe.";
+
+        let expected: [u8; 127] = [
+            0x62, 0xe6, 0x34, 0x7f, 0x95, 0xed, 0x87, 0xa4,
+            0x5f, 0xfa, 0xe7, 0x42, 0x6f, 0x27, 0xa1, 0xdf,
+            0x5f, 0xb6, 0x91, 0x10, 0x04, 0x4c, 0x0d, 0x73,
+            0x11, 0x8e, 0xff, 0xa9, 0x5b, 0x01, 0xe5, 0xcf,
+            0x16, 0x6d, 0x3d, 0xf2, 0xd7, 0x21, 0xca, 0xf9,
+            0xb2, 0x1e, 0x5f, 0xb1, 0x4c, 0x61, 0x68, 0x71,
+            0xfd, 0x84, 0xc5, 0x4f, 0x9d, 0x65, 0xb2, 0x83,
+            0x19, 0x6c, 0x7f, 0xe4, 0xf6, 0x05, 0x53, 0xeb,
+            0xf3, 0x9c, 0x64, 0x02, 0xc4, 0x22, 0x34, 0xe3,
+            0x2a, 0x35, 0x6b, 0x3e, 0x76, 0x43, 0x12, 0xa6,
+            0x1a, 0x55, 0x32, 0x05, 0x57, 0x16, 0xea, 0xd6,
+            0x96, 0x25, 0x68, 0xf8, 0x7d, 0x3f, 0x3f, 0x77,
+            0x04, 0xc6, 0xa8, 0xd1, 0xbc, 0xd1, 0xbf, 0x4d,
+            0x50, 0xd6, 0x15, 0x4b, 0x6d, 0xa7, 0x31, 0xb1,
+            0x87, 0xb5, 0x8d, 0xfd, 0x72, 0x8a, 0xfa, 0x36,
+            0x75, 0x7a, 0x79, 0x7a, 0xc1, 0x88, 0xd1
+        ];
+
+        let ciphertext = encrypt(&key, &nonce, 42, plaintext);
+        assert_eq!(&ciphertext[..], &expected[..]);
+        assert_eq!(&decrypt(&key, &nonce, 42, &ciphertext)[..], &plaintext[..]);
+    }
+
+    #[test]
+    fn test_multiple_states() {
+        let mut state = ChaCha20::new_with_raw_nonce(&[0; 32], &[0; 12], 0);
+
+        let expected_state: [u8; 64] = [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90,
+            0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86, 0xbd, 0x28,
+            0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a,
+            0xa8, 0x36, 0xef, 0xcc, 0x8b, 0x77, 0x0d, 0xc7,
+            0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d,
+            0x77, 0x24, 0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37,
+            0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
+            0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86
+        ];
+        let expected_state = format_expected(expected_state);
+
+
+        assert_eq!(
+            state.next(),
+            expected_state
+        );
+
+        let expected_state: [u8; 64] = [
+            0x9f, 0x07, 0xe7, 0xbe, 0x55, 0x51, 0x38, 0x7a,
+            0x98, 0xba, 0x97, 0x7c, 0x73, 0x2d, 0x08, 0x0d,
+            0xcb, 0x0f, 0x29, 0xa0, 0x48, 0xe3, 0x65, 0x69,
+            0x12, 0xc6, 0x53, 0x3e, 0x32, 0xee, 0x7a, 0xed,
+            0x29, 0xb7, 0x21, 0x76, 0x9c, 0xe6, 0x4e, 0x43,
+            0xd5, 0x71, 0x33, 0xb0, 0x74, 0xd8, 0x39, 0xd5,
+            0x31, 0xed, 0x1f, 0x28, 0x51, 0x0a, 0xfb, 0x45,
+            0xac, 0xe1, 0x0a, 0x1f, 0x4b, 0x79, 0x4d, 0x6f
+        ];
+        let expected_state = format_expected(expected_state);
+
+        assert_eq!(
+            state.next(),
+            expected_state
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_keeps_the_key_out_of_debug_output() {
+        let mut chacha = ChaCha20::new_with_raw_nonce(&[0xAB; 32], &[0u8; 12], 1);
+        chacha.apply_keystream(&mut [0u8; 70]);
+        let debug = format!("{:?}", chacha);
+
+        assert_eq!(debug, format!("ChaCha {{ fingerprint: {:?}, counter: 2 }}", ChaCha20::fingerprint(&[0xAB; 32])));
+    }
+}
+
+/// Checks that dropped ciphers leave no key material in freed memory, and
+/// that the paths promising no allocation keep that promise
+///
+/// Relies on the test allocator of `mem::test_alloc`.
+#[cfg(all(test, feature = "std"))]
+mod erasure_test {
+    use super::*;
+    use std::boxed::Box;
+
+    use crate::chacha20_poly1305::ChaCha20Poly1305;
+    use crate::mem::test_alloc::{allocations, freed_bytes};
+    use crate::poly1305::TAG_LEN;
+    use crate::stream::{StreamDecryptor, StreamEncryptor};
+
+    fn contains_run(bytes: &[u8], value: u8) -> bool {
+        bytes.windows(4).any(|window| window.iter().all(|byte| *byte == value))
+    }
+
+    #[test]
+    fn test_allocator_sees_freed_memory() {
+        let bytes = freed_bytes(Box::new([0xAAu8; 64]));
+
+        assert!(contains_run(&bytes, 0xAA));
+    }
+
+    #[test]
+    fn test_drop_erases_state() {
+        let chacha20 = Box::new(ChaCha20::new_with_raw_nonce(&[0xAAu8; 32], &[0xBBu8; 12], 0xCCCCCCCC));
+        let bytes = freed_bytes(chacha20);
+
+        assert!(!contains_run(&bytes, 0xAA));
+        assert!(!contains_run(&bytes, 0xBB));
+        assert!(!contains_run(&bytes, 0xCC));
+    }
+
+    #[test]
+    fn test_drop_erases_buffered_keystream() {
+        let keystream = ChaCha20::new_with_raw_nonce(&[0xAAu8; 32], &[0xBBu8; 12], 0).next_block_bytes();
+
+        let mut chacha20 = Box::new(ChaCha20::new_with_raw_nonce(&[0xAAu8; 32], &[0xBBu8; 12], 0));
+        chacha20.apply_keystream(&mut [0u8; 1]);
+        let bytes = freed_bytes(chacha20);
+
+        assert!(!bytes.windows(8).any(|window| keystream.windows(8).any(|run| run == window)));
+    }
+
+    #[test]
+    fn test_allocator_counts_allocations() {
+        assert_eq!(allocations(|| drop(Box::new(0u64))), 1);
+    }
+
+    #[test]
+    fn test_apply_keystream_does_not_allocate() {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&[0xAAu8; 32], &[0xBBu8; 12], 0);
+        let mut buffer = [0u8; 1000];
+
+        assert_eq!(allocations(|| {
+            chacha20.apply_keystream(&mut buffer[..7]);
+            chacha20.apply_keystream(&mut buffer[7..]);
+        }), 0);
+    }
+
+    #[test]
+    fn test_in_place_aead_does_not_allocate() {
+        let aead = ChaCha20Poly1305::new(&[0xAAu8; 32]);
+        let mut buffer = [0u8; 1000];
+        let mut tag = [0u8; TAG_LEN];
+
+        assert_eq!(allocations(|| {
+            aead.seal_in_place(&[0xBBu8; 12], b"aad", &mut buffer, &mut tag);
+            aead.open_in_place(&[0xBBu8; 12], b"aad", &mut buffer, &tag).unwrap();
+        }), 0);
+    }
+
+    #[test]
+    fn test_stream_chunks_do_not_allocate() {
+        let mut encryptor = StreamEncryptor::new(&[0xAAu8; 32], &[0xBBu8; 7]);
+        let mut decryptor = StreamDecryptor::new(&[0xAAu8; 32], &[0xBBu8; 7]);
+        let mut buffer = [0u8; 1000];
+        let mut tag = [0u8; TAG_LEN];
+
+        assert_eq!(allocations(|| {
+            encryptor.encrypt_next_in_place(b"", &mut buffer, &mut tag).unwrap();
+            decryptor.decrypt_next_in_place(b"", &mut buffer, &tag).unwrap();
+        }), 0);
+    }
+}
@@ -0,0 +1,237 @@
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20::ChaCha20;
+
+/// Keystream bytes covered by one value of the upper counter word
+const SEGMENT_LEN: u64 = 64 << 32;
+
+/// Bernstein's original ChaCha20 with an 8-byte nonce and a 64-bit block counter
+///
+/// Words 12 and 13 of the state hold the counter and words 14 and 15 the
+/// nonce, instead of the 32-bit counter and 12-byte nonce of RFC 8439. This is
+/// the layout used by libsodium's `crypto_stream_chacha20` and several file
+/// formats. The key is kept to restart the cipher each time the lower counter
+/// word wraps, and is wiped on drop.
+pub struct ChaCha20Legacy {
+    key: [u8; 32],
+    key_len: usize,
+    nonce: [u8; 8],
+    counter_high: u32,
+    chacha: ChaCha20,
+}
+
+impl ChaCha20Legacy {
+    /// Returns a new instance of ChaCha20Legacy starting at block `counter`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `nonce` is not of size 8
+    pub fn new(key: &[u8], nonce: &[u8], counter: u64) -> ChaCha20Legacy {
+        assert_eq!(key.len(), 32);
+
+        ChaCha20Legacy::with_key(key, nonce, counter)
+    }
+
+    /// Returns a new instance of ChaCha20Legacy keyed with a 128-bit key
+    ///
+    /// See `ChaCha20::with_128_bit_key`.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 16
+    /// The function will panic if `nonce` is not of size 8
+    pub fn with_128_bit_key(key: &[u8], nonce: &[u8], counter: u64) -> ChaCha20Legacy {
+        assert_eq!(key.len(), 16);
+
+        ChaCha20Legacy::with_key(key, nonce, counter)
+    }
+
+    /// Returns a new instance of ChaCha20Legacy for a key of either size
+    fn with_key(key: &[u8], nonce: &[u8], counter: u64) -> ChaCha20Legacy {
+        assert_eq!(nonce.len(), 8);
+
+        let mut legacy = ChaCha20Legacy {
+            key: [0u8; 32],
+            key_len: key.len(),
+            nonce: [0u8; 8],
+            counter_high: (counter >> 32) as u32,
+            chacha: ChaCha20::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0),
+        };
+        legacy.key[..key.len()].copy_from_slice(key);
+        legacy.nonce.copy_from_slice(nonce);
+        legacy.chacha = legacy.segment(counter as u32);
+
+        legacy
+    }
+
+    /// Encrypts or decrypts `buf` in place
+    ///
+    /// Successive calls consume one contiguous keystream, as with
+    /// `ChaCha20::apply_keystream`, and the counter carries into its upper
+    /// word instead of stopping after 256 GiB.
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+        let mut done = 0;
+
+        while done < buf.len() {
+            if self.chacha.remaining_keystream() == 0 {
+                self.counter_high = self.counter_high.wrapping_add(1);
+                self.chacha = self.segment(0);
+            }
+
+            let take = ((buf.len() - done) as u64).min(self.chacha.remaining_keystream()) as usize;
+            self.chacha.apply_keystream(&mut buf[done..(done + take)]);
+
+            done += take;
+        }
+    }
+
+    /// Moves to byte `offset` of the keystream, counted from block counter 0
+    ///
+    /// Only the first 2^64 bytes of the 2^70 byte keystream can be addressed
+    /// this way; use `new` with a block counter to start further in.
+    pub fn seek(&mut self, offset: u64) {
+        self.counter_high = (offset / SEGMENT_LEN) as u32;
+        self.chacha = self.segment(0);
+        self.chacha.seek(offset % SEGMENT_LEN);
+    }
+
+    /// Returns the absolute keystream byte position, truncated to 64 bits
+    pub fn position(&self) -> u64 {
+        (self.counter_high as u64).wrapping_mul(SEGMENT_LEN).wrapping_add(self.chacha.position())
+    }
+
+    /// Returns a ChaCha20 instance running the 2^32 blocks that share `counter_high`
+    ///
+    /// With the upper counter word in place of the first RFC 8439 nonce word,
+    /// the RFC state is exactly the original state for those blocks.
+    fn segment(&self, counter_low: u32) -> ChaCha20 {
+        let mut ietf_nonce = [0u8; 12];
+        ietf_nonce[0..4].copy_from_slice(&self.counter_high.to_le_bytes());
+        ietf_nonce[4..12].copy_from_slice(&self.nonce);
+
+        match self.key_len {
+            16 => ChaCha20::with_128_bit_key(&self.key[0..16], &ietf_nonce, counter_low),
+            _ => ChaCha20::new_with_raw_nonce(&self.key, &ietf_nonce, counter_low),
+        }
+    }
+}
+
+impl fmt::Debug for ChaCha20Legacy {
+    /// Shows the key fingerprint and the block of the next keystream byte, never the key
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fingerprint = ChaCha20::fingerprint(&self.key);
+
+        f.debug_struct("ChaCha20Legacy").field("fingerprint", &fingerprint).field("counter", &(self.position() / 64)).finish()
+    }
+}
+
+impl Drop for ChaCha20Legacy {
+    /// Wipes the key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NONCE: [u8; 8] = [0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+
+    fn key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+
+        key
+    }
+
+    #[test]
+    fn it_matches_the_original_layout() {
+        let expected: [u8; 64] = [
+            0x5a, 0x32, 0x05, 0xb5, 0xc0, 0xbb, 0x39, 0xa3,
+            0x7c, 0x39, 0x31, 0xda, 0x25, 0x56, 0x53, 0x31,
+            0x6c, 0xf1, 0xc8, 0x81, 0x37, 0x5d, 0xf6, 0x18,
+            0x43, 0x6e, 0xa7, 0xd9, 0x54, 0x8e, 0xeb, 0x5d,
+            0xfb, 0x6e, 0xbd, 0x5b, 0xaa, 0x0c, 0xfb, 0xd7,
+            0xef, 0x3a, 0x83, 0xd5, 0xf5, 0x40, 0xf5, 0xaf,
+            0x24, 0xcb, 0x23, 0xd9, 0x7d, 0xa4, 0x0c, 0x3a,
+            0x1a, 0x03, 0x3d, 0xed, 0x65, 0xae, 0x68, 0xe5
+        ];
+
+        let mut keystream = [0u8; 64];
+        ChaCha20Legacy::new(&key(), &NONCE, 0).apply_keystream(&mut keystream);
+
+        assert_eq!(&keystream[..], &expected[..]);
+    }
+
+    #[test]
+    fn it_supports_128_bit_keys() {
+        let mut expected = [0u8; 64];
+        ChaCha20::with_128_bit_key(&[0u8; 16], &[0u8; 12], 0).apply_keystream(&mut expected);
+
+        let mut keystream = [0u8; 64];
+        ChaCha20Legacy::with_128_bit_key(&[0u8; 16], &[0u8; 8], 0).apply_keystream(&mut keystream);
+
+        assert_eq!(&keystream[..], &expected[..]);
+        assert_eq!(&keystream[0..4], &[0x89, 0x67, 0x09, 0x52]);
+    }
+
+    #[test]
+    fn it_carries_into_the_upper_counter_word() {
+        let expected: [u8; 128] = [
+            0xaf, 0x6f, 0x39, 0x55, 0x65, 0xec, 0xba, 0x51,
+            0xc3, 0x90, 0x6a, 0x98, 0x79, 0x4c, 0x3b, 0xdd,
+            0x4f, 0xbc, 0x2e, 0x68, 0x04, 0x6d, 0xf5, 0x2e,
+            0xd8, 0xde, 0xd2, 0x6e, 0x0c, 0x48, 0x6c, 0xd4,
+            0x66, 0xc7, 0xab, 0x27, 0xf1, 0x85, 0x63, 0x45,
+            0x14, 0xf5, 0xb2, 0x43, 0x5f, 0xae, 0xe2, 0x65,
+            0x3e, 0x6d, 0x55, 0xbf, 0x46, 0x07, 0x30, 0xbe,
+            0x1b, 0x94, 0x3e, 0x9f, 0xde, 0x0a, 0xcc, 0xf0,
+            0x38, 0x68, 0x54, 0xd3, 0x6a, 0x1a, 0x70, 0x3e,
+            0x03, 0xad, 0x37, 0xf0, 0x2b, 0x41, 0x80, 0x77,
+            0x51, 0x2c, 0x25, 0x68, 0xad, 0xda, 0x7c, 0x5f,
+            0x5c, 0xca, 0xf2, 0x5d, 0xd3, 0xa1, 0x3a, 0x26,
+            0xd5, 0x64, 0x21, 0xa3, 0x71, 0x89, 0x12, 0xa8,
+            0x58, 0x14, 0x15, 0xf8, 0x91, 0xa6, 0xd6, 0xf6,
+            0x5b, 0x27, 0xac, 0xbe, 0xb5, 0xc0, 0xe8, 0xd6,
+            0x83, 0xe4, 0xc7, 0x1f, 0xb7, 0x78, 0xf4, 0xbb
+        ];
+
+        let mut keystream = [0u8; 128];
+        let mut legacy = ChaCha20Legacy::new(&key(), &NONCE, u32::MAX as u64);
+        legacy.apply_keystream(&mut keystream[..10]);
+        legacy.apply_keystream(&mut keystream[10..]);
+
+        assert_eq!(&keystream[..], &expected[..]);
+        assert_eq!(legacy.position(), (u32::MAX as u64 + 2) * 64);
+    }
+
+    #[test]
+    fn it_seeks_across_the_upper_counter_word() {
+        let mut whole = [0u8; 128];
+        ChaCha20Legacy::new(&key(), &NONCE, u32::MAX as u64).apply_keystream(&mut whole);
+
+        let mut legacy = ChaCha20Legacy::new(&key(), &NONCE, 0);
+        legacy.seek(SEGMENT_LEN + 3);
+        let mut tail = [0u8; 61];
+        legacy.apply_keystream(&mut tail);
+
+        assert_eq!(&tail[..], &whole[67..]);
+        assert_eq!(legacy.position(), SEGMENT_LEN + 64);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_keeps_the_key_out_of_debug_output() {
+        let legacy = ChaCha20Legacy::new(&key(), &NONCE, 3);
+        let debug = format!("{:?}", legacy);
+
+        assert_eq!(debug, format!("ChaCha20Legacy {{ fingerprint: {:?}, counter: 3 }}", ChaCha20::fingerprint(&key())));
+    }
+}
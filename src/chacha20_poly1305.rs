@@ -0,0 +1,636 @@
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{constant_time_eq, wipe, Endian, LittleEndian};
+#[cfg(feature = "alloc")]
+use crate::chacha20::hchacha20;
+use crate::chacha20::ChaCha;
+use crate::error::Error;
+use crate::poly1305::{Poly1305, TAG_LEN};
+
+/// Size of the key commitment in front of a committing ciphertext
+pub const COMMITMENT_LEN: usize = 32;
+
+/// First 4 bytes of the HChaCha20 input deriving the key commitment
+#[cfg(feature = "alloc")]
+const COMMITMENT_LABEL: &[u8; 4] = b"cmt\0";
+
+/// First 4 bytes of the HChaCha20 input deriving the message key of committing mode
+#[cfg(feature = "alloc")]
+const SUBKEY_LABEL: &[u8; 4] = b"enc\0";
+
+/// ChaCha-Poly1305 authenticated encryption with associated data, using `R` ChaCha rounds
+///
+/// Block 0 of the keystream keys Poly1305 and the message is encrypted from
+/// block 1. The tag covers the AAD and the ciphertext, each zero-padded to 16
+/// bytes, followed by both lengths as 64-bit little endian values. A nonce
+/// must never be reused with the same key.
+///
+/// Use the `ChaCha20Poly1305` alias, which is RFC 8439. `ChaCha8Poly1305` and
+/// `ChaCha12Poly1305` apply the same construction to the reduced-round
+/// ciphers, for users who accept the smaller security margin; they do not
+/// interoperate with anything standardised.
+pub struct ChaChaPoly1305<const R: usize> {
+    key: [u8; 32],
+}
+
+/// ChaCha-Poly1305 with the keystream reduced to 8 rounds
+pub type ChaCha8Poly1305 = ChaChaPoly1305<8>;
+
+/// ChaCha-Poly1305 with the keystream reduced to 12 rounds
+pub type ChaCha12Poly1305 = ChaChaPoly1305<12>;
+
+/// The standard ChaCha20-Poly1305 AEAD (RFC 8439)
+pub type ChaCha20Poly1305 = ChaChaPoly1305<20>;
+
+impl<const R: usize> ChaChaPoly1305<R> {
+    /// Returns a new instance of ChaChaPoly1305
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> ChaChaPoly1305<R> {
+        assert_eq!(key.len(), 32);
+
+        let mut aead = ChaChaPoly1305 { key: [0u8; 32] };
+        aead.key.copy_from_slice(key);
+
+        aead
+    }
+
+    /// Encrypts `plaintext` and returns the ciphertext followed by the 16-byte tag
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    /// The function will panic if `plaintext` is longer than the 256 GiB keystream
+    #[cfg(feature = "alloc")]
+    pub fn encrypt(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let (mut sealed, tag) = self.encrypt_detached(nonce, aad, plaintext);
+        sealed.extend_from_slice(&tag);
+
+        sealed
+    }
+
+    /// Verifies the tag at the end of `ciphertext` and returns the decrypted message
+    ///
+    /// Nothing is decrypted unless the tag matches; a wrong key, nonce or
+    /// AAD, or any modification, returns `Error::InvalidTag`.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    #[cfg(feature = "alloc")]
+    pub fn decrypt(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        if ciphertext.len() < TAG_LEN {
+            return Err(Error::InvalidTag);
+        }
+
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_LEN);
+        self.decrypt_detached(nonce, aad, ciphertext, tag)
+    }
+
+    /// Encrypts `plaintext` and returns the ciphertext and the tag separately
+    ///
+    /// For formats that store the tag apart from the ciphertext. The bytes are
+    /// the same as `encrypt` produces, split before the last 16.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    /// The function will panic if `plaintext` is longer than the 256 GiB keystream
+    #[cfg(feature = "alloc")]
+    pub fn encrypt_detached(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> (Vec<u8>, [u8; TAG_LEN]) {
+        let mut ciphertext = plaintext.to_vec();
+        let mut tag = [0u8; TAG_LEN];
+        self.seal_in_place(nonce, aad, &mut ciphertext, &mut tag);
+
+        (ciphertext, tag)
+    }
+
+    /// Verifies the detached `tag` and returns the decrypted `ciphertext`
+    ///
+    /// Nothing is decrypted unless the tag matches.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    #[cfg(feature = "alloc")]
+    pub fn decrypt_detached(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut plaintext = ciphertext.to_vec();
+        self.open_in_place(nonce, aad, &mut plaintext, tag)?;
+
+        Ok(plaintext)
+    }
+
+    /// Encrypts `buffer` in place and writes the tag to `tag_out`
+    ///
+    /// Needs no allocation, so it is available without the `alloc` feature.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    /// The function will panic if `tag_out` is not of size 16
+    /// The function will panic if `buffer` is longer than the 256 GiB keystream
+    pub fn seal_in_place(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag_out: &mut [u8]) {
+        assert_eq!(tag_out.len(), TAG_LEN);
+
+        let (mut chacha20, mac) = self.start(nonce);
+        chacha20.apply_keystream(buffer);
+        tag_out.copy_from_slice(&Self::tag(mac, aad, buffer));
+    }
+
+    /// Verifies `tag` and decrypts `buffer` in place
+    ///
+    /// Returns `Error::InvalidTag` and leaves `buffer` untouched if the tag
+    /// does not match.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    pub fn open_in_place(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<(), Error> {
+        let (mut chacha20, mac) = self.start(nonce);
+        if !constant_time_eq(&Self::tag(mac, aad, buffer), tag) {
+            return Err(Error::InvalidTag);
+        }
+
+        chacha20.apply_keystream(buffer);
+
+        Ok(())
+    }
+
+    /// Encrypts `plaintext` in key-committing mode
+    ///
+    /// Plain ChaCha20-Poly1305 lets an attacker build one ciphertext that
+    /// decrypts under several keys, which turns a password-guessing setting
+    /// into a partitioning oracle. This mode derives two values from the key
+    /// and nonce with HChaCha20, using the nonce prefixed with a 4-byte label
+    /// as input:
+    ///
+    /// - the commitment, with label `"cmt\0"`, written in front of the output
+    /// - the message key, with label `"enc\0"`, used for ordinary
+    ///   ChaCha20-Poly1305 with the same nonce and AAD
+    ///
+    /// The output is `commitment || ciphertext || tag`, 48 bytes longer than
+    /// `plaintext`. It can only be opened with `decrypt_committing`.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    /// The function will panic if `plaintext` is longer than the 256 GiB keystream
+    #[cfg(feature = "alloc")]
+    pub fn encrypt_committing(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let (commitment, aead) = self.commit(nonce);
+
+        let mut sealed = Vec::with_capacity(COMMITMENT_LEN + plaintext.len() + TAG_LEN);
+        sealed.extend_from_slice(&commitment);
+        sealed.extend_from_slice(&aead.encrypt(nonce, aad, plaintext));
+
+        sealed
+    }
+
+    /// Verifies and decrypts the output of `encrypt_committing`
+    ///
+    /// Returns `Error::InvalidTag` if either the commitment or the tag does
+    /// not match, so a ciphertext opens under at most one key.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    #[cfg(feature = "alloc")]
+    pub fn decrypt_committing(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let (commitment, aead) = self.commit(nonce);
+        if ciphertext.len() < COMMITMENT_LEN || !constant_time_eq(&commitment, &ciphertext[..COMMITMENT_LEN]) {
+            return Err(Error::InvalidTag);
+        }
+
+        aead.decrypt(nonce, aad, &ciphertext[COMMITMENT_LEN..])
+    }
+
+    /// Returns the key commitment and the message key instance for `nonce`
+    #[cfg(feature = "alloc")]
+    fn commit(&self, nonce: &[u8]) -> ([u8; COMMITMENT_LEN], ChaChaPoly1305<R>) {
+        assert_eq!(nonce.len(), 12);
+
+        let mut input = [0u8; 16];
+        input[4..16].copy_from_slice(nonce);
+
+        input[0..4].copy_from_slice(COMMITMENT_LABEL);
+        let commitment = hchacha20(&self.key, &input);
+
+        input[0..4].copy_from_slice(SUBKEY_LABEL);
+        let mut subkey = hchacha20(&self.key, &input);
+        let aead = ChaChaPoly1305::new(&subkey);
+        wipe(&mut subkey);
+
+        (commitment, aead)
+    }
+
+    /// Returns the cipher positioned at block 1 and Poly1305 keyed from block 0
+    fn start(&self, nonce: &[u8]) -> (ChaCha<R>, Poly1305) {
+        let mut chacha20 = ChaCha::new_with_raw_nonce(&self.key, nonce, 0);
+
+        let mut block = chacha20.next_block_bytes();
+        let mac = Poly1305::new(&block[0..32]);
+        wipe(&mut block);
+
+        (chacha20, mac)
+    }
+
+    /// Computes the tag over the padded AAD, padded ciphertext and their lengths
+    fn tag(mut mac: Poly1305, aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        let mut lengths = [0u8; 16];
+        LittleEndian::write_u64(aad.len() as u64, &mut lengths[0..8]);
+        LittleEndian::write_u64(ciphertext.len() as u64, &mut lengths[8..16]);
+
+        mac.update(aad);
+        mac.pad_to_block();
+        mac.update(ciphertext);
+        mac.pad_to_block();
+        mac.update(&lengths);
+
+        mac.finalize()
+    }
+}
+
+impl<const R: usize> Drop for ChaChaPoly1305<R> {
+    /// Wipes the key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+/// ChaCha20-Poly1305 with nonces built from a 64-bit message counter
+///
+/// This is the WireGuard and Noise nonce layout: 4 zero bytes followed by the
+/// counter in little endian. Sealing uses the next counter value and returns
+/// it for the receiver, which passes it back to `open`. The counter never
+/// repeats, so neither does the nonce; once it is used up the key has to be
+/// replaced.
+#[cfg(feature = "alloc")]
+pub struct CounterNonceAead {
+    aead: ChaCha20Poly1305,
+    counter: u64,
+}
+
+#[cfg(feature = "alloc")]
+impl CounterNonceAead {
+    /// Returns a new instance of CounterNonceAead starting at counter 0
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> CounterNonceAead {
+        CounterNonceAead::with_counter(key, 0)
+    }
+
+    /// Returns a new instance of CounterNonceAead resuming at `counter`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn with_counter(key: &[u8], counter: u64) -> CounterNonceAead {
+        CounterNonceAead { aead: ChaCha20Poly1305::new(key), counter }
+    }
+
+    /// Encrypts `plaintext` under the next counter and returns the counter with the sealed message
+    ///
+    /// Returns `Error::KeyExpired` once every counter below `u64::MAX` has been used.
+    pub fn seal(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<(u64, Vec<u8>), Error> {
+        if self.counter == u64::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        let counter = self.counter;
+        self.counter += 1;
+
+        Ok((counter, self.aead.encrypt(&counter_nonce(counter), aad, plaintext)))
+    }
+
+    /// Verifies and decrypts a message sealed under `counter`
+    ///
+    /// Rejecting replayed counters is left to the caller, which usually keeps
+    /// a sliding window of the counters already seen.
+    pub fn open(&self, counter: u64, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        self.aead.decrypt(&counter_nonce(counter), aad, ciphertext)
+    }
+
+    /// Returns the counter the next call to `seal` will use
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+}
+
+/// Returns the 12-byte nonce for a 64-bit message counter, 4 zero bytes followed by the counter in little endian
+pub fn counter_nonce(counter: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    LittleEndian::write_u64(counter, &mut nonce[4..12]);
+
+    nonce
+}
+
+/// Encrypts `plaintext` under `key` and returns the ciphertext followed by the 16-byte tag
+///
+/// Same as `ChaCha20Poly1305::new(key).encrypt(nonce, aad, plaintext)`, for
+/// sealing a single message without keeping the cipher around.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+/// The function will panic if `nonce` is not of size 12
+/// The function will panic if `plaintext` is longer than the 256 GiB keystream
+#[cfg(feature = "alloc")]
+pub fn seal(key: &[u8], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(key).encrypt(nonce, aad, plaintext)
+}
+
+/// Verifies the tag at the end of `ciphertext` and returns the decrypted message
+///
+/// Same as `ChaCha20Poly1305::new(key).decrypt(nonce, aad, ciphertext)`.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+/// The function will panic if `nonce` is not of size 12
+#[cfg(feature = "alloc")]
+pub fn open(key: &[u8], nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    ChaCha20Poly1305::new(key).decrypt(nonce, aad, ciphertext)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NONCE: [u8; 12] = [0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+
+    const AAD: [u8; 12] = [0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7];
+
+    const PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+    fn key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+
+        key
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_matches_the_rfc_8439_vector() {
+        let expected: [u8; 130] = [
+            0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb,
+            0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef, 0x7e, 0xc2,
+            0xa4, 0xad, 0xed, 0x51, 0x29, 0x6e, 0x08, 0xfe,
+            0xa9, 0xe2, 0xb5, 0xa7, 0x36, 0xee, 0x62, 0xd6,
+            0x3d, 0xbe, 0xa4, 0x5e, 0x8c, 0xa9, 0x67, 0x12,
+            0x82, 0xfa, 0xfb, 0x69, 0xda, 0x92, 0x72, 0x8b,
+            0x1a, 0x71, 0xde, 0x0a, 0x9e, 0x06, 0x0b, 0x29,
+            0x05, 0xd6, 0xa5, 0xb6, 0x7e, 0xcd, 0x3b, 0x36,
+            0x92, 0xdd, 0xbd, 0x7f, 0x2d, 0x77, 0x8b, 0x8c,
+            0x98, 0x03, 0xae, 0xe3, 0x28, 0x09, 0x1b, 0x58,
+            0xfa, 0xb3, 0x24, 0xe4, 0xfa, 0xd6, 0x75, 0x94,
+            0x55, 0x85, 0x80, 0x8b, 0x48, 0x31, 0xd7, 0xbc,
+            0x3f, 0xf4, 0xde, 0xf0, 0x8e, 0x4b, 0x7a, 0x9d,
+            0xe5, 0x76, 0xd2, 0x65, 0x86, 0xce, 0xc6, 0x4b,
+            0x61, 0x16, 0x1a, 0xe1, 0x0b, 0x59, 0x4f, 0x09,
+            0xe2, 0x6a, 0x7e, 0x90, 0x2e, 0xcb, 0xd0, 0x60,
+            0x06, 0x91
+        ];
+
+        let aead = ChaCha20Poly1305::new(&key());
+        let sealed = aead.encrypt(&NONCE, &AAD, PLAINTEXT);
+
+        assert_eq!(&sealed[..], &expected[..]);
+        assert_eq!(aead.decrypt(&NONCE, &AAD, &sealed), Ok(PLAINTEXT.to_vec()));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_authenticates_empty_messages() {
+        let expected: [u8; 16] = [
+            0xa0, 0x78, 0x4d, 0x7a, 0x47, 0x16, 0xf3, 0xfe,
+            0xb4, 0xf6, 0x4e, 0x7f, 0x4b, 0x39, 0xbf, 0x04
+        ];
+
+        let aead = ChaCha20Poly1305::new(&key());
+
+        assert_eq!(&aead.encrypt(&NONCE, b"", b"")[..], &expected[..]);
+        assert_eq!(aead.decrypt(&NONCE, b"", &expected), Ok(Vec::new()));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_round_trips_reduced_round_variants() {
+        let eight = ChaCha8Poly1305::new(&key()).encrypt(&NONCE, &AAD, PLAINTEXT);
+        let twelve = ChaCha12Poly1305::new(&key()).encrypt(&NONCE, &AAD, PLAINTEXT);
+        let twenty = ChaCha20Poly1305::new(&key()).encrypt(&NONCE, &AAD, PLAINTEXT);
+
+        assert_ne!(eight, twenty);
+        assert_ne!(twelve, twenty);
+        assert_ne!(eight, twelve);
+        assert_eq!(ChaCha8Poly1305::new(&key()).decrypt(&NONCE, &AAD, &eight), Ok(PLAINTEXT.to_vec()));
+        assert_eq!(ChaCha12Poly1305::new(&key()).decrypt(&NONCE, &AAD, &twelve), Ok(PLAINTEXT.to_vec()));
+        assert_eq!(ChaCha20Poly1305::new(&key()).decrypt(&NONCE, &AAD, &eight), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_seals_reduced_round_variants_in_place() {
+        let mut buffer = *b"reduced rounds";
+        let mut tag = [0u8; TAG_LEN];
+        let aead = ChaCha8Poly1305::new(&key());
+        aead.seal_in_place(&NONCE, &AAD, &mut buffer, &mut tag);
+
+        assert_ne!(&buffer, b"reduced rounds");
+        assert_eq!(aead.open_in_place(&NONCE, &AAD, &mut buffer, &tag), Ok(()));
+        assert_eq!(&buffer, b"reduced rounds");
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_seals_and_opens_in_one_call() {
+        let sealed = seal(&key(), &NONCE, &AAD, PLAINTEXT);
+
+        assert_eq!(sealed, ChaCha20Poly1305::new(&key()).encrypt(&NONCE, &AAD, PLAINTEXT));
+        assert_eq!(open(&key(), &NONCE, &AAD, &sealed), Ok(PLAINTEXT.to_vec()));
+        assert_eq!(open(&key(), &NONCE, b"", &sealed), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_rejects_tampering() {
+        let aead = ChaCha20Poly1305::new(&key());
+        let mut sealed = aead.encrypt(&NONCE, &AAD, PLAINTEXT);
+
+        assert_eq!(aead.decrypt(&NONCE, &AAD[1..], &sealed), Err(Error::InvalidTag));
+        assert_eq!(aead.decrypt(&[0u8; 12], &AAD, &sealed), Err(Error::InvalidTag));
+        assert_eq!(ChaCha20Poly1305::new(&[0u8; 32]).decrypt(&NONCE, &AAD, &sealed), Err(Error::InvalidTag));
+        assert_eq!(aead.decrypt(&NONCE, &AAD, &sealed[..15]), Err(Error::InvalidTag));
+
+        sealed[0] ^= 1;
+        assert_eq!(aead.decrypt(&NONCE, &AAD, &sealed), Err(Error::InvalidTag));
+
+        sealed[0] ^= 1;
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert_eq!(aead.decrypt(&NONCE, &AAD, &sealed), Err(Error::InvalidTag));
+    }
+
+    /// Asserts `open` accepts `sealed` but rejects every bit flip, truncation and extension of it, of `aad` and of the nonce
+    #[cfg(feature = "alloc")]
+    fn assert_rejects_mutations(sealed: &[u8], aad: &[u8], open: impl Fn(&[u8], &[u8], &[u8]) -> Result<Vec<u8>, Error>) {
+        assert!(open(&NONCE, aad, sealed).is_ok());
+
+        for bit in 0..sealed.len() * 8 {
+            let mut mutated = sealed.to_vec();
+            mutated[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(open(&NONCE, aad, &mutated), Err(Error::InvalidTag), "sealed bit {}", bit);
+        }
+
+        for bit in 0..aad.len() * 8 {
+            let mut mutated = aad.to_vec();
+            mutated[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(open(&NONCE, &mutated, sealed), Err(Error::InvalidTag), "aad bit {}", bit);
+        }
+
+        for bit in 0..NONCE.len() * 8 {
+            let mut mutated = NONCE;
+            mutated[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(open(&mutated, aad, sealed), Err(Error::InvalidTag), "nonce bit {}", bit);
+        }
+
+        for length in 0..sealed.len() {
+            assert_eq!(open(&NONCE, aad, &sealed[..length]), Err(Error::InvalidTag), "sealed length {}", length);
+        }
+
+        for length in 0..aad.len() {
+            assert_eq!(open(&NONCE, &aad[..length], sealed), Err(Error::InvalidTag), "aad length {}", length);
+        }
+
+        let mut extended = sealed.to_vec();
+        extended.push(0);
+        assert_eq!(open(&NONCE, aad, &extended), Err(Error::InvalidTag));
+
+        let mut extended = aad.to_vec();
+        extended.push(0);
+        assert_eq!(open(&NONCE, &extended, sealed), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_rejects_every_mutation_of_the_rfc_8439_vectors() {
+        let aead = ChaCha20Poly1305::new(&key());
+
+        for (aad, plaintext) in [(&AAD[..], PLAINTEXT), (&b""[..], &b""[..])].iter() {
+            let sealed = aead.encrypt(&NONCE, aad, plaintext);
+            assert_rejects_mutations(&sealed, aad, |nonce, aad, sealed| aead.decrypt(nonce, aad, sealed));
+            assert_rejects_mutations(&sealed, aad, |nonce, aad, sealed| open(&key(), nonce, aad, sealed));
+
+            let committed = aead.encrypt_committing(&NONCE, aad, plaintext);
+            assert_rejects_mutations(&committed, aad, |nonce, aad, sealed| aead.decrypt_committing(nonce, aad, sealed));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_detaches_the_tag() {
+        let aead = ChaCha20Poly1305::new(&key());
+        let sealed = aead.encrypt(&NONCE, &AAD, PLAINTEXT);
+
+        let (ciphertext, tag) = aead.encrypt_detached(&NONCE, &AAD, PLAINTEXT);
+        assert_eq!(&ciphertext[..], &sealed[..PLAINTEXT.len()]);
+        assert_eq!(&tag[..], &sealed[PLAINTEXT.len()..]);
+
+        assert_eq!(aead.decrypt_detached(&NONCE, &AAD, &ciphertext, &tag), Ok(PLAINTEXT.to_vec()));
+        assert_eq!(aead.decrypt_detached(&NONCE, &AAD, &ciphertext, &tag[..8]), Err(Error::InvalidTag));
+        assert_eq!(aead.decrypt_detached(&NONCE, &AAD, &ciphertext[1..], &tag), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_seals_in_place() {
+        let aead = ChaCha20Poly1305::new(&key());
+
+        let mut buffer = [0u8; 114];
+        buffer.copy_from_slice(PLAINTEXT);
+        let mut tag = [0u8; 16];
+        aead.seal_in_place(&NONCE, &AAD, &mut buffer, &mut tag);
+
+        assert_eq!(&buffer[0..4], &[0xd3, 0x1a, 0x8d, 0x34]);
+        assert_eq!(&tag[0..4], &[0x1a, 0xe1, 0x0b, 0x59]);
+
+        let mut tampered = tag;
+        tampered[0] ^= 1;
+        let ciphertext = buffer;
+        assert_eq!(aead.open_in_place(&NONCE, &AAD, &mut buffer, &tampered), Err(Error::InvalidTag));
+        assert_eq!(buffer, ciphertext);
+
+        assert_eq!(aead.open_in_place(&NONCE, &AAD, &mut buffer, &tag), Ok(()));
+        assert_eq!(&buffer[..], PLAINTEXT);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_commits_to_the_key() {
+        let expected: [u8; 62] = [
+            0xe9, 0xe5, 0x9f, 0xa5, 0xc3, 0x1d, 0xc2, 0x4a,
+            0x7e, 0xa4, 0x22, 0x6c, 0x29, 0xce, 0x92, 0xb8,
+            0x4c, 0xf4, 0x70, 0x9e, 0xc7, 0x7e, 0xaa, 0x9b,
+            0x47, 0x1a, 0xeb, 0x8e, 0x1b, 0xde, 0x88, 0x8c,
+            0x33, 0xbb, 0x42, 0xb5, 0x63, 0x3e, 0x5e, 0xd2,
+            0x34, 0xf5, 0x53, 0x65, 0x05, 0x97, 0x33, 0xb4,
+            0xce, 0xa1, 0x9c, 0x61, 0x2b, 0x48, 0xa7, 0xe6,
+            0x2b, 0xe4, 0x8f, 0xa7, 0xa9, 0x65
+        ];
+
+        let aead = ChaCha20Poly1305::new(&key());
+        let sealed = aead.encrypt_committing(&NONCE, &AAD, b"attack at dawn");
+
+        assert_eq!(&sealed[..], &expected[..]);
+        assert_eq!(aead.decrypt_committing(&NONCE, &AAD, &sealed), Ok(b"attack at dawn".to_vec()));
+        assert_eq!(aead.decrypt(&NONCE, &AAD, &sealed), Err(Error::InvalidTag));
+
+        let other = ChaCha20Poly1305::new(&[0u8; 32]);
+        assert_eq!(other.decrypt_committing(&NONCE, &AAD, &sealed), Err(Error::InvalidTag));
+        assert_eq!(aead.decrypt_committing(&NONCE, &AAD, &sealed[..31]), Err(Error::InvalidTag));
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert_eq!(aead.decrypt_committing(&NONCE, &AAD, &tampered), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_builds_counter_nonces() {
+        assert_eq!(counter_nonce(0), [0u8; 12]);
+        assert_eq!(counter_nonce(0x0102030405060708), [0, 0, 0, 0, 8, 7, 6, 5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_seals_under_successive_counters() {
+        let mut sender = CounterNonceAead::new(&key());
+        let receiver = CounterNonceAead::new(&key());
+
+        let (first, sealed_first) = sender.seal(&AAD, b"one").unwrap();
+        let (second, sealed_second) = sender.seal(&AAD, b"one").unwrap();
+
+        assert_eq!((first, second), (0, 1));
+        assert_eq!(sender.counter(), 2);
+        assert_ne!(sealed_first, sealed_second);
+        assert_eq!(sealed_second, ChaCha20Poly1305::new(&key()).encrypt(&counter_nonce(1), &AAD, b"one"));
+
+        assert_eq!(receiver.open(second, &AAD, &sealed_second), Ok(b"one".to_vec()));
+        assert_eq!(receiver.open(first, &AAD, &sealed_second), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn it_expires_when_the_counter_is_used_up() {
+        let mut sender = CounterNonceAead::with_counter(&key(), u64::MAX - 1);
+
+        assert_eq!(sender.seal(b"", b"last").map(|(counter, _)| counter), Ok(u64::MAX - 1));
+        assert_eq!(sender.seal(b"", b"too many"), Err(Error::KeyExpired));
+    }
+}
@@ -0,0 +1,275 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{Endian, LittleEndian};
+use crate::chacha20_poly1305::{counter_nonce, ChaCha20Poly1305, CounterNonceAead};
+use crate::error::Error;
+use crate::poly1305::TAG_LEN;
+
+/// Bytes a datagram adds to its payload: the 8-byte counter in front and the tag at the end
+pub const DATAGRAM_OVERHEAD: usize = 8 + TAG_LEN;
+
+/// Number of counters below the highest one received that `DatagramOpener::new` still accepts
+pub const DEFAULT_REPLAY_WINDOW: u64 = 2048;
+
+/// Sending half of a replay-protected datagram protocol
+///
+/// Each datagram is `counter (8 bytes LE) || ciphertext || tag`, sealed with
+/// ChaCha20-Poly1305 under the nonce `counter_nonce(counter)`. Sending the
+/// counter lets the receiving `DatagramOpener` decrypt datagrams that arrive
+/// out of order or after others were lost, and drop replays.
+pub struct DatagramSealer {
+    aead: CounterNonceAead,
+}
+
+impl DatagramSealer {
+    /// Returns a new instance of DatagramSealer starting at counter 0
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> DatagramSealer {
+        DatagramSealer::with_counter(key, 0)
+    }
+
+    /// Returns a new instance of DatagramSealer resuming at `counter`
+    ///
+    /// `counter` must be one that has not been sent under `key` yet, such as
+    /// a value of `counter()` saved before a restart.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn with_counter(key: &[u8], counter: u64) -> DatagramSealer {
+        DatagramSealer { aead: CounterNonceAead::with_counter(key, counter) }
+    }
+
+    /// Seals `payload` under the next counter and returns the datagram
+    ///
+    /// Returns `Error::KeyExpired` once every counter below `u64::MAX` has been used.
+    pub fn seal(&mut self, aad: &[u8], payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (counter, sealed) = self.aead.seal(aad, payload)?;
+
+        let mut datagram = vec![0u8; 8];
+        LittleEndian::write_u64(counter, &mut datagram);
+        datagram.extend_from_slice(&sealed);
+
+        Ok(datagram)
+    }
+
+    /// Returns the counter the next datagram will use
+    pub fn counter(&self) -> u64 {
+        self.aead.counter()
+    }
+}
+
+/// Receiving half of a replay-protected datagram protocol, see `DatagramSealer`
+///
+/// Keeps a sliding window over the counters below the highest one received:
+/// a datagram whose counter was already accepted, or that lies below the
+/// window, is dropped with `Error::Replayed`. The window only moves when a
+/// datagram authenticates, so forged counters cannot push it forward.
+pub struct DatagramOpener {
+    aead: ChaCha20Poly1305,
+    window: u64,
+    next: u64,
+    seen: Vec<u64>,
+}
+
+impl DatagramOpener {
+    /// Returns a new instance of DatagramOpener with a window of `DEFAULT_REPLAY_WINDOW` counters
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> DatagramOpener {
+        DatagramOpener::with_window(key, DEFAULT_REPLAY_WINDOW)
+    }
+
+    /// Returns a new instance of DatagramOpener accepting counters up to `window` below the highest one received
+    ///
+    /// A wider window tolerates more reordering and costs one bit per counter.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `window` is zero
+    pub fn with_window(key: &[u8], window: u64) -> DatagramOpener {
+        assert!(window > 0);
+
+        let words = (window + 63) / 64;
+        DatagramOpener { aead: ChaCha20Poly1305::new(key), window, next: 0, seen: vec![0u64; words as usize] }
+    }
+
+    /// Verifies and decrypts `datagram` and returns its counter with the payload
+    ///
+    /// Returns `Error::Replayed` if the counter was accepted before or is
+    /// older than the window, and `Error::InvalidTag` if the datagram is
+    /// truncated or has been modified.
+    pub fn open(&mut self, aad: &[u8], datagram: &[u8]) -> Result<(u64, Vec<u8>), Error> {
+        if datagram.len() < DATAGRAM_OVERHEAD {
+            return Err(Error::InvalidTag);
+        }
+
+        let counter = LittleEndian::read_u64(&datagram[0..8]);
+        if counter == u64::MAX {
+            return Err(Error::InvalidTag);
+        }
+        if self.is_replay(counter) {
+            return Err(Error::Replayed);
+        }
+
+        let payload = self.aead.decrypt(&counter_nonce(counter), aad, &datagram[8..])?;
+        self.accept(counter);
+
+        Ok((counter, payload))
+    }
+
+    /// Returns one more than the highest counter accepted so far, or 0 before the first datagram
+    pub fn next_counter(&self) -> u64 {
+        self.next
+    }
+
+    fn is_replay(&self, counter: u64) -> bool {
+        if counter >= self.next {
+            return false;
+        }
+        if self.next - counter > self.window {
+            return true;
+        }
+
+        let (word, bit) = self.position(counter);
+        self.seen[word] & bit != 0
+    }
+
+    /// Marks `counter` as received, sliding the window forward if it is the highest so far
+    fn accept(&mut self, counter: u64) {
+        if counter >= self.next {
+            let bits = self.seen.len() as u64 * 64;
+            if counter - self.next >= bits {
+                for word in self.seen.iter_mut() {
+                    *word = 0;
+                }
+            } else {
+                for skipped in self.next..counter {
+                    let (word, bit) = self.position(skipped);
+                    self.seen[word] &= !bit;
+                }
+            }
+            self.next = counter + 1;
+        }
+
+        let (word, bit) = self.position(counter);
+        self.seen[word] |= bit;
+    }
+
+    /// Returns the word and bit of `counter` in the ring of seen counters
+    fn position(&self, counter: u64) -> (usize, u64) {
+        let index = counter % (self.seen.len() as u64 * 64);
+
+        ((index / 64) as usize, 1 << (index % 64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x5a; 32];
+
+    fn datagrams(count: usize) -> Vec<Vec<u8>> {
+        let mut sealer = DatagramSealer::new(&KEY);
+
+        (0..count).map(|i| sealer.seal(b"hdr", &[i as u8; 3]).unwrap()).collect()
+    }
+
+    #[test]
+    fn it_frames_the_counter_in_front_of_the_aead_output() {
+        let mut sealer = DatagramSealer::with_counter(&KEY, 0x0102);
+        let datagram = sealer.seal(b"hdr", b"payload").unwrap();
+
+        assert_eq!(datagram.len(), 7 + DATAGRAM_OVERHEAD);
+        assert_eq!(&datagram[0..8], &[0x02, 0x01, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&datagram[8..], &ChaCha20Poly1305::new(&KEY).encrypt(&counter_nonce(0x0102), b"hdr", b"payload")[..]);
+        assert_eq!(sealer.counter(), 0x0103);
+    }
+
+    #[test]
+    fn it_opens_datagrams_out_of_order() {
+        let datagrams = datagrams(4);
+        let mut opener = DatagramOpener::new(&KEY);
+
+        assert_eq!(opener.open(b"hdr", &datagrams[2]), Ok((2, vec![2u8; 3])));
+        assert_eq!(opener.open(b"hdr", &datagrams[0]), Ok((0, vec![0u8; 3])));
+        assert_eq!(opener.open(b"hdr", &datagrams[3]), Ok((3, vec![3u8; 3])));
+        assert_eq!(opener.open(b"hdr", &datagrams[1]), Ok((1, vec![1u8; 3])));
+        assert_eq!(opener.next_counter(), 4);
+    }
+
+    #[test]
+    fn it_drops_replays() {
+        let datagrams = datagrams(3);
+        let mut opener = DatagramOpener::new(&KEY);
+
+        assert!(opener.open(b"hdr", &datagrams[2]).is_ok());
+        assert!(opener.open(b"hdr", &datagrams[0]).is_ok());
+        assert_eq!(opener.open(b"hdr", &datagrams[2]), Err(Error::Replayed));
+        assert_eq!(opener.open(b"hdr", &datagrams[0]), Err(Error::Replayed));
+        assert!(opener.open(b"hdr", &datagrams[1]).is_ok());
+        assert_eq!(opener.open(b"hdr", &datagrams[1]), Err(Error::Replayed));
+    }
+
+    #[test]
+    fn it_drops_datagrams_older_than_the_window() {
+        let datagrams = datagrams(12);
+        let mut opener = DatagramOpener::with_window(&KEY, 4);
+
+        assert!(opener.open(b"hdr", &datagrams[10]).is_ok());
+        assert!(opener.open(b"hdr", &datagrams[7]).is_ok());
+        assert_eq!(opener.open(b"hdr", &datagrams[6]), Err(Error::Replayed));
+        assert_eq!(opener.open(b"hdr", &datagrams[0]), Err(Error::Replayed));
+
+        assert!(opener.open(b"hdr", &datagrams[11]).is_ok());
+        assert_eq!(opener.open(b"hdr", &datagrams[7]), Err(Error::Replayed));
+        assert!(opener.open(b"hdr", &datagrams[8]).is_ok());
+    }
+
+    #[test]
+    fn it_forgets_counters_the_window_slid_past() {
+        let mut sealer = DatagramSealer::new(&KEY);
+        let mut opener = DatagramOpener::with_window(&KEY, 64);
+
+        let first = sealer.seal(b"", b"first").unwrap();
+        assert!(opener.open(b"", &first).is_ok());
+
+        let mut sealer = DatagramSealer::with_counter(&KEY, 64);
+        let wrapped = sealer.seal(b"", b"same ring slot as 0").unwrap();
+        assert!(opener.open(b"", &wrapped).is_ok());
+
+        let mut sealer = DatagramSealer::with_counter(&KEY, 1000);
+        assert!(opener.open(b"", &sealer.seal(b"", b"far ahead").unwrap()).is_ok());
+        let mut sealer = DatagramSealer::with_counter(&KEY, 999);
+        assert!(opener.open(b"", &sealer.seal(b"", b"just behind").unwrap()).is_ok());
+        assert_eq!(opener.open(b"", &wrapped), Err(Error::Replayed));
+    }
+
+    #[test]
+    fn it_moves_the_window_only_for_authentic_datagrams() {
+        let datagrams = datagrams(2);
+        let mut opener = DatagramOpener::with_window(&KEY, 4);
+
+        let mut forged = datagrams[1].clone();
+        forged[0] = 100;
+        assert_eq!(opener.open(b"hdr", &forged), Err(Error::InvalidTag));
+        assert_eq!(opener.next_counter(), 0);
+
+        let mut tampered = datagrams[0].clone();
+        tampered[8] ^= 1;
+        assert_eq!(opener.open(b"hdr", &tampered), Err(Error::InvalidTag));
+        assert_eq!(opener.open(b"other", &datagrams[0]), Err(Error::InvalidTag));
+        assert_eq!(opener.open(b"hdr", &datagrams[0][..DATAGRAM_OVERHEAD - 1]), Err(Error::InvalidTag));
+        assert_eq!(opener.open(b"hdr", &datagrams[0]), Ok((0, vec![0u8; 3])));
+    }
+}
@@ -0,0 +1,229 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::byte_manipulation::{constant_time_eq, wipe, Endian, LittleEndian};
+use crate::chacha20::{decrypt, encrypt};
+use crate::error::Error;
+use crate::sha256::{HmacSha256, DIGEST_LEN};
+
+/// Message authentication code usable with `EncryptThenMac`
+pub trait Mac {
+    /// Tag produced by the MAC
+    type Tag: AsRef<[u8]>;
+
+    /// Returns a new MAC instance keyed with `key`
+    fn new(key: &[u8]) -> Self;
+
+    /// Feeds `data` into the MAC
+    fn update(&mut self, data: &[u8]);
+
+    /// Consumes the MAC and returns the tag over everything fed so far
+    fn finalize(self) -> Self::Tag;
+}
+
+/// HMAC-SHA256 (RFC 2104) as a `Mac`, with a 32-byte tag
+///
+/// For deployments that have to use HMAC rather than a one-time MAC. Any key
+/// length works; keys longer than 64 bytes are hashed first.
+pub struct HmacSha256Mac {
+    hmac: HmacSha256,
+}
+
+impl Mac for HmacSha256Mac {
+    type Tag = [u8; DIGEST_LEN];
+
+    fn new(key: &[u8]) -> HmacSha256Mac {
+        HmacSha256Mac { hmac: HmacSha256::new(key) }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.hmac.update(data);
+    }
+
+    fn finalize(self) -> [u8; DIGEST_LEN] {
+        self.hmac.finalize()
+    }
+}
+
+/// ChaCha20 encryption followed by a MAC over the nonce, AAD and ciphertext
+///
+/// The tag covers `nonce || aad || ciphertext || len(aad) || len(ciphertext)`
+/// with both lengths as 64-bit little endian values, so none of the parts can
+/// be shifted into another. The MAC key must be independent from the cipher key.
+pub struct EncryptThenMac<M: Mac> {
+    key: [u8; 32],
+    mac_key: Vec<u8>,
+    mac: PhantomData<M>,
+}
+
+impl<M: Mac> EncryptThenMac<M> {
+    /// Returns a new instance of EncryptThenMac
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8], mac_key: &[u8]) -> EncryptThenMac<M> {
+        assert_eq!(key.len(), 32);
+
+        let mut cipher_key = [0u8; 32];
+        cipher_key.copy_from_slice(key);
+
+        EncryptThenMac {
+            key: cipher_key,
+            mac_key: mac_key.to_vec(),
+            mac: PhantomData,
+        }
+    }
+
+    /// Encrypts `plaintext` and returns the ciphertext with its tag
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    pub fn encrypt(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> (Vec<u8>, M::Tag) {
+        let ciphertext = encrypt(&self.key, nonce, 0, plaintext);
+        let tag = self.compute_tag(nonce, aad, &ciphertext);
+
+        (ciphertext, tag)
+    }
+
+    /// Verifies `tag` and returns the decrypted `ciphertext`
+    ///
+    /// Nothing is decrypted unless the tag matches.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    pub fn decrypt(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>, Error> {
+        let expected = self.compute_tag(nonce, aad, ciphertext);
+        if !constant_time_eq(expected.as_ref(), tag) {
+            return Err(Error::InvalidTag);
+        }
+
+        Ok(decrypt(&self.key, nonce, 0, ciphertext))
+    }
+
+    /// Computes the tag over the nonce, AAD, ciphertext and their lengths
+    fn compute_tag(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> M::Tag {
+        let mut lengths = [0u8; 16];
+        LittleEndian::write_u64(aad.len() as u64, &mut lengths[0..8]);
+        LittleEndian::write_u64(ciphertext.len() as u64, &mut lengths[8..16]);
+
+        let mut mac = M::new(&self.mac_key);
+        mac.update(nonce);
+        mac.update(aad);
+        mac.update(ciphertext);
+        mac.update(&lengths);
+
+        mac.finalize()
+    }
+}
+
+impl<M: Mac> Drop for EncryptThenMac<M> {
+    /// Wipes both keys from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+        wipe(&mut self.mac_key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Keyed checksum standing in for a real MAC
+    struct TestMac {
+        state: u64,
+    }
+
+    impl Mac for TestMac {
+        type Tag = [u8; 8];
+
+        fn new(key: &[u8]) -> TestMac {
+            let mut mac = TestMac { state: 0xcbf29ce484222325 };
+            mac.update(key);
+            mac
+        }
+
+        fn update(&mut self, data: &[u8]) {
+            for byte in data {
+                self.state = (self.state ^ (*byte as u64)).wrapping_mul(0x100000001b3);
+            }
+        }
+
+        fn finalize(self) -> [u8; 8] {
+            self.state.to_le_bytes()
+        }
+    }
+
+    fn cipher() -> EncryptThenMac<TestMac> {
+        EncryptThenMac::new(&[1u8; 32], b"mac key")
+    }
+
+    #[test]
+    fn it_roundtrips() {
+        let plaintext = [0x55u8; 150];
+        let (ciphertext, tag) = cipher().encrypt(&[2u8; 12], b"header", &plaintext);
+
+        assert_ne!(&ciphertext[..], &plaintext[..]);
+        assert_eq!(
+            cipher().decrypt(&[2u8; 12], b"header", &ciphertext, &tag),
+            Ok(plaintext.to_vec())
+        );
+    }
+
+    #[test]
+    fn it_rejects_tampering() {
+        let nonce = [2u8; 12];
+        let (mut ciphertext, tag) = cipher().encrypt(&nonce, b"header", b"attack at dawn");
+
+        assert_eq!(cipher().decrypt(&[3u8; 12], b"header", &ciphertext, &tag), Err(Error::InvalidTag));
+        assert_eq!(cipher().decrypt(&nonce, b"headers", &ciphertext, &tag), Err(Error::InvalidTag));
+        assert_eq!(cipher().decrypt(&nonce, b"header", &ciphertext, &[0u8; 8]), Err(Error::InvalidTag));
+
+        ciphertext[0] ^= 1;
+        assert_eq!(cipher().decrypt(&nonce, b"header", &ciphertext, &tag), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_binds_the_aad_ciphertext_boundary() {
+        let nonce = [2u8; 12];
+        let (ciphertext, tag) = cipher().encrypt(&nonce, b"ab", b"cd");
+
+        let mut shifted = b"b".to_vec();
+        shifted.extend_from_slice(&ciphertext);
+        assert_eq!(cipher().decrypt(&nonce, b"a", &shifted, &tag), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_computes_hmac_sha256() {
+        // RFC 4231, test case 2
+        let expected: [u8; 32] = [
+            0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e,
+            0x6a, 0x04, 0x24, 0x26, 0x08, 0x95, 0x75, 0xc7,
+            0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83,
+            0x9d, 0xec, 0x58, 0xb9, 0x64, 0xec, 0x38, 0x43
+        ];
+
+        let mut mac = HmacSha256Mac::new(b"Jefe");
+        mac.update(b"what do ya ");
+        mac.update(b"want for nothing?");
+
+        assert_eq!(mac.finalize(), expected);
+    }
+
+    #[test]
+    fn it_roundtrips_with_hmac_sha256() {
+        let cipher: EncryptThenMac<HmacSha256Mac> = EncryptThenMac::new(&[1u8; 32], b"Jefe");
+        let (mut ciphertext, tag) = cipher.encrypt(&[2u8; 12], b"header", b"what do ya want for nothing?");
+
+        assert_eq!(tag.len(), 32);
+        assert_eq!(cipher.decrypt(&[2u8; 12], b"header", &ciphertext, &tag), Ok(b"what do ya want for nothing?".to_vec()));
+        assert_eq!(cipher.decrypt(&[2u8; 12], b"header", &ciphertext, &tag[..16]), Err(Error::InvalidTag));
+
+        ciphertext[3] ^= 1;
+        assert_eq!(cipher.decrypt(&[2u8; 12], b"header", &ciphertext, &tag), Err(Error::InvalidTag));
+    }
+}
@@ -0,0 +1,232 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20::ChaCha20;
+use crate::chacha20_poly1305::ChaCha20Poly1305;
+use crate::error::Error;
+#[cfg(feature = "getrandom")]
+use crate::rng::random_bytes;
+use crate::rng::EntropySource;
+use crate::siv::ChaCha20Poly1305Siv;
+
+/// Size of a content key wrapped for one recipient: the SIV tag and the encrypted key
+pub const WRAPPED_KEY_LEN: usize = 16 + 32;
+
+/// Key-encryption key of one recipient of an envelope
+///
+/// Identified in envelopes by its `ChaCha20::fingerprint`, so a recipient
+/// finds its key slot without trying every one.
+pub struct RecipientKey {
+    key: [u8; 32],
+    fingerprint: [u8; 8],
+}
+
+impl RecipientKey {
+    /// Returns a new instance of RecipientKey
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> RecipientKey {
+        let mut recipient = RecipientKey { key: [0u8; 32], fingerprint: ChaCha20::fingerprint(key) };
+        recipient.key.copy_from_slice(key);
+
+        recipient
+    }
+
+    /// Returns the fingerprint naming this key in key slots
+    pub fn fingerprint(&self) -> [u8; 8] {
+        self.fingerprint
+    }
+}
+
+impl Drop for RecipientKey {
+    /// Wipes the key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+/// The content key wrapped for a single recipient
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySlot {
+    pub fingerprint: [u8; 8],
+    pub wrapped_key: [u8; WRAPPED_KEY_LEN],
+}
+
+/// A message encrypted once for several recipients
+///
+/// `ciphertext` is the message sealed with the key-committing mode of
+/// ChaCha20-Poly1305 under a random content key drawn for this envelope
+/// only, with a zero nonce. Each key slot holds that content key encrypted
+/// with `ChaCha20Poly1305Siv` under one recipient's key, with a zero nonce
+/// and the slot fingerprint as AAD; the SIV mode keeps wrapping safe although
+/// recipient keys are reused across envelopes. The commitment stops a sender
+/// from wrapping different content keys for different recipients so that
+/// one ciphertext opens to different messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub slots: Vec<KeySlot>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Verifies and decrypts the envelope with the key slot belonging to `recipient`
+    ///
+    /// Returns `Error::NoKeySlot` if the envelope was not sealed for this
+    /// key, and `Error::InvalidTag` if the slot or the ciphertext has been
+    /// modified.
+    pub fn open(&self, recipient: &RecipientKey) -> Result<Vec<u8>, Error> {
+        let slot = self.slots.iter().find(|slot| slot.fingerprint == recipient.fingerprint).ok_or(Error::NoKeySlot)?;
+
+        let siv = ChaCha20Poly1305Siv::new(&recipient.key);
+        let mut content_key = siv.decrypt(&[0u8; 12], &slot.fingerprint, &slot.wrapped_key)?;
+        let opened = ChaCha20Poly1305::new(&content_key).decrypt_committing(&[0u8; 12], b"", &self.ciphertext);
+        wipe(&mut content_key);
+
+        opened
+    }
+}
+
+/// Encrypts `plaintext` once and wraps a fresh content key for every recipient
+///
+/// The content key is drawn from `random_bytes` and wiped once the envelope
+/// is sealed.
+#[cfg(feature = "getrandom")]
+pub fn seal_for_recipients(recipients: &[RecipientKey], plaintext: &[u8]) -> Envelope {
+    let mut content_key = random_bytes(32);
+    let envelope = seal_with_content_key(recipients, &content_key, plaintext);
+    wipe(&mut content_key);
+
+    envelope
+}
+
+/// Encrypts `plaintext` once and wraps a content key drawn from `source` for every recipient
+///
+/// Same as `seal_for_recipients` for targets without the `getrandom`
+/// feature. Each call draws a new seed from `source` as the content key.
+pub fn seal_for_recipients_from<S: EntropySource>(recipients: &[RecipientKey], source: &mut S, plaintext: &[u8]) -> Envelope {
+    let mut content_key = [0u8; 32];
+    source.fill_seed(&mut content_key);
+    let envelope = seal_with_content_key(recipients, &content_key, plaintext);
+    wipe(&mut content_key);
+
+    envelope
+}
+
+/// Seals `plaintext` under `content_key`, which must not have sealed anything before
+fn seal_with_content_key(recipients: &[RecipientKey], content_key: &[u8], plaintext: &[u8]) -> Envelope {
+    let ciphertext = ChaCha20Poly1305::new(content_key).encrypt_committing(&[0u8; 12], b"", plaintext);
+
+    let slots = recipients
+        .iter()
+        .map(|recipient| {
+            let mut wrapped = ChaCha20Poly1305Siv::new(&recipient.key).encrypt(&[0u8; 12], &recipient.fingerprint, content_key);
+
+            let mut slot = KeySlot { fingerprint: recipient.fingerprint, wrapped_key: [0u8; WRAPPED_KEY_LEN] };
+            slot.wrapped_key.copy_from_slice(&wrapped);
+            wipe(&mut wrapped);
+
+            slot
+        })
+        .collect();
+
+    Envelope { slots, ciphertext }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chacha20_poly1305::COMMITMENT_LEN;
+    use crate::poly1305::TAG_LEN;
+
+    /// Hands out content keys filled with 1, then 2, and so on
+    struct CountingSource(u8);
+
+    impl EntropySource for CountingSource {
+        fn fill_seed(&mut self, seed: &mut [u8; 32]) {
+            self.0 += 1;
+            *seed = [self.0; 32];
+        }
+    }
+
+    fn recipients() -> [RecipientKey; 3] {
+        [RecipientKey::new(&[1u8; 32]), RecipientKey::new(&[2u8; 32]), RecipientKey::new(&[3u8; 32])]
+    }
+
+    fn sealed(recipients: &[RecipientKey]) -> Envelope {
+        seal_for_recipients_from(recipients, &mut CountingSource(0), b"quarterly numbers")
+    }
+
+    #[test]
+    fn it_opens_for_every_recipient() {
+        let recipients = recipients();
+        let envelope = sealed(&recipients);
+
+        assert_eq!(envelope.slots.len(), 3);
+        assert_eq!(envelope.ciphertext.len(), COMMITMENT_LEN + 17 + TAG_LEN);
+        for recipient in recipients.iter() {
+            assert_eq!(envelope.open(recipient), Ok(b"quarterly numbers".to_vec()));
+        }
+    }
+
+    #[test]
+    fn it_seals_with_the_committing_mode() {
+        let envelope = sealed(&recipients());
+        let expected = ChaCha20Poly1305::new(&[1u8; 32]).encrypt_committing(&[0u8; 12], b"", b"quarterly numbers");
+
+        assert_eq!(envelope.ciphertext, expected);
+    }
+
+    #[test]
+    fn it_draws_a_new_content_key_per_envelope() {
+        let recipients = recipients();
+        let mut source = CountingSource(0);
+        let first = seal_for_recipients_from(&recipients, &mut source, b"quarterly numbers");
+        let second = seal_for_recipients_from(&recipients, &mut source, b"quarterly numbers");
+
+        assert_ne!(first.ciphertext, second.ciphertext);
+        assert_eq!(second.open(&recipients[0]), Ok(b"quarterly numbers".to_vec()));
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn it_seals_under_random_content_keys() {
+        let recipients = recipients();
+        let first = seal_for_recipients(&recipients, b"quarterly numbers");
+        let second = seal_for_recipients(&recipients, b"quarterly numbers");
+
+        assert_ne!(first.ciphertext, second.ciphertext);
+        assert_eq!(first.open(&recipients[1]), Ok(b"quarterly numbers".to_vec()));
+        assert_eq!(second.open(&recipients[2]), Ok(b"quarterly numbers".to_vec()));
+    }
+
+    #[test]
+    fn it_rejects_other_keys() {
+        let envelope = sealed(&recipients());
+
+        assert_eq!(envelope.open(&RecipientKey::new(&[4u8; 32])), Err(Error::NoKeySlot));
+    }
+
+    #[test]
+    fn it_rejects_modified_envelopes() {
+        let recipients = recipients();
+        let envelope = sealed(&recipients);
+
+        let mut modified = envelope.clone();
+        modified.slots[1].wrapped_key[20] ^= 1;
+        assert_eq!(modified.open(&recipients[1]), Err(Error::InvalidTag));
+        assert_eq!(modified.open(&recipients[0]), Ok(b"quarterly numbers".to_vec()));
+
+        let mut modified = envelope.clone();
+        modified.slots.swap(0, 1);
+        modified.slots[0].fingerprint = recipients[0].fingerprint();
+        assert_eq!(modified.open(&recipients[0]), Err(Error::InvalidTag));
+
+        let mut modified = envelope;
+        modified.ciphertext[COMMITMENT_LEN] ^= 1;
+        assert_eq!(modified.open(&recipients[2]), Err(Error::InvalidTag));
+    }
+}
@@ -0,0 +1,47 @@
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+/// Errors returned by the fallible operations of this crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A public key was rejected because it cannot give a secret shared key
+    InvalidPublicKey,
+    /// The authentication tag did not match the message
+    InvalidTag,
+    /// The key reached its lifetime or usage limit and must be rotated
+    KeyExpired,
+    /// The 32-bit block counter has no keystream left for this key and nonce
+    KeystreamExhausted,
+    /// An output buffer does not match the length of its input
+    LengthMismatch,
+    /// None of the key slots of an envelope belongs to the key
+    NoKeySlot,
+    /// The message was received before, or is too old to tell
+    Replayed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPublicKey => write!(f, "public key is of small order"),
+            Error::InvalidTag => write!(f, "authentication tag mismatch"),
+            Error::KeyExpired => write!(f, "key lifetime or usage limit reached"),
+            Error::KeystreamExhausted => write!(f, "keystream for this key and nonce is used up"),
+            Error::LengthMismatch => write!(f, "output length does not match input length"),
+            Error::NoKeySlot => write!(f, "no key slot for this key"),
+            Error::Replayed => write!(f, "message replayed or older than the replay window"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    /// Wraps the error as `InvalidData`, for the `Read` and `Write` adapters
+    fn from(error: Error) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
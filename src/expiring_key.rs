@@ -0,0 +1,157 @@
+#![forbid(unsafe_code)]
+
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20::encrypt;
+use crate::error::Error;
+
+/// Limits after which an `ExpiringKey` refuses to be used
+///
+/// A `None` field means that dimension is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyLimits {
+    pub lifetime: Option<Duration>,
+    pub max_bytes: Option<u64>,
+    pub max_messages: Option<u64>,
+}
+
+/// Usage recorded by an `ExpiringKey`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUsage {
+    pub age: Duration,
+    pub bytes: u64,
+    pub messages: u64,
+}
+
+/// Key that stops encrypting once it is too old or has processed too much data
+///
+/// Every call to `encrypt` counts as one message. A call that would go over a
+/// limit fails as a whole with `Error::KeyExpired` and is not counted.
+pub struct ExpiringKey {
+    key: [u8; 32],
+    limits: KeyLimits,
+    created: Instant,
+    bytes: u64,
+    messages: u64,
+}
+
+impl ExpiringKey {
+    /// Returns a new key with the given limits, starting its lifetime now
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8], limits: KeyLimits) -> ExpiringKey {
+        assert_eq!(key.len(), 32);
+
+        let mut owned_key = [0u8; 32];
+        owned_key.copy_from_slice(key);
+
+        ExpiringKey {
+            key: owned_key,
+            limits,
+            created: Instant::now(),
+            bytes: 0,
+            messages: 0,
+        }
+    }
+
+    /// Encrypts or decrypts `data` from block `counter` if the key is still within its limits
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    pub fn encrypt(&mut self, nonce: &[u8], counter: u32, data: &[u8]) -> Result<Vec<u8>, Error> {
+        if !self.allows(data.len() as u64) {
+            return Err(Error::KeyExpired);
+        }
+
+        self.bytes += data.len() as u64;
+        self.messages += 1;
+
+        Ok(encrypt(&self.key, nonce, counter, data))
+    }
+
+    /// Returns whether the key can still process a message of `len` bytes
+    pub fn allows(&self, len: u64) -> bool {
+        let within_lifetime = self.limits.lifetime.map_or(true, |lifetime| self.created.elapsed() < lifetime);
+        let within_bytes = self.limits.max_bytes.map_or(true, |max| self.bytes.saturating_add(len) <= max);
+        let within_messages = self.limits.max_messages.map_or(true, |max| self.messages < max);
+
+        within_lifetime && within_bytes && within_messages
+    }
+
+    /// Returns whether the key refuses to process any further data
+    pub fn is_expired(&self) -> bool {
+        !self.allows(1)
+    }
+
+    /// Returns the usage recorded so far
+    pub fn usage(&self) -> KeyUsage {
+        KeyUsage {
+            age: self.created.elapsed(),
+            bytes: self.bytes,
+            messages: self.messages,
+        }
+    }
+
+    /// Returns the configured limits
+    pub fn limits(&self) -> KeyLimits {
+        self.limits
+    }
+}
+
+impl Drop for ExpiringKey {
+    /// Wipes the key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_encrypts_within_limits() {
+        let mut key = ExpiringKey::new(&[1u8; 32], KeyLimits::default());
+        let ciphertext = key.encrypt(&[2u8; 12], 0, b"hello").unwrap();
+
+        assert_eq!(ciphertext, encrypt(&[1u8; 32], &[2u8; 12], 0, b"hello"));
+        assert_eq!(key.usage().bytes, 5);
+        assert_eq!(key.usage().messages, 1);
+    }
+
+    #[test]
+    fn it_enforces_the_byte_budget() {
+        let limits = KeyLimits { max_bytes: Some(10), ..KeyLimits::default() };
+        let mut key = ExpiringKey::new(&[1u8; 32], limits);
+
+        assert!(key.encrypt(&[2u8; 12], 0, &[0u8; 6]).is_ok());
+        assert_eq!(key.encrypt(&[3u8; 12], 0, &[0u8; 5]), Err(Error::KeyExpired));
+        assert_eq!(key.usage().bytes, 6);
+        assert!(key.encrypt(&[3u8; 12], 0, &[0u8; 4]).is_ok());
+        assert!(key.is_expired());
+    }
+
+    #[test]
+    fn it_enforces_the_message_budget() {
+        let limits = KeyLimits { max_messages: Some(2), ..KeyLimits::default() };
+        let mut key = ExpiringKey::new(&[1u8; 32], limits);
+
+        assert!(key.encrypt(&[2u8; 12], 0, b"a").is_ok());
+        assert!(key.encrypt(&[3u8; 12], 0, b"b").is_ok());
+        assert_eq!(key.encrypt(&[4u8; 12], 0, b"c"), Err(Error::KeyExpired));
+    }
+
+    #[test]
+    fn it_enforces_the_lifetime() {
+        let expired = ExpiringKey::new(&[1u8; 32], KeyLimits { lifetime: Some(Duration::from_secs(0)), ..KeyLimits::default() });
+        let fresh = ExpiringKey::new(&[1u8; 32], KeyLimits { lifetime: Some(Duration::from_secs(3600)), ..KeyLimits::default() });
+
+        assert!(expired.is_expired());
+        assert!(!fresh.is_expired());
+    }
+}
@@ -0,0 +1,241 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20_poly1305::ChaCha20Poly1305;
+use crate::error::Error;
+use crate::poly1305::TAG_LEN;
+#[cfg(feature = "getrandom")]
+use crate::rng::random_bytes;
+use crate::rng::EntropySource;
+use crate::sha256::{hkdf_expand, hkdf_extract, HmacSha256, DIGEST_LEN};
+
+/// Bytes a sealed field adds to its value: the 12-byte nonce in front and the tag at the end
+pub const FIELD_OVERHEAD: usize = 12 + TAG_LEN;
+
+/// HKDF salt of the master key, separating field keys from other uses of it
+const FIELD_SALT: &[u8] = b"chacha20 field";
+
+/// HKDF info prefix of the key sealing a column's values
+const SEAL_LABEL: &[u8; 4] = b"seal";
+
+/// HKDF info prefix of the key computing a column's blind indexes
+const INDEX_LABEL: &[u8; 4] = b"indx";
+
+/// Master key for encrypting individual database fields
+///
+/// Every column gets keys of its own, derived with HKDF-SHA256 from the
+/// master key and the column identifier, so a value sealed for one column
+/// does not open in another and columns can be handed to different services
+/// without sharing the master key.
+pub struct FieldKey {
+    prk: [u8; DIGEST_LEN],
+}
+
+impl FieldKey {
+    /// Returns a new instance of FieldKey
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `master_key` is not of size 32
+    pub fn new(master_key: &[u8]) -> FieldKey {
+        assert_eq!(master_key.len(), 32);
+
+        FieldKey { prk: hkdf_extract(FIELD_SALT, &[master_key]) }
+    }
+
+    /// Derives the keys of the column named `column`, such as `b"users.email"`
+    pub fn column(&self, column: &[u8]) -> ColumnKey {
+        let mut column_key = ColumnKey { seal_key: [0u8; 32], index_key: [0u8; 32] };
+        hkdf_expand(&self.prk, &[SEAL_LABEL, column], &mut column_key.seal_key);
+        hkdf_expand(&self.prk, &[INDEX_LABEL, column], &mut column_key.index_key);
+
+        column_key
+    }
+}
+
+impl Drop for FieldKey {
+    /// Wipes the extracted master key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.prk);
+    }
+}
+
+/// Keys of a single column, see `FieldKey::column`
+///
+/// Values are sealed with ChaCha20-Poly1305 under a random nonce and stored
+/// as `nonce || ciphertext || tag`, `FIELD_OVERHEAD` bytes longer than the
+/// value. Random nonces keep equal values from showing up as equal
+/// ciphertexts; stay well below 2^32 values sealed per column, after which
+/// nonce collisions become likely. `aad` is usually the row's primary key,
+/// so a sealed value cannot be copied into another row.
+pub struct ColumnKey {
+    seal_key: [u8; 32],
+    index_key: [u8; 32],
+}
+
+impl ColumnKey {
+    /// Seals `value` under a nonce from `random_bytes`
+    #[cfg(feature = "getrandom")]
+    pub fn seal(&self, aad: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&random_bytes(12));
+
+        self.seal_with_nonce(&nonce, aad, value)
+    }
+
+    /// Seals `value` under a nonce drawn from `source`
+    ///
+    /// Same as `seal` for targets without the `getrandom` feature. Each call
+    /// draws a new seed from `source` and uses its first 12 bytes as the nonce.
+    pub fn seal_from<S: EntropySource>(&self, source: &mut S, aad: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut seed = [0u8; 32];
+        source.fill_seed(&mut seed);
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&seed[..12]);
+        wipe(&mut seed);
+
+        self.seal_with_nonce(&nonce, aad, value)
+    }
+
+    /// Verifies and decrypts a field produced by `seal` or `seal_from` with the same `aad`
+    ///
+    /// Returns `Error::InvalidTag` if the field is too short, has been
+    /// modified, or was sealed for another column or row.
+    pub fn open(&self, aad: &[u8], field: &[u8]) -> Result<Vec<u8>, Error> {
+        if field.len() < FIELD_OVERHEAD {
+            return Err(Error::InvalidTag);
+        }
+
+        let (nonce, sealed) = field.split_at(12);
+        ChaCha20Poly1305::new(&self.seal_key).decrypt(nonce, aad, sealed)
+    }
+
+    /// Returns the blind index of `value`, HMAC-SHA256 under the column's index key
+    ///
+    /// Equal values get equal indexes, so storing the index next to the
+    /// sealed field allows exact-match lookups without decrypting. This
+    /// reveals which rows share a value; truncate the index to let lookups
+    /// return a few false positives that hide the exact matches.
+    pub fn blind_index(&self, value: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut mac = HmacSha256::new(&self.index_key);
+        mac.update(value);
+
+        mac.finalize()
+    }
+
+    fn seal_with_nonce(&self, nonce: &[u8; 12], aad: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut field = Vec::with_capacity(value.len() + FIELD_OVERHEAD);
+        field.extend_from_slice(nonce);
+        field.extend_from_slice(&ChaCha20Poly1305::new(&self.seal_key).encrypt(nonce, aad, value));
+
+        field
+    }
+}
+
+impl Drop for ColumnKey {
+    /// Wipes the column keys from memory
+    fn drop(&mut self) {
+        wipe(&mut self.seal_key);
+        wipe(&mut self.index_key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Hands out seeds filled with 1, then 2, and so on
+    struct CountingSource(u8);
+
+    impl EntropySource for CountingSource {
+        fn fill_seed(&mut self, seed: &mut [u8; 32]) {
+            self.0 += 1;
+            *seed = [self.0; 32];
+        }
+    }
+
+    fn email() -> ColumnKey {
+        FieldKey::new(&[7u8; 32]).column(b"users.email")
+    }
+
+    #[test]
+    fn it_round_trips_a_field() {
+        let column = email();
+        let field = column.seal_from(&mut CountingSource(0), b"user 42", b"alice@example.com");
+
+        assert_eq!(field.len(), 17 + FIELD_OVERHEAD);
+        assert_eq!(&field[..12], &[1u8; 12]);
+        assert_eq!(column.open(b"user 42", &field), Ok(b"alice@example.com".to_vec()));
+    }
+
+    #[test]
+    fn it_frames_the_nonce_in_front_of_the_aead_output() {
+        let column = email();
+        let field = column.seal_from(&mut CountingSource(0), b"user 42", b"alice@example.com");
+
+        let expected = ChaCha20Poly1305::new(&column.seal_key).encrypt(&[1u8; 12], b"user 42", b"alice@example.com");
+        assert_eq!(&field[12..], &expected[..]);
+    }
+
+    #[test]
+    fn it_derives_separate_keys_per_column() {
+        let master = FieldKey::new(&[7u8; 32]);
+        let email = master.column(b"users.email");
+        let phone = master.column(b"users.phone");
+
+        assert_ne!(email.seal_key, phone.seal_key);
+        assert_ne!(email.seal_key, email.index_key);
+        assert_eq!(FieldKey::new(&[7u8; 32]).column(b"users.email").seal_key, email.seal_key);
+        assert_ne!(FieldKey::new(&[8u8; 32]).column(b"users.email").seal_key, email.seal_key);
+
+        let field = email.seal_from(&mut CountingSource(0), b"user 42", b"alice@example.com");
+        assert_eq!(phone.open(b"user 42", &field), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_rejects_fields_moved_modified_or_truncated() {
+        let column = email();
+        let mut field = column.seal_from(&mut CountingSource(0), b"user 42", b"alice@example.com");
+
+        assert_eq!(column.open(b"user 43", &field), Err(Error::InvalidTag));
+        assert_eq!(column.open(b"user 42", &field[..FIELD_OVERHEAD - 1]), Err(Error::InvalidTag));
+        assert_eq!(column.open(b"user 42", b""), Err(Error::InvalidTag));
+
+        field[0] ^= 1;
+        assert_eq!(column.open(b"user 42", &field), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_seals_equal_values_differently() {
+        let column = email();
+        let mut source = CountingSource(0);
+        let first = column.seal_from(&mut source, b"", b"alice@example.com");
+        let second = column.seal_from(&mut source, b"", b"alice@example.com");
+
+        assert_ne!(first, second);
+        assert_eq!(column.open(b"", &second), Ok(b"alice@example.com".to_vec()));
+    }
+
+    #[test]
+    fn it_computes_blind_indexes_per_column() {
+        let master = FieldKey::new(&[7u8; 32]);
+        let email = master.column(b"users.email");
+
+        assert_eq!(email.blind_index(b"alice@example.com"), master.column(b"users.email").blind_index(b"alice@example.com"));
+        assert_ne!(email.blind_index(b"alice@example.com"), email.blind_index(b"bob@example.com"));
+        assert_ne!(email.blind_index(b"alice@example.com"), master.column(b"users.phone").blind_index(b"alice@example.com"));
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn it_seals_under_random_nonces() {
+        let column = email();
+        let first = column.seal(b"user 42", b"alice@example.com");
+        let second = column.seal(b"user 42", b"alice@example.com");
+
+        assert_ne!(first[..12], second[..12]);
+        assert_eq!(column.open(b"user 42", &first), Ok(b"alice@example.com".to_vec()));
+    }
+}
@@ -0,0 +1,459 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{constant_time_eq, wipe, BigEndian, Endian};
+use crate::chacha20_poly1305::ChaCha20Poly1305;
+use crate::error::Error;
+#[cfg(feature = "getrandom")]
+use crate::rng::random_bytes;
+use crate::sha256::{hkdf_expand, hkdf_extract, DIGEST_LEN};
+use crate::x25519::{x25519, x25519_base};
+
+/// Size of the encapsulated key sent along with the messages: an X25519 public key
+pub const ENC_LEN: usize = 32;
+
+/// Suite id of DHKEM(X25519, HKDF-SHA256) in the KEM key schedule
+const KEM_SUITE: &[u8] = b"KEM\x00\x20";
+
+/// Suite id of DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and ChaCha20Poly1305
+const HPKE_SUITE: &[u8] = b"HPKE\x00\x20\x00\x01\x00\x03";
+
+/// Version label of every HPKE derivation
+const VERSION: &[u8] = b"HPKE-v1";
+
+/// Identifier of the base mode in the key schedule context
+const MODE_BASE: u8 = 0;
+
+/// LabeledExtract of RFC 9180
+fn labeled_extract(salt: &[u8], suite: &[u8], label: &[u8], ikm: &[u8]) -> [u8; DIGEST_LEN] {
+    hkdf_extract(salt, &[VERSION, suite, label, ikm])
+}
+
+/// LabeledExpand of RFC 9180, filling all of `out`
+fn labeled_expand(prk: &[u8], suite: &[u8], label: &[u8], info: &[u8], out: &mut [u8]) {
+    assert!(out.len() <= 255 * DIGEST_LEN);
+
+    let length = (out.len() as u16).to_be_bytes();
+    hkdf_expand(prk, &[&length, VERSION, suite, label, info], out);
+}
+
+/// X25519 of a secret and a public key, rejecting the all-zero shared secret
+fn diffie_hellman(secret_key: &[u8], public_key: &[u8]) -> Result<[u8; 32], Error> {
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(secret_key);
+    let mut point = [0u8; 32];
+    point.copy_from_slice(public_key);
+
+    let shared = x25519(&scalar, &point);
+    wipe(&mut scalar);
+
+    if constant_time_eq(&shared, &[0u8; 32]) {
+        return Err(Error::InvalidPublicKey);
+    }
+
+    Ok(shared)
+}
+
+/// ExtractAndExpand of DHKEM: the KEM shared secret of a DH output and both public keys
+fn kem_shared_secret(dh: &[u8; 32], enc: &[u8], recipient_public: &[u8]) -> [u8; 32] {
+    let mut kem_context = [0u8; 64];
+    kem_context[0..32].copy_from_slice(enc);
+    kem_context[32..64].copy_from_slice(recipient_public);
+
+    let mut eae_prk = labeled_extract(b"", KEM_SUITE, b"eae_prk", dh);
+    let mut shared_secret = [0u8; 32];
+    labeled_expand(&eae_prk, KEM_SUITE, b"shared_secret", &kem_context, &mut shared_secret);
+    wipe(&mut eae_prk);
+
+    shared_secret
+}
+
+/// Derives an X25519 key pair from input keying material, as DeriveKeyPair of RFC 9180
+///
+/// Returns the secret key and the public key. `ikm` should hold at least 32
+/// bytes from a secure random source; the same `ikm` always gives the same
+/// pair.
+pub fn derive_key_pair(ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut dkp_prk = labeled_extract(b"", KEM_SUITE, b"dkp_prk", ikm);
+    let mut secret_key = [0u8; 32];
+    labeled_expand(&dkp_prk, KEM_SUITE, b"sk", b"", &mut secret_key);
+    wipe(&mut dkp_prk);
+
+    let public_key = x25519_base(&secret_key);
+
+    (secret_key, public_key)
+}
+
+/// Keys of an HPKE context and its message sequence number
+struct Context {
+    aead: ChaCha20Poly1305,
+    base_nonce: [u8; 12],
+    exporter_secret: [u8; 32],
+    sequence: u64,
+}
+
+impl Context {
+    /// KeyScheduleS and KeyScheduleR of RFC 9180 in base mode
+    fn new(shared_secret: &[u8; 32], info: &[u8]) -> Context {
+        let psk_id_hash = labeled_extract(b"", HPKE_SUITE, b"psk_id_hash", b"");
+        let info_hash = labeled_extract(b"", HPKE_SUITE, b"info_hash", info);
+
+        let mut key_schedule_context = [0u8; 1 + 2 * DIGEST_LEN];
+        key_schedule_context[0] = MODE_BASE;
+        key_schedule_context[1..33].copy_from_slice(&psk_id_hash);
+        key_schedule_context[33..65].copy_from_slice(&info_hash);
+
+        let mut secret = labeled_extract(shared_secret, HPKE_SUITE, b"secret", b"");
+        let mut key = [0u8; 32];
+        labeled_expand(&secret, HPKE_SUITE, b"key", &key_schedule_context, &mut key);
+
+        let mut context = Context {
+            aead: ChaCha20Poly1305::new(&key),
+            base_nonce: [0u8; 12],
+            exporter_secret: [0u8; 32],
+            sequence: 0,
+        };
+        labeled_expand(&secret, HPKE_SUITE, b"base_nonce", &key_schedule_context, &mut context.base_nonce);
+        labeled_expand(&secret, HPKE_SUITE, b"exp", &key_schedule_context, &mut context.exporter_secret);
+        wipe(&mut secret);
+        wipe(&mut key);
+
+        context
+    }
+
+    /// Nonce of the current message: the base nonce XORed with the sequence number
+    fn nonce(&self) -> Result<[u8; 12], Error> {
+        if self.sequence == u64::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        let mut sequence = [0u8; 12];
+        BigEndian::write_u64(self.sequence, &mut sequence[4..12]);
+
+        let mut nonce = self.base_nonce;
+        for (byte, sequence) in nonce.iter_mut().zip(sequence.iter()) {
+            *byte ^= sequence;
+        }
+
+        Ok(nonce)
+    }
+
+    /// Secret export of RFC 9180, filling all of `out`
+    fn export(&self, exporter_context: &[u8], out: &mut [u8]) {
+        labeled_expand(&self.exporter_secret, HPKE_SUITE, b"sec", exporter_context, out);
+    }
+}
+
+impl Drop for Context {
+    /// Wipes the nonce and the exporter secret from memory
+    fn drop(&mut self) {
+        wipe(&mut self.base_nonce);
+        wipe(&mut self.exporter_secret);
+    }
+}
+
+/// Sending half of an HPKE context in base mode
+///
+/// HPKE (RFC 9180) with DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and
+/// ChaCha20Poly1305. Messages are sealed under nonces derived from their
+/// index, so the receiver must open them in the order they were sealed.
+pub struct SenderContext {
+    context: Context,
+}
+
+impl SenderContext {
+    /// Encrypts `plaintext` as the next message and returns the ciphertext followed by the tag
+    ///
+    /// Returns `Error::KeyExpired` once `u64::MAX` messages have been sealed.
+    pub fn seal(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let sealed = self.context.aead.encrypt(&self.context.nonce()?, aad, plaintext);
+        self.context.sequence += 1;
+
+        Ok(sealed)
+    }
+
+    /// Fills `out` with a secret derived from the context and `exporter_context`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `out` is longer than 255 * 32 bytes
+    pub fn export(&self, exporter_context: &[u8], out: &mut [u8]) {
+        self.context.export(exporter_context, out);
+    }
+}
+
+/// Receiving half of an HPKE context in base mode, see `SenderContext`
+pub struct ReceiverContext {
+    context: Context,
+}
+
+impl ReceiverContext {
+    /// Verifies and decrypts the next message
+    ///
+    /// Returns `Error::InvalidTag` if the message is not the next one of the
+    /// context or has been modified, and `Error::KeyExpired` past `u64::MAX`
+    /// messages. The sequence number only advances on success.
+    pub fn open(&mut self, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let plaintext = self.context.aead.decrypt(&self.context.nonce()?, aad, ciphertext)?;
+        self.context.sequence += 1;
+
+        Ok(plaintext)
+    }
+
+    /// Fills `out` with a secret derived from the context and `exporter_context`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `out` is longer than 255 * 32 bytes
+    pub fn export(&self, exporter_context: &[u8], out: &mut [u8]) {
+        self.context.export(exporter_context, out);
+    }
+}
+
+/// Sets up a sender context for the owner of `recipient_public`, as SetupBaseS of RFC 9180
+///
+/// Returns the encapsulated key, to be sent to the recipient, and the
+/// context. `ephemeral_secret` must be 32 fresh bytes from a secure random
+/// source for every context, or the output of `derive_key_pair` on such
+/// bytes. With the `getrandom` feature, `setup_base_sender_random` draws it
+/// itself.
+///
+/// Returns `Error::InvalidPublicKey` if `recipient_public` is of small order.
+///
+/// # Panics
+///
+/// The function will panic if `recipient_public` is not of size 32
+/// The function will panic if `ephemeral_secret` is not of size 32
+pub fn setup_base_sender(recipient_public: &[u8], ephemeral_secret: &[u8], info: &[u8]) -> Result<([u8; ENC_LEN], SenderContext), Error> {
+    assert_eq!(recipient_public.len(), 32);
+    assert_eq!(ephemeral_secret.len(), 32);
+
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(ephemeral_secret);
+    let enc = x25519_base(&scalar);
+    wipe(&mut scalar);
+
+    let mut dh = diffie_hellman(ephemeral_secret, recipient_public)?;
+    let mut shared_secret = kem_shared_secret(&dh, &enc, recipient_public);
+    let context = Context::new(&shared_secret, info);
+    wipe(&mut dh);
+    wipe(&mut shared_secret);
+
+    Ok((enc, SenderContext { context }))
+}
+
+/// Sets up a sender context for the owner of `recipient_public` under a fresh ephemeral key
+///
+/// Same as `setup_base_sender` with the ephemeral secret drawn from
+/// `random_bytes` and wiped before returning.
+///
+/// Returns `Error::InvalidPublicKey` if `recipient_public` is of small order.
+///
+/// # Panics
+///
+/// The function will panic if `recipient_public` is not of size 32
+#[cfg(feature = "getrandom")]
+pub fn setup_base_sender_random(recipient_public: &[u8], info: &[u8]) -> Result<([u8; ENC_LEN], SenderContext), Error> {
+    let mut ephemeral_secret = random_bytes(32);
+    let sender = setup_base_sender(recipient_public, &ephemeral_secret, info);
+    wipe(&mut ephemeral_secret);
+
+    sender
+}
+
+/// Sets up the receiver context matching `enc`, as SetupBaseR of RFC 9180
+///
+/// Returns `Error::InvalidPublicKey` if `enc` is of small order. A wrong
+/// `enc`, key or `info` is only detected when opening messages.
+///
+/// # Panics
+///
+/// The function will panic if `enc` is not of size 32
+/// The function will panic if `recipient_secret` is not of size 32
+pub fn setup_base_receiver(enc: &[u8], recipient_secret: &[u8], info: &[u8]) -> Result<ReceiverContext, Error> {
+    assert_eq!(enc.len(), ENC_LEN);
+    assert_eq!(recipient_secret.len(), 32);
+
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(recipient_secret);
+    let recipient_public = x25519_base(&scalar);
+    wipe(&mut scalar);
+
+    let mut dh = diffie_hellman(recipient_secret, enc)?;
+    let mut shared_secret = kem_shared_secret(&dh, enc, &recipient_public);
+    let context = Context::new(&shared_secret, info);
+    wipe(&mut dh);
+    wipe(&mut shared_secret);
+
+    Ok(ReceiverContext { context })
+}
+
+/// Encrypts a single message to the owner of `recipient_public`, see `setup_base_sender`
+///
+/// Returns the encapsulated key and the ciphertext followed by the tag.
+///
+/// # Panics
+///
+/// The function will panic if `recipient_public` is not of size 32
+/// The function will panic if `ephemeral_secret` is not of size 32
+pub fn seal(recipient_public: &[u8], ephemeral_secret: &[u8], info: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<([u8; ENC_LEN], Vec<u8>), Error> {
+    let (enc, mut context) = setup_base_sender(recipient_public, ephemeral_secret, info)?;
+    let sealed = context.seal(aad, plaintext)?;
+
+    Ok((enc, sealed))
+}
+
+/// Verifies and decrypts a single message sealed with `seal`
+///
+/// # Panics
+///
+/// The function will panic if `enc` is not of size 32
+/// The function will panic if `recipient_secret` is not of size 32
+pub fn open(enc: &[u8], recipient_secret: &[u8], info: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    setup_base_receiver(enc, recipient_secret, info)?.open(aad, ciphertext)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // RFC 9180, appendix A.2.1
+
+    const INFO: &[u8] = b"Ode on a Grecian Urn";
+
+    const PLAINTEXT: &[u8] = b"Beauty is truth, truth beauty";
+
+    const EPHEMERAL_IKM: [u8; 32] = [
+        0x90, 0x9a, 0x9b, 0x35, 0xd3, 0xdc, 0x47, 0x13,
+        0xa5, 0xe7, 0x2a, 0x4d, 0xa2, 0x74, 0xb5, 0x5d,
+        0x3d, 0x38, 0x21, 0xa3, 0x7e, 0x5d, 0x09, 0x9e,
+        0x74, 0xa6, 0x47, 0xdb, 0x58, 0x3a, 0x90, 0x4b
+    ];
+
+    const EPHEMERAL_SECRET: [u8; 32] = [
+        0xf4, 0xec, 0x9b, 0x33, 0xb7, 0x92, 0xc3, 0x72,
+        0xc1, 0xd2, 0xc2, 0x06, 0x35, 0x07, 0xb6, 0x84,
+        0xef, 0x92, 0x5b, 0x8c, 0x75, 0xa4, 0x2d, 0xbc,
+        0xbf, 0x57, 0xd6, 0x3c, 0xcd, 0x38, 0x16, 0x00
+    ];
+
+    const RECIPIENT_PUBLIC: [u8; 32] = [
+        0x43, 0x10, 0xee, 0x97, 0xd8, 0x8c, 0xc1, 0xf0,
+        0x88, 0xa5, 0x57, 0x6c, 0x77, 0xab, 0x0c, 0xf5,
+        0xc3, 0xac, 0x79, 0x7f, 0x3d, 0x95, 0x13, 0x9c,
+        0x6c, 0x84, 0xb5, 0x42, 0x9c, 0x59, 0x66, 0x2a
+    ];
+
+    const RECIPIENT_SECRET: [u8; 32] = [
+        0x80, 0x57, 0x99, 0x1e, 0xef, 0x8f, 0x1f, 0x1a,
+        0xf1, 0x8f, 0x4a, 0x94, 0x91, 0xd1, 0x6a, 0x1c,
+        0xe3, 0x33, 0xf6, 0x95, 0xd4, 0xdb, 0x8e, 0x38,
+        0xda, 0x75, 0x97, 0x5c, 0x44, 0x78, 0xe0, 0xfb
+    ];
+
+    const ENC: [u8; 32] = [
+        0x1a, 0xfa, 0x08, 0xd3, 0xde, 0xc0, 0x47, 0xa6,
+        0x43, 0x88, 0x51, 0x63, 0xf1, 0x18, 0x04, 0x76,
+        0xfa, 0x7d, 0xdb, 0x54, 0xc6, 0xa8, 0x02, 0x9e,
+        0xa3, 0x3f, 0x95, 0x79, 0x6b, 0xf2, 0xac, 0x4a
+    ];
+
+    const CIPHERTEXT_0: [u8; 45] = [
+        0x1c, 0x52, 0x50, 0xd8, 0x03, 0x4e, 0xc2, 0xb7,
+        0x84, 0xba, 0x2c, 0xfd, 0x69, 0xdb, 0xdb, 0x8a,
+        0xf4, 0x06, 0xcf, 0xe3, 0xff, 0x93, 0x8e, 0x13,
+        0x1f, 0x0d, 0xef, 0x8c, 0x8b, 0x60, 0xb4, 0xdb,
+        0x21, 0x99, 0x3c, 0x62, 0xce, 0x81, 0x88, 0x3d,
+        0x2d, 0xd1, 0xb5, 0x1a, 0x28
+    ];
+
+    const CIPHERTEXT_1: [u8; 45] = [
+        0x6b, 0x53, 0xc0, 0x51, 0xe4, 0x19, 0x9c, 0x51,
+        0x8d, 0xe7, 0x95, 0x94, 0xe1, 0xc4, 0xab, 0x18,
+        0xb9, 0x6f, 0x08, 0x15, 0x49, 0xd4, 0x5c, 0xe0,
+        0x15, 0xbe, 0x00, 0x20, 0x90, 0xbb, 0x11, 0x9e,
+        0x85, 0x28, 0x53, 0x37, 0xcc, 0x95, 0xba, 0x5f,
+        0x59, 0x99, 0x2d, 0xc9, 0x8c
+    ];
+
+    #[test]
+    fn it_derives_the_rfc_9180_key_pair() {
+        assert_eq!(derive_key_pair(&EPHEMERAL_IKM), (EPHEMERAL_SECRET, ENC));
+    }
+
+    #[test]
+    fn it_seals_the_rfc_9180_messages() {
+        let (enc, mut sender) = setup_base_sender(&RECIPIENT_PUBLIC, &EPHEMERAL_SECRET, INFO).unwrap();
+
+        assert_eq!(enc, ENC);
+        assert_eq!(&sender.seal(b"Count-0", PLAINTEXT).unwrap()[..], &CIPHERTEXT_0[..]);
+        assert_eq!(&sender.seal(b"Count-1", PLAINTEXT).unwrap()[..], &CIPHERTEXT_1[..]);
+
+        let mut receiver = setup_base_receiver(&ENC, &RECIPIENT_SECRET, INFO).unwrap();
+        assert_eq!(receiver.open(b"Count-0", &CIPHERTEXT_0), Ok(PLAINTEXT.to_vec()));
+        assert_eq!(receiver.open(b"Count-1", &CIPHERTEXT_1), Ok(PLAINTEXT.to_vec()));
+    }
+
+    #[test]
+    fn it_exports_the_rfc_9180_secrets() {
+        let empty_context: [u8; 32] = [
+            0x4b, 0xbd, 0x62, 0x43, 0xb8, 0xbb, 0x54, 0xce,
+            0xc3, 0x11, 0xfa, 0xc9, 0xdf, 0x81, 0x84, 0x1b,
+            0x6f, 0xd6, 0x1f, 0x56, 0x53, 0x8a, 0x77, 0x5e,
+            0x7c, 0x80, 0xa9, 0xf4, 0x01, 0x60, 0x60, 0x6e
+        ];
+        let test_context: [u8; 32] = [
+            0x5a, 0xcb, 0x09, 0x21, 0x11, 0x39, 0xc4, 0x3b,
+            0x30, 0x90, 0x48, 0x9a, 0x9d, 0xa4, 0x33, 0xe8,
+            0xa3, 0x0e, 0xe7, 0x18, 0x8b, 0xa8, 0xb0, 0xa9,
+            0xa1, 0xcc, 0xf0, 0xc2, 0x29, 0x28, 0x3e, 0x53
+        ];
+
+        let (_, sender) = setup_base_sender(&RECIPIENT_PUBLIC, &EPHEMERAL_SECRET, INFO).unwrap();
+        let receiver = setup_base_receiver(&ENC, &RECIPIENT_SECRET, INFO).unwrap();
+
+        let mut exported = [0u8; 32];
+        sender.export(b"", &mut exported);
+        assert_eq!(exported, empty_context);
+        receiver.export(b"TestContext", &mut exported);
+        assert_eq!(exported, test_context);
+    }
+
+    #[test]
+    fn it_rejects_out_of_order_and_modified_messages() {
+        let mut receiver = setup_base_receiver(&ENC, &RECIPIENT_SECRET, INFO).unwrap();
+        assert_eq!(receiver.open(b"Count-1", &CIPHERTEXT_1), Err(Error::InvalidTag));
+        assert_eq!(receiver.open(b"Count-1", &CIPHERTEXT_0), Err(Error::InvalidTag));
+
+        let mut modified = CIPHERTEXT_0;
+        modified[3] ^= 1;
+        assert_eq!(receiver.open(b"Count-0", &modified), Err(Error::InvalidTag));
+        assert_eq!(receiver.open(b"Count-0", &CIPHERTEXT_0), Ok(PLAINTEXT.to_vec()));
+
+        assert_eq!(open(&ENC, &RECIPIENT_SECRET, b"another info", b"Count-0", &CIPHERTEXT_0), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_round_trips_single_messages() {
+        let (recipient_secret, recipient_public) = derive_key_pair(&[7u8; 32]);
+        let (enc, sealed) = seal(&recipient_public, &[9u8; 32], b"", b"aad", b"hello").unwrap();
+
+        assert_eq!(open(&enc, &recipient_secret, b"", b"aad", &sealed), Ok(b"hello".to_vec()));
+        assert_eq!(seal(&[0u8; 32], &[9u8; 32], b"", b"", b"hello"), Err(Error::InvalidPublicKey));
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn it_sets_up_senders_under_fresh_ephemeral_keys() {
+        let (recipient_secret, recipient_public) = derive_key_pair(&[7u8; 32]);
+        let (enc, mut sender) = setup_base_sender_random(&recipient_public, INFO).unwrap();
+        let (other_enc, _) = setup_base_sender_random(&recipient_public, INFO).unwrap();
+
+        assert_ne!(enc, other_enc);
+
+        let mut receiver = setup_base_receiver(&enc, &recipient_secret, INFO).unwrap();
+        let sealed = sender.seal(b"aad", b"hello").unwrap();
+        assert_eq!(receiver.open(b"aad", &sealed), Ok(b"hello".to_vec()));
+    }
+}
@@ -0,0 +1,86 @@
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20::ChaCha20;
+
+/// A 256-bit ChaCha20 key
+///
+/// Owns the key bytes and wipes them on drop. The `Debug` output shows the
+/// fingerprint instead of the key, so a key can end up in logs by accident
+/// without leaking.
+pub struct Key {
+    bytes: [u8; 32],
+}
+
+impl Key {
+    /// Returns a new instance of Key
+    pub fn new(bytes: [u8; 32]) -> Key {
+        Key { bytes }
+    }
+
+    /// Returns a new instance of Key copied from `bytes`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `bytes` is not of size 32
+    pub fn from_slice(bytes: &[u8]) -> Key {
+        assert_eq!(bytes.len(), 32);
+
+        let mut key = Key { bytes: [0u8; 32] };
+        key.bytes.copy_from_slice(bytes);
+
+        key
+    }
+
+    /// Returns the key bytes, for passing them to the ciphers
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+
+    /// Returns a short identifier for the key, see `ChaCha20::fingerprint`
+    ///
+    /// Meant for container headers and logs, so a decryptor can tell which
+    /// key a file needs without trial decryption.
+    pub fn fingerprint(&self) -> [u8; 8] {
+        ChaCha20::fingerprint(&self.bytes)
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key").field("fingerprint", &self.fingerprint()).finish()
+    }
+}
+
+impl Drop for Key {
+    /// Wipes the key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_fingerprints_like_the_cipher() {
+        let key = Key::new([0x17; 32]);
+
+        assert_eq!(key.fingerprint(), ChaCha20::fingerprint(&[0x17; 32]));
+        assert_eq!(Key::from_slice(&[0x17; 32]).fingerprint(), key.fingerprint());
+        assert_ne!(Key::new([0x18; 32]).fingerprint(), key.fingerprint());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_keeps_the_key_out_of_debug_output() {
+        let key = Key::new([0xAB; 32]);
+        let debug = format!("{:?}", key);
+
+        assert!(debug.starts_with("Key { fingerprint: ["));
+        assert!(!debug.contains("171, 171"));
+    }
+}
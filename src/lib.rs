@@ -1,2 +1,93 @@
-mod byte_manipulation;
-pub mod chacha20;
\ No newline at end of file
+//! ChaCha20 stream cipher and the constructions built on it
+//!
+//! The crate is layered by features:
+//!
+//! - without any feature only `core` is used: the cipher, key derivation and
+//!   byte helpers work on caller-provided buffers
+//! - `alloc` adds the APIs returning `Vec`, such as `encrypt` and `EncryptThenMac`
+//! - `std` (the default) adds what needs the operating system, such as the
+//!   wall-clock nonce generator, `std::error::Error` and the CLI
+//! - `getrandom` seeds the RNGs from the operating system with
+//!   `ChaCha20Rng::from_os_entropy` and `OsEntropy`, and adds the thread-local
+//!   `random_bytes` and `random_u64` along with `sealed_box::seal_random`,
+//!   `hpke::setup_base_sender_random`, `envelope::seal_for_recipients` and
+//!   `field::ColumnKey::seal`; it reads /dev/urandom, so enabling it on a
+//!   target that is not Unix is a compile error
+//! - `hazmat` exposes low-level access, such as the raw cipher state and
+//!   `ChaChaVar` with a round count chosen at runtime, that is only meant for
+//!   research and interoperability testing
+//!
+//! Everything is implemented in portable, safe Rust. The only `unsafe` code
+//! lives in the private `mem` module: the volatile write behind
+//! `byte_manipulation::wipe` and the cast of a fully written buffer used by
+//! `ChaCha20::encrypt_b2b_uninit`, plus the allocator of the erasure tests.
+//! Every other module is `#![forbid(unsafe_code)]`, and the crate-wide `deny`
+//! catches new modules that forget to.
+#![cfg_attr(not(feature = "std"), no_std)]
+#![deny(unsafe_code)]
+
+// `OsEntropy` reads /dev/urandom, so other targets fail here instead of at runtime
+#[cfg(all(feature = "getrandom", not(unix)))]
+compile_error!("the `getrandom` feature reads /dev/urandom and is only supported on Unix targets");
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+mod arx;
+pub mod backend;
+#[cfg(feature = "alloc")]
+mod blake2b;
+#[cfg(feature = "alloc")]
+pub mod box_;
+pub mod byte_manipulation;
+pub mod chacha20;
+pub mod chacha20_legacy;
+pub mod chacha20_poly1305;
+#[cfg(feature = "alloc")]
+pub mod datagram;
+#[cfg(feature = "alloc")]
+pub mod encrypt_then_mac;
+#[cfg(feature = "alloc")]
+pub mod envelope;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod expiring_key;
+#[cfg(feature = "alloc")]
+pub mod field;
+#[cfg(feature = "alloc")]
+pub mod hpke;
+pub mod key;
+#[cfg(feature = "alloc")]
+pub mod log;
+#[cfg(feature = "alloc")]
+pub mod managed_cipher;
+mod mem;
+pub mod nonce;
+#[cfg(feature = "alloc")]
+pub mod packet;
+pub mod poly1305;
+pub mod quic;
+pub mod rng;
+pub mod salsa20;
+#[cfg(feature = "alloc")]
+pub mod sealed_box;
+#[cfg(feature = "alloc")]
+pub mod secretbox;
+#[cfg(feature = "alloc")]
+pub mod secretstream;
+pub mod session_keys;
+mod sha256;
+#[cfg(feature = "alloc")]
+pub mod siv;
+#[cfg(feature = "alloc")]
+pub mod stream;
+pub mod xchacha20;
+#[cfg(feature = "alloc")]
+mod x25519;
+
+pub use crate::chacha20::hchacha20;
+pub use crate::salsa20::hsalsa20;
+#[cfg(feature = "alloc")]
+pub use crate::chacha20::{decrypt, encrypt};
+#[cfg(feature = "getrandom")]
+pub use crate::rng::{random_bytes, random_u64};
\ No newline at end of file
@@ -0,0 +1,280 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20::ChaCha20;
+use crate::chacha20_poly1305::{counter_nonce, ChaCha20Poly1305};
+use crate::error::Error;
+use crate::poly1305::TAG_LEN;
+
+/// Nonce of the ChaCha20 block that ratchets the chain key
+const RATCHET_NONCE: &[u8; 12] = b"log ratchet\0";
+
+/// Position in a log shared by `LogWriter` and `LogReader`
+///
+/// Every record gets its own key from a one-way ratchet: block 0 of ChaCha20
+/// under the chain key gives the next chain key and the record key, and the
+/// old chain key is wiped. Each record is sealed with ChaCha20-Poly1305 under
+/// its record key, a nonce holding its index and a closing flag, and the tag
+/// of the previous record as AAD.
+struct Chain {
+    chain_key: [u8; 32],
+    index: u64,
+    previous_tag: [u8; TAG_LEN],
+}
+
+impl Chain {
+    fn new(key: &[u8]) -> Chain {
+        assert_eq!(key.len(), 32);
+
+        let mut chain = Chain { chain_key: [0u8; 32], index: 0, previous_tag: [0u8; TAG_LEN] };
+        chain.chain_key.copy_from_slice(key);
+
+        chain
+    }
+
+    /// Returns the next chain key and the key of the current record, without moving the chain
+    fn record_key(&self) -> ([u8; 32], [u8; 32]) {
+        let mut block = ChaCha20::new_with_raw_nonce(&self.chain_key, RATCHET_NONCE, 0).next_block_bytes();
+
+        let mut next_chain_key = [0u8; 32];
+        let mut record_key = [0u8; 32];
+        next_chain_key.copy_from_slice(&block[0..32]);
+        record_key.copy_from_slice(&block[32..64]);
+        wipe(&mut block);
+
+        (next_chain_key, record_key)
+    }
+
+    /// Nonce of the current record: its index in little endian and the closing flag in byte 0
+    fn nonce(&self, closing: bool) -> Result<[u8; 12], Error> {
+        if self.index == u64::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        let mut nonce = counter_nonce(self.index);
+        nonce[0] = closing as u8;
+
+        Ok(nonce)
+    }
+
+    /// Moves to the next record once `sealed` has been written or verified
+    fn advance(&mut self, mut next_chain_key: [u8; 32], sealed: &[u8]) {
+        self.chain_key = next_chain_key;
+        wipe(&mut next_chain_key);
+
+        self.previous_tag.copy_from_slice(&sealed[sealed.len() - TAG_LEN..]);
+        self.index += 1;
+    }
+}
+
+impl Drop for Chain {
+    /// Wipes the chain key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.chain_key);
+    }
+}
+
+/// Writing half of an append-only encrypted log
+///
+/// Each appended record is sealed under a key of its own, derived by a
+/// one-way ratchet from the previous one, so a writer compromised later
+/// cannot decrypt or forge the records it already wrote. Records are bound
+/// to their position and to the record before them, so `LogReader` detects
+/// records that were dropped, reordered or replaced, and a log cut short,
+/// because only `close` writes a record that opens as the last one.
+pub struct LogWriter {
+    chain: Chain,
+}
+
+impl LogWriter {
+    /// Returns a new instance of LogWriter, at the start of a log
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> LogWriter {
+        LogWriter { chain: Chain::new(key) }
+    }
+
+    /// Seals `record` and returns the ciphertext followed by the 16-byte tag
+    ///
+    /// Returns `Error::KeyExpired` once `u64::MAX` records have been written.
+    pub fn append(&mut self, record: &[u8]) -> Result<Vec<u8>, Error> {
+        self.seal(record, false)
+    }
+
+    /// Seals the empty closing record and ends the log
+    ///
+    /// Returns `Error::KeyExpired` once `u64::MAX` records have been written.
+    pub fn close(mut self) -> Result<Vec<u8>, Error> {
+        self.seal(b"", true)
+    }
+
+    /// Returns the index of the next record
+    pub fn index(&self) -> u64 {
+        self.chain.index
+    }
+
+    fn seal(&mut self, record: &[u8], closing: bool) -> Result<Vec<u8>, Error> {
+        let nonce = self.chain.nonce(closing)?;
+        let (next_chain_key, mut record_key) = self.chain.record_key();
+        let sealed = ChaCha20Poly1305::new(&record_key).encrypt(&nonce, &self.chain.previous_tag, record);
+        wipe(&mut record_key);
+
+        self.chain.advance(next_chain_key, &sealed);
+
+        Ok(sealed)
+    }
+}
+
+/// Reading half of an append-only encrypted log, see `LogWriter`
+///
+/// Records must be opened in the order they were appended. A log whose
+/// closing record is missing has been truncated, or is still being written.
+pub struct LogReader {
+    chain: Chain,
+}
+
+impl LogReader {
+    /// Returns a new instance of LogReader, at the start of a log
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> LogReader {
+        LogReader { chain: Chain::new(key) }
+    }
+
+    /// Verifies and decrypts the next record
+    ///
+    /// Returns `Error::InvalidTag` if the record is not the next one of the
+    /// log or has been modified. The position only advances on success.
+    pub fn open_next(&mut self, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        self.open(sealed, false)
+    }
+
+    /// Verifies the closing record and ends the log
+    ///
+    /// Returns `Error::InvalidTag` if `sealed` is not the closing record
+    /// following the records opened so far.
+    pub fn finish(mut self, sealed: &[u8]) -> Result<(), Error> {
+        self.open(sealed, true).map(|_| ())
+    }
+
+    /// Returns the index of the next record
+    pub fn index(&self) -> u64 {
+        self.chain.index
+    }
+
+    fn open(&mut self, sealed: &[u8], closing: bool) -> Result<Vec<u8>, Error> {
+        let nonce = self.chain.nonce(closing)?;
+        let (mut next_chain_key, mut record_key) = self.chain.record_key();
+        let opened = ChaCha20Poly1305::new(&record_key).decrypt(&nonce, &self.chain.previous_tag, sealed);
+        wipe(&mut record_key);
+
+        match opened {
+            Ok(record) => {
+                self.chain.advance(next_chain_key, sealed);
+                Ok(record)
+            }
+            Err(e) => {
+                wipe(&mut next_chain_key);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x24; 32];
+
+    fn written_log() -> [Vec<u8>; 4] {
+        let mut writer = LogWriter::new(&KEY);
+        let first = writer.append(b"user alice logged in").unwrap();
+        let second = writer.append(b"user alice read payroll").unwrap();
+        let third = writer.append(b"user alice logged out").unwrap();
+        let closing = writer.close().unwrap();
+
+        [first, second, third, closing]
+    }
+
+    #[test]
+    fn it_round_trips_a_log() {
+        let log = written_log();
+
+        let mut reader = LogReader::new(&KEY);
+        assert_eq!(reader.open_next(&log[0]), Ok(b"user alice logged in".to_vec()));
+        assert_eq!(reader.open_next(&log[1]), Ok(b"user alice read payroll".to_vec()));
+        assert_eq!(reader.open_next(&log[2]), Ok(b"user alice logged out".to_vec()));
+        assert_eq!(reader.index(), 3);
+        assert_eq!(reader.finish(&log[3]), Ok(()));
+    }
+
+    #[test]
+    fn it_seals_every_record_under_a_new_key() {
+        let mut writer = LogWriter::new(&KEY);
+        let first = writer.append(b"same record").unwrap();
+        let second = writer.append(b"same record").unwrap();
+
+        assert_ne!(first[..11], second[..11]);
+        assert_ne!(writer.chain.chain_key, KEY);
+    }
+
+    #[test]
+    fn it_detects_reordering_and_dropped_records() {
+        let log = written_log();
+
+        let mut reader = LogReader::new(&KEY);
+        assert_eq!(reader.open_next(&log[1]), Err(Error::InvalidTag));
+        assert!(reader.open_next(&log[0]).is_ok());
+        assert_eq!(reader.open_next(&log[0]), Err(Error::InvalidTag));
+        assert_eq!(reader.open_next(&log[2]), Err(Error::InvalidTag));
+        assert!(reader.open_next(&log[1]).is_ok());
+    }
+
+    #[test]
+    fn it_detects_truncation() {
+        let log = written_log();
+
+        let mut reader = LogReader::new(&KEY);
+        assert!(reader.open_next(&log[0]).is_ok());
+        assert!(reader.open_next(&log[1]).is_ok());
+        assert_eq!(reader.finish(&log[2]), Err(Error::InvalidTag));
+
+        let mut reader = LogReader::new(&KEY);
+        for record in log[0..3].iter() {
+            assert!(reader.open_next(record).is_ok());
+        }
+        assert_eq!(reader.open_next(&log[3]), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_detects_modified_records() {
+        let mut log = written_log();
+        log[1][0] ^= 1;
+
+        let mut reader = LogReader::new(&KEY);
+        assert!(reader.open_next(&log[0]).is_ok());
+        assert_eq!(reader.open_next(&log[1]), Err(Error::InvalidTag));
+        assert_eq!(reader.index(), 1);
+    }
+
+    #[test]
+    fn it_chains_each_record_to_the_previous_tag() {
+        let log = written_log();
+
+        let mut chain = Chain::new(&KEY);
+        let (next_chain_key, _) = chain.record_key();
+        chain.advance(next_chain_key, &log[0]);
+        let (_, record_key) = chain.record_key();
+
+        let aead = ChaCha20Poly1305::new(&record_key);
+        assert_eq!(aead.decrypt(&counter_nonce(1), &log[0][log[0].len() - TAG_LEN..], &log[1]), Ok(b"user alice read payroll".to_vec()));
+        assert_eq!(aead.decrypt(&counter_nonce(1), &[0u8; TAG_LEN], &log[1]), Err(Error::InvalidTag));
+    }
+}
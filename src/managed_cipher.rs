@@ -0,0 +1,362 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{wipe, Endian, LittleEndian};
+use crate::chacha20::ChaCha20;
+use crate::chacha20_poly1305::ChaCha20Poly1305;
+use crate::error::Error;
+
+/// Domain separation prefix for the per-epoch key derivation
+const EPOCH_KEY_CONTEXT: &[u8; 8] = b"mgd-key!";
+
+/// Usage allowed under one derived key before `ManagedCipher` rekeys
+///
+/// A single message larger than `bytes_per_key` gets a fresh key of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManagedLimits {
+    pub messages_per_key: u64,
+    pub bytes_per_key: u64,
+}
+
+impl Default for ManagedLimits {
+    /// 2^32 messages or 1 TiB per derived key, whichever comes first
+    fn default() -> ManagedLimits {
+        ManagedLimits {
+            messages_per_key: 1 << 32,
+            bytes_per_key: 1 << 40,
+        }
+    }
+}
+
+/// Counters kept by `ManagedCipher` over its whole lifetime
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CipherMetrics {
+    pub messages: u64,
+    pub bytes: u64,
+    pub rekeys: u64,
+}
+
+/// High-level cipher handle that owns the key and all nonce bookkeeping
+///
+/// Messages are sealed with ChaCha20-Poly1305 under a key derived from the
+/// master key with HChaCha20 for the current epoch. Each message gets the
+/// nonce `epoch (4 bytes LE) || sequence (8 bytes LE)`, so the nonce alone
+/// tells the receiver which epoch key to use. Once the current key reaches
+/// its `ManagedLimits` the cipher moves to the next epoch automatically.
+///
+/// Nonces only stay unique if a single `ManagedCipher` encrypts under a
+/// master key at any time, and each instance carries on where the previous
+/// one stopped: save `epoch()` and `sequence()` after encrypting and pass
+/// them to `resume`, or, to save only on rekeying, resume at sequence 0 of
+/// the epoch after the saved one. `new` starts at epoch 0 and is only for a
+/// master key that has never been used. Other parties decrypt with a
+/// `ManagedReceiver`. All key material is wiped on drop.
+pub struct ManagedCipher {
+    master_key: [u8; 32],
+    key: [u8; 32],
+    epoch: u32,
+    sequence: u64,
+    bytes: u64,
+    limits: ManagedLimits,
+    metrics: CipherMetrics,
+}
+
+impl ManagedCipher {
+    /// Returns a new ManagedCipher with the default limits, for a master key that has never encrypted anything
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `master_key` is not of size 32
+    pub fn new(master_key: &[u8]) -> ManagedCipher {
+        ManagedCipher::with_limits(master_key, ManagedLimits::default())
+    }
+
+    /// Returns a new ManagedCipher rekeying according to `limits`, for a master key that has never encrypted anything
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `master_key` is not of size 32
+    /// The function will panic if either limit is zero
+    pub fn with_limits(master_key: &[u8], limits: ManagedLimits) -> ManagedCipher {
+        ManagedCipher::resume(master_key, limits, 0, 0)
+    }
+
+    /// Returns a ManagedCipher carrying on at `sequence` of `epoch`, as saved from a previous instance
+    ///
+    /// The byte limit of the resumed epoch key counts from zero again, and
+    /// the metrics cover this instance only.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `master_key` is not of size 32
+    /// The function will panic if either limit is zero
+    pub fn resume(master_key: &[u8], limits: ManagedLimits, epoch: u32, sequence: u64) -> ManagedCipher {
+        assert_eq!(master_key.len(), 32);
+        assert!(limits.messages_per_key > 0 && limits.bytes_per_key > 0);
+
+        let mut owned_key = [0u8; 32];
+        owned_key.copy_from_slice(master_key);
+
+        ManagedCipher {
+            key: epoch_key(&owned_key, epoch),
+            master_key: owned_key,
+            epoch,
+            sequence,
+            bytes: 0,
+            limits,
+            metrics: CipherMetrics::default(),
+        }
+    }
+
+    /// Seals `plaintext` under a fresh nonce and returns the nonce with the ciphertext and tag
+    ///
+    /// Returns `Error::KeyExpired` if every epoch has been used up.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `plaintext` is longer than the 256 GiB keystream
+    pub fn encrypt(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<([u8; 12], Vec<u8>), Error> {
+        let len = plaintext.len() as u64;
+
+        if self.sequence >= self.limits.messages_per_key || self.bytes + len > self.limits.bytes_per_key {
+            self.rekey()?;
+        }
+
+        let mut nonce = [0u8; 12];
+        LittleEndian::write_u32(self.epoch, &mut nonce[0..4]);
+        LittleEndian::write_u64(self.sequence, &mut nonce[4..12]);
+
+        self.sequence += 1;
+        self.bytes += len;
+        self.metrics.messages += 1;
+        self.metrics.bytes += len;
+
+        Ok((nonce, ChaCha20Poly1305::new(&self.key).encrypt(&nonce, aad, plaintext)))
+    }
+
+    /// Verifies and decrypts a message sealed by `encrypt` under the same master key
+    ///
+    /// Returns `Error::InvalidTag` if the message, nonce or `aad` has been
+    /// modified or the message was sealed under another master key.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    pub fn decrypt(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        assert_eq!(nonce.len(), 12);
+
+        if LittleEndian::read_u32(&nonce[0..4]) == self.epoch {
+            return ChaCha20Poly1305::new(&self.key).decrypt(nonce, aad, ciphertext);
+        }
+
+        decrypt_with_master_key(&self.master_key, nonce, aad, ciphertext)
+    }
+
+    /// Returns the lifetime metrics
+    pub fn metrics(&self) -> CipherMetrics {
+        self.metrics
+    }
+
+    /// Returns the current key epoch
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Returns the sequence number the next message of the current epoch will use
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Moves to the next epoch key
+    fn rekey(&mut self) -> Result<(), Error> {
+        let epoch = self.epoch.checked_add(1).ok_or(Error::KeyExpired)?;
+
+        wipe(&mut self.key);
+        self.key = epoch_key(&self.master_key, epoch);
+        self.epoch = epoch;
+        self.sequence = 0;
+        self.bytes = 0;
+        self.metrics.rekeys += 1;
+
+        Ok(())
+    }
+}
+
+impl Drop for ManagedCipher {
+    /// Wipes the master and epoch keys from memory
+    fn drop(&mut self) {
+        wipe(&mut self.master_key);
+        wipe(&mut self.key);
+    }
+}
+
+/// Decrypting half of `ManagedCipher`, for parties that never encrypt under the master key
+pub struct ManagedReceiver {
+    master_key: [u8; 32],
+}
+
+impl ManagedReceiver {
+    /// Returns a new ManagedReceiver
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `master_key` is not of size 32
+    pub fn new(master_key: &[u8]) -> ManagedReceiver {
+        assert_eq!(master_key.len(), 32);
+
+        let mut receiver = ManagedReceiver { master_key: [0u8; 32] };
+        receiver.master_key.copy_from_slice(master_key);
+
+        receiver
+    }
+
+    /// Verifies and decrypts a message sealed by `ManagedCipher::encrypt`, see `ManagedCipher::decrypt`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    pub fn decrypt(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        assert_eq!(nonce.len(), 12);
+
+        decrypt_with_master_key(&self.master_key, nonce, aad, ciphertext)
+    }
+}
+
+impl Drop for ManagedReceiver {
+    /// Wipes the master key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.master_key);
+    }
+}
+
+/// Derives the key for `epoch` from the master key
+fn epoch_key(master_key: &[u8; 32], epoch: u32) -> [u8; 32] {
+    let mut input = [0u8; 16];
+    input[0..8].copy_from_slice(EPOCH_KEY_CONTEXT);
+    LittleEndian::write_u64(epoch as u64, &mut input[8..16]);
+
+    ChaCha20::hchacha20(master_key, &input)
+}
+
+/// Decrypts under the key of the epoch named in the first 4 bytes of `nonce`
+fn decrypt_with_master_key(master_key: &[u8; 32], nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut key = epoch_key(master_key, LittleEndian::read_u32(&nonce[0..4]));
+    let plaintext = ChaCha20Poly1305::new(&key).decrypt(nonce, aad, ciphertext);
+    wipe(&mut key);
+
+    plaintext
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_roundtrips_with_distinct_nonces() {
+        let mut sender = ManagedCipher::new(&[9u8; 32]);
+        let receiver = ManagedReceiver::new(&[9u8; 32]);
+
+        let (first_nonce, first) = sender.encrypt(b"aad", b"first message").unwrap();
+        let (second_nonce, second) = sender.encrypt(b"aad", b"first message").unwrap();
+
+        assert_ne!(first_nonce, second_nonce);
+        assert_ne!(first, second);
+        assert_eq!(receiver.decrypt(&first_nonce, b"aad", &first), Ok(b"first message".to_vec()));
+        assert_eq!(receiver.decrypt(&second_nonce, b"aad", &second), Ok(b"first message".to_vec()));
+        assert_eq!(sender.decrypt(&first_nonce, b"aad", &first), Ok(b"first message".to_vec()));
+    }
+
+    #[test]
+    fn it_seals_with_chacha20_poly1305_under_the_epoch_key() {
+        let mut sender = ManagedCipher::new(&[9u8; 32]);
+        let (nonce, sealed) = sender.encrypt(b"aad", b"message").unwrap();
+
+        let expected = ChaCha20Poly1305::new(&epoch_key(&[9u8; 32], 0)).encrypt(&nonce, b"aad", b"message");
+        assert_eq!(sealed, expected);
+    }
+
+    #[test]
+    fn it_rejects_modified_messages() {
+        let mut sender = ManagedCipher::new(&[9u8; 32]);
+        let receiver = ManagedReceiver::new(&[9u8; 32]);
+        let (mut nonce, mut sealed) = sender.encrypt(b"aad", b"message").unwrap();
+
+        assert_eq!(receiver.decrypt(&nonce, b"other", &sealed), Err(Error::InvalidTag));
+        assert_eq!(ManagedReceiver::new(&[8u8; 32]).decrypt(&nonce, b"aad", &sealed), Err(Error::InvalidTag));
+
+        sealed[0] ^= 1;
+        assert_eq!(receiver.decrypt(&nonce, b"aad", &sealed), Err(Error::InvalidTag));
+        assert_eq!(sender.decrypt(&nonce, b"aad", &sealed), Err(Error::InvalidTag));
+
+        sealed[0] ^= 1;
+        nonce[0] ^= 1;
+        assert_eq!(receiver.decrypt(&nonce, b"aad", &sealed), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_rekeys_after_the_message_limit() {
+        let limits = ManagedLimits { messages_per_key: 2, ..ManagedLimits::default() };
+        let mut sender = ManagedCipher::with_limits(&[9u8; 32], limits);
+        let receiver = ManagedReceiver::new(&[9u8; 32]);
+
+        sender.encrypt(b"", b"a").unwrap();
+        sender.encrypt(b"", b"b").unwrap();
+        let (nonce, ciphertext) = sender.encrypt(b"", b"c").unwrap();
+
+        assert_eq!(sender.epoch(), 1);
+        assert_eq!(&nonce[0..4], &[1, 0, 0, 0]);
+        assert_eq!(&nonce[4..12], &[0u8; 8]);
+        assert_eq!(receiver.decrypt(&nonce, b"", &ciphertext), Ok(b"c".to_vec()));
+        assert_eq!(sender.metrics(), CipherMetrics { messages: 3, bytes: 3, rekeys: 1 });
+    }
+
+    #[test]
+    fn it_rekeys_after_the_byte_limit() {
+        let limits = ManagedLimits { bytes_per_key: 10, ..ManagedLimits::default() };
+        let mut sender = ManagedCipher::with_limits(&[9u8; 32], limits);
+
+        sender.encrypt(b"", &[0u8; 8]).unwrap();
+        assert_eq!(sender.epoch(), 0);
+
+        sender.encrypt(b"", &[0u8; 8]).unwrap();
+        assert_eq!(sender.epoch(), 1);
+    }
+
+    #[test]
+    fn it_resumes_where_the_previous_instance_stopped() {
+        let limits = ManagedLimits::default();
+        let mut sender = ManagedCipher::with_limits(&[9u8; 32], limits);
+        let (first_nonce, _) = sender.encrypt(b"", b"a").unwrap();
+        let (epoch, sequence) = (sender.epoch(), sender.sequence());
+        drop(sender);
+
+        let mut resumed = ManagedCipher::resume(&[9u8; 32], limits, epoch, sequence);
+        let (nonce, sealed) = resumed.encrypt(b"", b"b").unwrap();
+
+        assert_ne!(nonce, first_nonce);
+        assert_eq!(&nonce[4..12], &[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(ManagedReceiver::new(&[9u8; 32]).decrypt(&nonce, b"", &sealed), Ok(b"b".to_vec()));
+
+        let mut next_epoch = ManagedCipher::resume(&[9u8; 32], limits, epoch + 1, 0);
+        let (nonce, _) = next_epoch.encrypt(b"", b"c").unwrap();
+        assert_eq!(&nonce[0..4], &[1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn it_uses_a_different_key_per_epoch() {
+        let master_key = [9u8; 32];
+
+        assert_ne!(epoch_key(&master_key, 0), epoch_key(&master_key, 1));
+        assert_ne!(epoch_key(&master_key, 0), master_key);
+    }
+
+    #[test]
+    fn it_expires_after_the_last_epoch() {
+        let limits = ManagedLimits { messages_per_key: 1, ..ManagedLimits::default() };
+        let mut sender = ManagedCipher::resume(&[9u8; 32], limits, u32::MAX, 0);
+
+        assert!(sender.encrypt(b"", b"a").is_ok());
+        assert_eq!(sender.encrypt(b"", b"b"), Err(Error::KeyExpired));
+    }
+}
@@ -0,0 +1,132 @@
+#![allow(unsafe_code)]
+
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrites every element of `buf` with its default value
+///
+/// Uses volatile writes followed by a compiler fence so the optimizer cannot
+/// drop the clear, even when `buf` is never read again. Meant for wiping keys
+/// and cipher state.
+pub fn wipe<T: Copy + Default>(buf: &mut [T]) {
+    for element in buf.iter_mut() {
+        // SAFETY: `element` is a valid, aligned and exclusive reference
+        unsafe { ptr::write_volatile(element, T::default()) };
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Copies `input` into the uninitialized `output` and returns it as initialized bytes
+///
+/// # Panics
+///
+/// The function will panic if `input` and `output` differ in length
+pub(crate) fn copy_to_uninit<'a>(input: &[u8], output: &'a mut [MaybeUninit<u8>]) -> &'a mut [u8] {
+    assert_eq!(input.len(), output.len());
+
+    for (out, byte) in output.iter_mut().zip(input) {
+        out.write(*byte);
+    }
+
+    // SAFETY: every element of `output` was written in the loop above, and
+    // `MaybeUninit<u8>` has the same layout as `u8`
+    unsafe { &mut *(output as *mut [MaybeUninit<u8>] as *mut [u8]) }
+}
+
+/// Global allocator for the tests, which snapshots the contents of one watched
+/// allocation right before handing it back to the system allocator, and
+/// counts the allocations made by each thread
+#[cfg(all(test, feature = "std"))]
+pub(crate) mod test_alloc {
+    use core::cell::Cell;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::boxed::Box;
+    use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+    /// Bytes of a freed allocation captured by `freed_bytes`
+    pub(crate) const SNAPSHOT_SIZE: usize = 256;
+
+    struct InspectingAllocator;
+
+    static WATCHED: AtomicUsize = AtomicUsize::new(0);
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: AtomicU8 = AtomicU8::new(0);
+    static SNAPSHOT: [AtomicU8; SNAPSHOT_SIZE] = [EMPTY; SNAPSHOT_SIZE];
+
+    std::thread_local! {
+        /// Allocations made by the current thread, so parallel tests do not interfere
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for InspectingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            if WATCHED.compare_exchange(ptr as usize, 0, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                for (i, byte) in SNAPSHOT.iter().enumerate().take(layout.size()) {
+                    byte.store(core::ptr::read_volatile(ptr.add(i)), Ordering::SeqCst);
+                }
+            }
+
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: InspectingAllocator = InspectingAllocator;
+
+    /// Drops `value` and returns the bytes its allocation held when freed
+    pub(crate) fn freed_bytes<T>(value: Box<T>) -> [u8; SNAPSHOT_SIZE] {
+        assert!(core::mem::size_of::<T>() <= SNAPSHOT_SIZE);
+
+        WATCHED.store(&*value as *const T as usize, Ordering::SeqCst);
+        drop(value);
+
+        let mut bytes = [0u8; SNAPSHOT_SIZE];
+        for (byte, snapshot) in bytes.iter_mut().zip(SNAPSHOT.iter()) {
+            *byte = snapshot.swap(0, Ordering::SeqCst);
+        }
+
+        bytes
+    }
+
+    /// Runs `f` and returns the number of allocations it made
+    pub(crate) fn allocations<F: FnOnce()>(f: F) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+
+        ALLOCATIONS.with(Cell::get) - before
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_wipes_to_the_default_value() {
+        let mut words = [0xAAAA_AAAAu32; 8];
+        wipe(&mut words);
+
+        assert_eq!(words, [0u32; 8]);
+    }
+
+    #[test]
+    fn it_copies_into_uninitialized_bytes() {
+        let mut output = [MaybeUninit::<u8>::uninit(); 5];
+
+        assert_eq!(copy_to_uninit(b"bytes", &mut output), b"bytes");
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_rejects_mismatched_lengths() {
+        copy_to_uninit(b"bytes", &mut [MaybeUninit::<u8>::uninit(); 4]);
+    }
+}
@@ -0,0 +1,287 @@
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chacha20::ChaCha20;
+
+/// Domain separation prefix for record-keyed nonces
+const RECORD_NONCE_CONTEXT: &[u8; 8] = b"rec-nonc";
+
+/// Generates 12-byte nonces made of a coarse timestamp, an instance id and a counter
+///
+/// The layout is `timestamp (4 bytes) || instance id (4 bytes) || counter (4 bytes)`,
+/// all big endian so that nonces sort roughly by creation time.
+///
+/// Uniqueness within one generator is guaranteed by the counter, which resets
+/// whenever the timestamp advances. Uniqueness across generators sharing a key
+/// relies on every generator picking a random `instance_id`; see
+/// `collision_probability` for the resulting bound.
+#[derive(Debug)]
+pub struct TimestampNonceGenerator {
+    instance_id: u32,
+    last_timestamp: u32,
+    counter: u32,
+    issued: u64,
+}
+
+impl TimestampNonceGenerator {
+    /// Returns a new generator
+    ///
+    /// `instance_id` should be drawn at random once per process.
+    pub fn new(instance_id: u32) -> TimestampNonceGenerator {
+        TimestampNonceGenerator {
+            instance_id,
+            last_timestamp: 0,
+            counter: 0,
+            issued: 0,
+        }
+    }
+
+    /// Returns the next nonce using the current system time
+    ///
+    /// Returns `None` if `2^32` nonces were already issued within the current second.
+    #[cfg(feature = "std")]
+    pub fn generate(&mut self) -> Option<[u8; 12]> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as u32)
+            .unwrap_or(0);
+
+        self.generate_at(timestamp)
+    }
+
+    /// Returns the next nonce using the current system time as a `OneTimeNonce`
+    ///
+    /// Returns `None` under the same conditions as `generate`.
+    #[cfg(feature = "std")]
+    pub fn generate_one_time(&mut self) -> Option<OneTimeNonce> {
+        self.generate().map(OneTimeNonce)
+    }
+
+    /// Returns the next nonce for the given timestamp as a `OneTimeNonce`
+    ///
+    /// Returns `None` under the same conditions as `generate_at`.
+    pub fn generate_one_time_at(&mut self, timestamp: u32) -> Option<OneTimeNonce> {
+        self.generate_at(timestamp).map(OneTimeNonce)
+    }
+
+    /// Returns the next nonce for the given timestamp in seconds
+    ///
+    /// A timestamp older than the last one used is treated as the last one, so a
+    /// clock going backwards can never reproduce an earlier nonce.
+    ///
+    /// Returns `None` if the counter for the current timestamp is exhausted.
+    pub fn generate_at(&mut self, timestamp: u32) -> Option<[u8; 12]> {
+        if timestamp > self.last_timestamp || self.issued == 0 {
+            self.last_timestamp = timestamp.max(self.last_timestamp);
+            self.counter = 0;
+        } else if self.counter == u32::MAX {
+            return None;
+        } else {
+            self.counter += 1;
+        }
+
+        self.issued += 1;
+
+        let mut nonce = [0u8; 12];
+        nonce[0..4].copy_from_slice(&self.last_timestamp.to_be_bytes());
+        nonce[4..8].copy_from_slice(&self.instance_id.to_be_bytes());
+        nonce[8..12].copy_from_slice(&self.counter.to_be_bytes());
+
+        Some(nonce)
+    }
+
+    /// Returns the number of nonces issued by this generator
+    pub fn issued(&self) -> u64 {
+        self.issued
+    }
+
+    /// Returns the probability that at least two of `generators` concurrently
+    /// running generators picked the same random instance id
+    ///
+    /// This is the birthday bound over the 32-bit instance id space and is the
+    /// only way two generators sharing a key can emit the same nonce.
+    #[cfg(feature = "std")]
+    pub fn collision_probability(generators: u64) -> f64 {
+        if generators < 2 {
+            return 0.0;
+        }
+
+        let pairs = (generators as f64) * ((generators - 1) as f64) / 2.0;
+        let space = (u32::MAX as f64) + 1.0;
+
+        1.0 - (-pairs / space).exp()
+    }
+}
+
+/// Nonce that can be used to build exactly one cipher
+///
+/// The type is neither `Copy` nor `Clone` and is consumed by
+/// `ChaCha20::new`, so reusing a nonce needs a second, explicitly named
+/// `assume_unique` call. Nonces from `TimestampNonceGenerator` come out of
+/// `generate_one_time` already wrapped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OneTimeNonce([u8; 12]);
+
+impl OneTimeNonce {
+    /// Wraps a nonce the caller promises is never used twice under one key
+    pub fn assume_unique(nonce: [u8; 12]) -> OneTimeNonce {
+        OneTimeNonce(nonce)
+    }
+
+    /// Returns the nonce bytes, for sending them along with the ciphertext
+    pub fn as_bytes(&self) -> &[u8; 12] {
+        &self.0
+    }
+}
+
+/// Record identifier that the caller guarantees is never reused under one key
+///
+/// Deriving two nonces from the same id under the same key yields the same
+/// nonce, and encrypting two different records with it leaks their XOR. The
+/// only way to build this type is the explicitly named `assume_unique`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniqueRecordId(u64);
+
+impl UniqueRecordId {
+    /// Wraps a record id the caller promises is unique for the key it is used with
+    pub fn assume_unique(id: u64) -> UniqueRecordId {
+        UniqueRecordId(id)
+    }
+
+    /// Returns the wrapped record id
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Returns a nonce derived deterministically from `key` and `record_id`
+///
+/// The nonce is the first 12 bytes of HChaCha20 over a fixed context and the
+/// little endian record id, so re-encrypting the same record always yields the
+/// same nonce. This is only safe when record ids never repeat under one key.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+pub fn derive_record_nonce(key: &[u8], record_id: UniqueRecordId) -> [u8; 12] {
+    let mut input = [0u8; 16];
+    input[0..8].copy_from_slice(RECORD_NONCE_CONTEXT);
+    input[8..16].copy_from_slice(&record_id.get().to_le_bytes());
+
+    let subkey = ChaCha20::hchacha20(key, &input);
+
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(&subkey[0..12]);
+
+    nonce
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_lays_out_timestamp_instance_and_counter() {
+        let mut generator = TimestampNonceGenerator::new(0xAABBCCDD);
+
+        assert_eq!(
+            generator.generate_at(0x01020304),
+            Some([0x01, 0x02, 0x03, 0x04, 0xAA, 0xBB, 0xCC, 0xDD, 0x00, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            generator.generate_at(0x01020304),
+            Some([0x01, 0x02, 0x03, 0x04, 0xAA, 0xBB, 0xCC, 0xDD, 0x00, 0x00, 0x00, 0x01])
+        );
+        assert_eq!(generator.issued(), 2);
+    }
+
+    #[test]
+    fn it_resets_the_counter_when_time_advances() {
+        let mut generator = TimestampNonceGenerator::new(1);
+
+        generator.generate_at(10);
+        generator.generate_at(10);
+        let nonce = generator.generate_at(11).unwrap();
+
+        assert_eq!(&nonce[0..4], &11u32.to_be_bytes());
+        assert_eq!(&nonce[8..12], &0u32.to_be_bytes());
+    }
+
+    #[test]
+    fn it_never_repeats_when_the_clock_goes_backwards() {
+        let mut generator = TimestampNonceGenerator::new(1);
+
+        let first = generator.generate_at(20).unwrap();
+        let second = generator.generate_at(19).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(&second[0..4], &20u32.to_be_bytes());
+    }
+
+    #[test]
+    fn it_stops_when_the_counter_is_exhausted() {
+        let mut generator = TimestampNonceGenerator::new(1);
+        generator.generate_at(5);
+        generator.counter = u32::MAX;
+
+        assert_eq!(generator.generate_at(5), None);
+        assert!(generator.generate_at(6).is_some());
+    }
+
+    #[test]
+    fn it_wraps_generated_nonces_as_one_time_nonces() {
+        let mut generator = TimestampNonceGenerator::new(0xAABBCCDD);
+        let mut reference = TimestampNonceGenerator::new(0xAABBCCDD);
+
+        let nonce = generator.generate_one_time_at(5).unwrap();
+        assert_eq!(nonce.as_bytes(), &reference.generate_at(5).unwrap());
+
+        let key = [7u8; 32];
+        let expected = ChaCha20::new_with_raw_nonce(&key, nonce.as_bytes(), 1).next_block_bytes();
+        assert_eq!(&ChaCha20::new(&key, nonce, 1).next_block_bytes()[..], &expected[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_computes_collision_probability() {
+        assert_eq!(TimestampNonceGenerator::collision_probability(1), 0.0);
+
+        let probability = TimestampNonceGenerator::collision_probability(65536);
+        assert!(probability > 0.39 && probability < 0.40);
+    }
+
+    #[test]
+    fn it_derives_the_same_nonce_for_the_same_record() {
+        let key = [7u8; 32];
+        let id = UniqueRecordId::assume_unique(42);
+
+        assert_eq!(derive_record_nonce(&key, id), derive_record_nonce(&key, id));
+    }
+
+    #[test]
+    fn it_derives_distinct_nonces_per_record_and_key() {
+        let key = [7u8; 32];
+        let first = derive_record_nonce(&key, UniqueRecordId::assume_unique(1));
+        let second = derive_record_nonce(&key, UniqueRecordId::assume_unique(2));
+        let other_key = derive_record_nonce(&[8u8; 32], UniqueRecordId::assume_unique(1));
+
+        assert_ne!(first, second);
+        assert_ne!(first, other_key);
+    }
+
+    #[test]
+    fn it_derives_the_documented_record_nonce() {
+        let key = [7u8; 32];
+        let id = UniqueRecordId::assume_unique(0x0102030405060708);
+
+        // First 12 bytes of HChaCha20(key, "rec-nonc" || le64(id))
+        let expected = [
+            0x7c, 0x89, 0xf7, 0xe3, 0xf9, 0x8b, 0x2b, 0x80,
+            0x15, 0xe5, 0x52, 0x0a
+        ];
+
+        assert_eq!(derive_record_nonce(&key, id), expected);
+    }
+}
@@ -0,0 +1,149 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::chacha20_poly1305::{counter_nonce, ChaCha20Poly1305, CounterNonceAead};
+use crate::error::Error;
+
+/// Sending half of a packet protocol with cleartext headers
+///
+/// Each packet is sealed with ChaCha20-Poly1305, with its header as AAD and
+/// the nonce `counter_nonce` of its sequence number, which starts at 0 and
+/// counts the packets sealed so far. The sequence number is not sent; the
+/// receiving `PacketOpener` counts along, so this suits transports that
+/// deliver every packet once and in order, such as a serial line or a TCP
+/// stream. Use `DatagramSealer` where packets can be lost or reordered.
+pub struct PacketSealer {
+    aead: CounterNonceAead,
+}
+
+impl PacketSealer {
+    /// Returns a new instance of PacketSealer starting at sequence number 0
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> PacketSealer {
+        PacketSealer { aead: CounterNonceAead::new(key) }
+    }
+
+    /// Seals `payload` under the next sequence number, authenticating `header` along with it
+    ///
+    /// Returns the ciphertext followed by the 16-byte tag; `header` is sent
+    /// in the clear in front of it. Returns `Error::KeyExpired` once
+    /// `u64::MAX` packets have been sealed.
+    pub fn seal_packet(&mut self, header: &[u8], payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.aead.seal(header, payload).map(|(_, sealed)| sealed)
+    }
+
+    /// Returns the sequence number of the next packet
+    pub fn sequence(&self) -> u64 {
+        self.aead.counter()
+    }
+}
+
+/// Receiving half of a packet protocol with cleartext headers, see `PacketSealer`
+pub struct PacketOpener {
+    aead: ChaCha20Poly1305,
+    sequence: u64,
+}
+
+impl PacketOpener {
+    /// Returns a new instance of PacketOpener expecting sequence number 0
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> PacketOpener {
+        PacketOpener { aead: ChaCha20Poly1305::new(key), sequence: 0 }
+    }
+
+    /// Verifies `header` and `sealed` as the next packet and returns the decrypted payload
+    ///
+    /// Returns `Error::InvalidTag` if the header or payload has been
+    /// modified, or the packet is not the next one, for instance a replay or
+    /// a packet sent after one that was lost. The sequence number only
+    /// advances on success.
+    pub fn open_packet(&mut self, header: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.sequence == u64::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        let payload = self.aead.decrypt(&counter_nonce(self.sequence), header, sealed)?;
+        self.sequence += 1;
+
+        Ok(payload)
+    }
+
+    /// Returns the sequence number of the next packet
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    #[test]
+    fn it_round_trips_packets_in_order() {
+        let mut sealer = PacketSealer::new(&KEY);
+        let mut opener = PacketOpener::new(&KEY);
+
+        let first = sealer.seal_packet(b"\x01\x00", b"temperature 21.5").unwrap();
+        let second = sealer.seal_packet(b"\x01\x01", b"temperature 21.7").unwrap();
+
+        assert_eq!(sealer.sequence(), 2);
+        assert_eq!(opener.open_packet(b"\x01\x00", &first), Ok(b"temperature 21.5".to_vec()));
+        assert_eq!(opener.open_packet(b"\x01\x01", &second), Ok(b"temperature 21.7".to_vec()));
+        assert_eq!(opener.sequence(), 2);
+    }
+
+    #[test]
+    fn it_binds_the_header_and_sequence_number() {
+        let mut sealer = PacketSealer::new(&KEY);
+        let sealed = sealer.seal_packet(b"header", b"payload").unwrap();
+
+        let expected = ChaCha20Poly1305::new(&KEY).encrypt(&counter_nonce(0), b"header", b"payload");
+        assert_eq!(sealed, expected);
+
+        let mut opener = PacketOpener::new(&KEY);
+        assert_eq!(opener.open_packet(b"Header", &sealed), Err(Error::InvalidTag));
+        assert_eq!(opener.open_packet(b"", &sealed), Err(Error::InvalidTag));
+        assert_eq!(opener.sequence(), 0);
+    }
+
+    #[test]
+    fn it_seals_equal_packets_differently() {
+        let mut sealer = PacketSealer::new(&KEY);
+        let first = sealer.seal_packet(b"header", b"payload").unwrap();
+        let second = sealer.seal_packet(b"header", b"payload").unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn it_rejects_replayed_and_skipped_packets() {
+        let mut sealer = PacketSealer::new(&KEY);
+        let first = sealer.seal_packet(b"", b"first").unwrap();
+        let second = sealer.seal_packet(b"", b"second").unwrap();
+        let third = sealer.seal_packet(b"", b"third").unwrap();
+
+        let mut opener = PacketOpener::new(&KEY);
+        assert_eq!(opener.open_packet(b"", &second), Err(Error::InvalidTag));
+        assert!(opener.open_packet(b"", &first).is_ok());
+        assert_eq!(opener.open_packet(b"", &first), Err(Error::InvalidTag));
+        assert_eq!(opener.open_packet(b"", &third), Err(Error::InvalidTag));
+        assert!(opener.open_packet(b"", &second).is_ok());
+    }
+
+    #[test]
+    fn it_expires_after_the_last_sequence_number() {
+        let mut opener = PacketOpener::new(&KEY);
+        opener.sequence = u64::MAX;
+
+        assert_eq!(opener.open_packet(b"", &[0u8; 16]), Err(Error::KeyExpired));
+    }
+}
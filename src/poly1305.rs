@@ -0,0 +1,299 @@
+#![forbid(unsafe_code)]
+
+use crate::byte_manipulation::{constant_time_eq, wipe, Endian, LittleEndian};
+use crate::error::Error;
+
+/// Mask of the 26 bits held by each limb
+const LIMB_MASK: u32 = 0x3ffffff;
+
+/// Size of a Poly1305 tag
+pub const TAG_LEN: usize = 16;
+
+/// Poly1305 one-time authenticator
+///
+/// Data can be fed in pieces of any size with `update`, so large files and
+/// streams are authenticated without buffering them. The accumulator and `r`
+/// are kept as five 26-bit limbs so every product fits in a `u64`. A key must
+/// only ever authenticate a single message.
+pub struct Poly1305 {
+    r: [u32; 5],
+    pad: [u32; 4],
+    h: [u32; 5],
+    pending: [u8; 16],
+    pending_len: usize,
+}
+
+impl Poly1305 {
+    /// Returns a new instance of Poly1305
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> Poly1305 {
+        assert_eq!(key.len(), 32);
+
+        let r = [
+            LittleEndian::read_u32(&key[0..4]) & 0x3ffffff,
+            (LittleEndian::read_u32(&key[3..7]) >> 2) & 0x3ffff03,
+            (LittleEndian::read_u32(&key[6..10]) >> 4) & 0x3ffc0ff,
+            (LittleEndian::read_u32(&key[9..13]) >> 6) & 0x3f03fff,
+            (LittleEndian::read_u32(&key[12..16]) >> 8) & 0x00fffff,
+        ];
+
+        let mut pad = [0u32; 4];
+        LittleEndian::read_u32_into(&key[16..32], &mut pad);
+
+        Poly1305 { r, pad, h: [0u32; 5], pending: [0u8; 16], pending_len: 0 }
+    }
+
+    /// Feeds `data` into the authenticator
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.pending_len > 0 {
+            let take = data.len().min(16 - self.pending_len);
+            self.pending[self.pending_len..(self.pending_len + take)].copy_from_slice(&data[..take]);
+            self.pending_len += take;
+            data = &data[take..];
+
+            if self.pending_len < 16 {
+                return;
+            }
+
+            let block = self.pending;
+            self.block(&block, 1 << 24);
+            self.pending_len = 0;
+        }
+
+        let mut blocks = data.chunks_exact(16);
+        for block in &mut blocks {
+            self.block(block, 1 << 24);
+        }
+
+        let remainder = blocks.remainder();
+        self.pending[..remainder.len()].copy_from_slice(remainder);
+        self.pending_len = remainder.len();
+    }
+
+    /// Feeds zeros up to the next multiple of 16 bytes, as the AEAD layout requires
+    pub(crate) fn pad_to_block(&mut self) {
+        if self.pending_len > 0 {
+            self.update(&[0u8; 16][self.pending_len..]);
+        }
+    }
+
+    /// Consumes the authenticator and returns the tag over everything fed so far
+    pub fn finalize(mut self) -> [u8; TAG_LEN] {
+        if self.pending_len > 0 {
+            let mut block = [0u8; 16];
+            block[..self.pending_len].copy_from_slice(&self.pending[..self.pending_len]);
+            block[self.pending_len] = 1;
+            self.block(&block, 0);
+            wipe(&mut block);
+        }
+
+        let mut h = self.h;
+
+        // Fully carry h
+        let mut carry = h[1] >> 26;
+        h[1] &= LIMB_MASK;
+        for limb in &mut h[2..5] {
+            *limb += carry;
+            carry = *limb >> 26;
+            *limb &= LIMB_MASK;
+        }
+        h[0] += carry * 5;
+        carry = h[0] >> 26;
+        h[0] &= LIMB_MASK;
+        h[1] += carry;
+
+        // Compute g = h + 5 - 2^130 and keep it if it did not go negative
+        let mut g = [0u32; 5];
+        carry = 5;
+        for i in 0..4 {
+            g[i] = h[i] + carry;
+            carry = g[i] >> 26;
+            g[i] &= LIMB_MASK;
+        }
+        g[4] = h[4].wrapping_add(carry).wrapping_sub(1 << 26);
+
+        let keep_g = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !keep_g) | (g[i] & keep_g);
+        }
+
+        // Pack into 128 bits and add the pad
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+
+        let mut tag = [0u8; TAG_LEN];
+        let mut sum = 0u64;
+        for i in 0..4 {
+            sum = words[i] as u64 + self.pad[i] as u64 + (sum >> 32);
+            LittleEndian::write_u32(sum as u32, &mut tag[(i * 4)..(i * 4 + 4)]);
+        }
+
+        wipe(&mut h);
+        wipe(&mut g);
+
+        tag
+    }
+
+    /// Consumes the authenticator and checks `tag` against the tag over everything fed so far
+    ///
+    /// The comparison is constant time. Returns `Error::InvalidTag` if the
+    /// tags differ.
+    pub fn verify(self, tag: &[u8]) -> Result<(), Error> {
+        if !constant_time_eq(&self.finalize(), tag) {
+            return Err(Error::InvalidTag);
+        }
+
+        Ok(())
+    }
+
+    /// Absorbs one 16-byte block, with `high_bit` set to `1 << 24` for every full block
+    fn block(&mut self, block: &[u8], high_bit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r;
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+        let h = &mut self.h;
+        h[0] += LittleEndian::read_u32(&block[0..4]) & LIMB_MASK;
+        h[1] += (LittleEndian::read_u32(&block[3..7]) >> 2) & LIMB_MASK;
+        h[2] += (LittleEndian::read_u32(&block[6..10]) >> 4) & LIMB_MASK;
+        h[3] += (LittleEndian::read_u32(&block[9..13]) >> 6) & LIMB_MASK;
+        h[4] += (LittleEndian::read_u32(&block[12..16]) >> 8) | high_bit;
+
+        let [h0, h1, h2, h3, h4] = [h[0] as u64, h[1] as u64, h[2] as u64, h[3] as u64, h[4] as u64];
+        let (r0, r1, r2, r3, r4) = (r0 as u64, r1 as u64, r2 as u64, r3 as u64, r4 as u64);
+        let (s1, s2, s3, s4) = (s1 as u64, s2 as u64, s3 as u64, s4 as u64);
+
+        let d = [
+            h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1,
+            h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2,
+            h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3,
+            h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4,
+            h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0,
+        ];
+
+        let mut carry = 0u64;
+        for i in 0..5 {
+            let limb = d[i] + carry;
+            carry = limb >> 26;
+            h[i] = limb as u32 & LIMB_MASK;
+        }
+        h[0] += carry as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= LIMB_MASK;
+    }
+}
+
+impl Drop for Poly1305 {
+    /// Wipes the key and accumulator from memory
+    fn drop(&mut self) {
+        wipe(&mut self.r);
+        wipe(&mut self.pad);
+        wipe(&mut self.h);
+        wipe(&mut self.pending);
+    }
+}
+
+/// Returns the RFC 8439 Poly1305 tag of `message` under the one-time `key`
+///
+/// A key must never authenticate two different messages: anyone seeing both
+/// tags can forge new ones. Derive a fresh key per message, as the AEAD
+/// constructions do from the cipher's first keystream block.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+pub fn authenticate(key: &[u8], message: &[u8]) -> [u8; TAG_LEN] {
+    let mut mac = Poly1305::new(key);
+    mac.update(message);
+
+    mac.finalize()
+}
+
+/// Checks `tag` against the Poly1305 tag of `message` in constant time
+///
+/// Returns `Error::InvalidTag` if they differ.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+pub fn verify(key: &[u8], message: &[u8], tag: &[u8]) -> Result<(), Error> {
+    let mut mac = Poly1305::new(key);
+    mac.update(message);
+
+    mac.verify(tag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_matches_the_rfc_8439_vector() {
+        let key: [u8; 32] = [
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33,
+            0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5, 0x06, 0xa8,
+            0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd,
+            0x4a, 0xbf, 0xf6, 0xaf, 0x41, 0x49, 0xf5, 0x1b
+        ];
+
+        let expected: [u8; 16] = [
+            0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6,
+            0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01, 0x27, 0xa9
+        ];
+
+        let message = b"Cryptographic Forum Research Group";
+        assert_eq!(authenticate(&key, message), expected);
+        assert_eq!(verify(&key, message, &expected), Ok(()));
+        assert_eq!(verify(&key, &message[1..], &expected), Err(Error::InvalidTag));
+        assert_eq!(verify(&key, message, &expected[..15]), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_reduces_modulo_p() {
+        let mut key = [0u8; 32];
+        key[0] = 2;
+
+        // Appendix A.3 test vectors 5 and 6: h reaches p and wraps to 3
+        assert_eq!(authenticate(&key, &[0xff; 16]), [3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        key[16..32].copy_from_slice(&[0xff; 16]);
+        let mut message = [0u8; 16];
+        message[0] = 2;
+        assert_eq!(authenticate(&key, &message), [3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn it_handles_partial_blocks_across_updates() {
+        let key = [0x42u8; 32];
+        let message = [0x5au8; 100];
+
+        let mut mac = Poly1305::new(&key);
+        mac.update(&message[..3]);
+        mac.update(&message[3..40]);
+        mac.update(&message[40..]);
+
+        assert_eq!(mac.finalize(), authenticate(&key, &message));
+    }
+
+    #[test]
+    fn it_verifies_incrementally() {
+        let key = [0x42u8; 32];
+        let tag = authenticate(&key, b"streamed in three parts");
+
+        let mut mac = Poly1305::new(&key);
+        mac.update(b"streamed");
+        mac.update(b" in three");
+        mac.update(b" parts");
+        assert_eq!(mac.verify(&tag), Ok(()));
+
+        let mut mac = Poly1305::new(&key);
+        mac.update(b"streamed in three part");
+        assert_eq!(mac.verify(&tag), Err(Error::InvalidTag));
+    }
+}
@@ -0,0 +1,62 @@
+#![forbid(unsafe_code)]
+
+use crate::byte_manipulation::{Endian, LittleEndian};
+use crate::chacha20::ChaCha20;
+
+/// Size of the ciphertext sample taken from a QUIC packet
+pub const SAMPLE_LEN: usize = 16;
+
+/// Size of the mask applied to the first byte and the packet number of a QUIC header
+pub const MASK_LEN: usize = 5;
+
+/// Returns the QUIC header protection mask for `sample`, per RFC 9001 §5.4.4
+///
+/// The first 4 bytes of the sample are the little endian block counter and
+/// the last 12 the nonce; the mask is the first 5 bytes of the ChaCha20
+/// keystream under `hp_key` at that position. The caller applies it to the
+/// header as RFC 9001 §5.4.1 describes.
+///
+/// # Panics
+///
+/// The function will panic if `hp_key` is not of size 32
+/// The function will panic if `sample` is not of size 16
+pub fn header_protection_mask(hp_key: &[u8], sample: &[u8]) -> [u8; MASK_LEN] {
+    assert_eq!(sample.len(), SAMPLE_LEN);
+
+    let counter = LittleEndian::read_u32(&sample[0..4]);
+    let mut mask = [0u8; MASK_LEN];
+    ChaCha20::new_with_raw_nonce(hp_key, &sample[4..16], counter).apply_keystream(&mut mask);
+
+    mask
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_masks_the_rfc_9001_short_header() {
+        let hp_key: [u8; 32] = [
+            0x25, 0xa2, 0x82, 0xb9, 0xe8, 0x2f, 0x06, 0xf2,
+            0x1f, 0x48, 0x89, 0x17, 0xa4, 0xfc, 0x8f, 0x1b,
+            0x73, 0x57, 0x36, 0x85, 0x60, 0x85, 0x97, 0xd0,
+            0xef, 0xcb, 0x07, 0x6b, 0x0a, 0xb7, 0xa7, 0xa4
+        ];
+        let sample: [u8; 16] = [
+            0x5e, 0x5c, 0xd5, 0x5c, 0x41, 0xf6, 0x90, 0x80,
+            0x57, 0x5d, 0x79, 0x99, 0xc2, 0x5a, 0x5b, 0xfb
+        ];
+
+        assert_eq!(header_protection_mask(&hp_key, &sample), [0xae, 0xfe, 0xfe, 0x7d, 0x03]);
+    }
+
+    #[test]
+    fn it_uses_the_sample_as_counter_and_nonce() {
+        let sample = [1, 0, 0, 0, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9];
+
+        let mut expected = [0u8; 69];
+        ChaCha20::new_with_raw_nonce(&[3u8; 32], &[9u8; 12], 0).apply_keystream(&mut expected);
+
+        assert_eq!(header_protection_mask(&[3u8; 32], &sample), expected[64..69]);
+    }
+}
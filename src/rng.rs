@@ -0,0 +1,702 @@
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "getrandom")]
+use std::cell::RefCell;
+#[cfg(feature = "getrandom")]
+use std::fs::File;
+#[cfg(feature = "getrandom")]
+use std::io::{self, Read};
+
+use crate::backend::{ChaChaCore, Scalar};
+use crate::byte_manipulation::{wipe, Endian, LittleEndian};
+use crate::chacha20::ChaCha20;
+
+/// Words of keystream produced by one block
+const BLOCK_WORDS: usize = 16;
+
+/// Keystream generated by each `FastEraseRng` refill, the next key included
+const ERASE_BUFFER_LEN: usize = 768;
+
+/// Bytes the thread-local generator produces between reseeds
+#[cfg(feature = "getrandom")]
+const THREAD_RESEED_INTERVAL: u64 = 64 * 1024;
+
+#[cfg(feature = "getrandom")]
+thread_local! {
+    /// Generator behind `random_bytes` and `random_u64`, seeded on first use in each thread
+    static THREAD_RNG: RefCell<ReseedingChaChaRng<OsEntropy>> = RefCell::new(ReseedingChaChaRng::new(OsEntropy, THREAD_RESEED_INTERVAL));
+}
+
+/// Word positions wrap at 2^68, after 2^64 blocks of 16 words
+const WORD_POS_MASK: u128 = (1 << 68) - 1;
+
+/// Cryptographically secure random number generator running ChaCha with `R` rounds
+///
+/// Produces the same values as `rand_chacha`: the seed is the key, words 12
+/// and 13 of the state hold a 64-bit block counter and words 14 and 15 the
+/// stream id, 0 unless `set_stream` picks another. Output is read from the
+/// keystream one little endian `u32` word at a time; `next_u64` takes the
+/// low half first, and `fill_bytes` discards what is left of its last word.
+///
+/// For the same seed, stream and word position the output is guaranteed to
+/// stay identical to `rand_chacha`'s generator with the same round count,
+/// for every mix of `next_u32`, `next_u64` and `fill_bytes` calls, so runs
+/// recorded with `rand_chacha` seeds can be reproduced.
+///
+/// The methods mirror `RngCore` and `SeedableRng` from `rand_core`, so a
+/// wrapper implementing those traits only has to forward them. Use the
+/// `ChaCha20Rng` alias unless speed matters more than security margin.
+pub struct ChaChaRng<const R: usize> {
+    state: [u32; 16],
+    /// Keystream block generated ahead of use
+    buffer: [u32; BLOCK_WORDS],
+    /// Words of `buffer` already consumed, `BLOCK_WORDS` when it is empty
+    index: usize,
+}
+
+/// ChaCha RNG reduced to 8 rounds
+pub type ChaCha8Rng = ChaChaRng<8>;
+
+/// ChaCha RNG reduced to 12 rounds
+pub type ChaCha12Rng = ChaChaRng<12>;
+
+/// The standard ChaCha20 RNG
+pub type ChaCha20Rng = ChaChaRng<20>;
+
+impl<const R: usize> ChaChaRng<R> {
+    /// Rejects round counts the block function cannot apply when `from_seed` is instantiated
+    const VALID_ROUNDS: () = assert!(R >= 2 && R % 2 == 0, "ChaCha rounds must be even and at least 2");
+
+    /// Returns a new instance of ChaChaRng keyed with `seed`
+    ///
+    /// The seed must come from a secure random source: the whole output is
+    /// determined by it.
+    pub fn from_seed(seed: [u8; 32]) -> ChaChaRng<R> {
+        let () = Self::VALID_ROUNDS;
+
+        let mut state = [0u32; 16];
+        state[0..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+        LittleEndian::read_u32_into(&seed, &mut state[4..12]);
+
+        ChaChaRng { state, buffer: [0u32; BLOCK_WORDS], index: BLOCK_WORDS }
+    }
+
+    /// Returns a new instance of ChaChaRng seeded from the operating system
+    ///
+    /// Returns the I/O error if the system generator cannot be read.
+    #[cfg(feature = "getrandom")]
+    pub fn from_os_entropy() -> io::Result<ChaChaRng<R>> {
+        let mut seed = [0u8; 32];
+        OsEntropy::read_seed(&mut seed)?;
+        let rng = ChaChaRng::from_seed(seed);
+        wipe(&mut seed);
+
+        Ok(rng)
+    }
+
+    /// Returns the next random `u32`
+    pub fn next_u32(&mut self) -> u32 {
+        if self.index == BLOCK_WORDS {
+            self.refill();
+        }
+
+        let word = self.buffer[self.index];
+        self.index += 1;
+
+        word
+    }
+
+    /// Returns the next random `u64`, made of two words with the low one first
+    pub fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+
+        (high << 32) | low
+    }
+
+    /// Fills `dest` with random bytes
+    ///
+    /// Whole words are consumed: a length that is not a multiple of 4 skips
+    /// the rest of the last word, as `rand_chacha` does.
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let word = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+
+    /// Returns a child generator seeded with the next 32 bytes of output
+    ///
+    /// The parent moves past those bytes, so the child's seed never appears
+    /// in the parent's output and every child is independent of the parent
+    /// and of its siblings. Unlike `ChaCha::fork`, the child does not repeat
+    /// the parent's stream. Hand one to each worker of a thread pool.
+    pub fn split(&mut self) -> ChaChaRng<R> {
+        let mut seed = [0u8; 32];
+        self.fill_bytes(&mut seed);
+        let child = ChaChaRng::from_seed(seed);
+        wipe(&mut seed);
+
+        child
+    }
+
+    /// Returns the index of the next output word in the keystream
+    ///
+    /// Positions count 32-bit words from the start of the stream, as in
+    /// `rand_chacha`, and wrap at 2^68 along with the block counter.
+    pub fn get_word_pos(&self) -> u128 {
+        let block = ((self.state[13] as u64) << 32 | self.state[12] as u64).wrapping_sub(1);
+
+        (block as u128 * BLOCK_WORDS as u128 + self.index as u128) & WORD_POS_MASK
+    }
+
+    /// Moves to word `word_pos` of the keystream, so the next output starts there
+    ///
+    /// Only the low 68 bits of `word_pos` are used, see `get_word_pos`.
+    pub fn set_word_pos(&mut self, word_pos: u128) {
+        let block = (word_pos / BLOCK_WORDS as u128) as u64;
+        self.state[12] = block as u32;
+        self.state[13] = (block >> 32) as u32;
+
+        self.refill();
+        self.index = (word_pos % BLOCK_WORDS as u128) as usize;
+    }
+
+    /// Returns the generator switched to stream `stream`, see `set_stream`
+    pub fn stream(mut self, stream: u64) -> ChaChaRng<R> {
+        self.set_stream(stream);
+
+        self
+    }
+
+    /// Switches to stream `stream` at the same word position
+    ///
+    /// The stream id fills the nonce words of the state, so each of the 2^64
+    /// streams of a seed is an independent keystream: workers sharing a seed
+    /// can each take their own stream instead of drawing new seeds.
+    pub fn set_stream(&mut self, stream: u64) {
+        let word_pos = self.get_word_pos();
+        self.state[14] = stream as u32;
+        self.state[15] = (stream >> 32) as u32;
+
+        if self.index != BLOCK_WORDS {
+            self.set_word_pos(word_pos);
+        }
+    }
+
+    /// Returns the current stream id, 0 unless changed with `set_stream`
+    pub fn get_stream(&self) -> u64 {
+        (self.state[15] as u64) << 32 | self.state[14] as u64
+    }
+
+    /// Generates the block at the current counter and advances the counter
+    fn refill(&mut self) {
+        self.buffer = Scalar::block(&self.state, R);
+        self.index = 0;
+
+        let counter = ((self.state[13] as u64) << 32 | self.state[12] as u64).wrapping_add(1);
+        self.state[12] = counter as u32;
+        self.state[13] = (counter >> 32) as u32;
+    }
+}
+
+impl<const R: usize> Drop for ChaChaRng<R> {
+    /// Wipes the seed and the buffered output from memory
+    fn drop(&mut self) {
+        wipe(&mut self.state);
+        wipe(&mut self.buffer);
+    }
+}
+
+/// Bernstein's fast-key-erasure RNG
+///
+/// Each refill runs ChaCha20 under the current key and a zero nonce for 768
+/// bytes: the first 32 replace the key at once and the rest is handed out,
+/// each byte wiped from the buffer as it is returned. Capturing the state
+/// therefore reveals nothing about earlier output, which `ChaChaRng` cannot
+/// offer since its key stays the same. Output is not compatible with any
+/// other generator.
+pub struct FastEraseRng {
+    key: [u8; 32],
+    buffer: [u8; ERASE_BUFFER_LEN],
+    /// Bytes of `buffer` already handed out or used as the key
+    used: usize,
+}
+
+impl FastEraseRng {
+    /// Returns a new instance of FastEraseRng keyed with `seed`
+    ///
+    /// The seed is retired right away by the first refill.
+    pub fn from_seed(seed: [u8; 32]) -> FastEraseRng {
+        let mut rng = FastEraseRng { key: seed, buffer: [0u8; ERASE_BUFFER_LEN], used: ERASE_BUFFER_LEN };
+        rng.refill();
+
+        rng
+    }
+
+    /// Returns the next random `u32`, from 4 little endian bytes
+    pub fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Returns the next random `u64`, from 8 little endian bytes
+    pub fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Fills `dest` with random bytes, wiping them from the buffer
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut done = 0;
+
+        while done < dest.len() {
+            if self.used == ERASE_BUFFER_LEN {
+                self.refill();
+            }
+
+            let take = (dest.len() - done).min(ERASE_BUFFER_LEN - self.used);
+            let output = &mut self.buffer[self.used..(self.used + take)];
+            dest[done..(done + take)].copy_from_slice(output);
+            wipe(output);
+
+            self.used += take;
+            done += take;
+        }
+    }
+
+    /// Replaces the key with the start of its own keystream and buffers the rest
+    fn refill(&mut self) {
+        self.buffer = [0u8; ERASE_BUFFER_LEN];
+        ChaCha20::new_with_raw_nonce(&self.key, &[0u8; 12], 0).apply_keystream(&mut self.buffer);
+
+        self.key.copy_from_slice(&self.buffer[0..32]);
+        wipe(&mut self.buffer[0..32]);
+        self.used = 32;
+    }
+}
+
+impl Drop for FastEraseRng {
+    /// Wipes the key and the remaining output from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+        wipe(&mut self.buffer);
+    }
+}
+
+/// Source of the seeds of a `ReseedingChaChaRng`
+pub trait EntropySource {
+    /// Fills `seed` with bytes from a secure random source
+    ///
+    /// There is no way to report failure: a source that cannot provide fresh
+    /// entropy must panic rather than return predictable bytes.
+    fn fill_seed(&mut self, seed: &mut [u8; 32]);
+}
+
+/// Seeds from the operating system's generator, `/dev/urandom`
+///
+/// Panics in `fill_seed` if the generator cannot be read.
+#[cfg(feature = "getrandom")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsEntropy;
+
+#[cfg(feature = "getrandom")]
+impl OsEntropy {
+    /// Fills `seed` from the system generator
+    fn read_seed(seed: &mut [u8; 32]) -> io::Result<()> {
+        File::open("/dev/urandom")?.read_exact(seed)
+    }
+}
+
+#[cfg(feature = "getrandom")]
+impl EntropySource for OsEntropy {
+    fn fill_seed(&mut self, seed: &mut [u8; 32]) {
+        OsEntropy::read_seed(seed).expect("failed to read the operating system's random generator");
+    }
+}
+
+/// `ChaCha20Rng` that reseeds itself from an `EntropySource`
+///
+/// A new seed is drawn before the first output once `interval` bytes have
+/// been produced since the last seed, so a compromise of the state only
+/// exposes output up to the next reseed. With the `std` feature it also
+/// reseeds when the process id changed, so the parent and child of a
+/// `fork` never share output.
+pub struct ReseedingChaChaRng<S: EntropySource> {
+    rng: ChaCha20Rng,
+    source: S,
+    interval: u64,
+    generated: u64,
+    #[cfg(feature = "std")]
+    process_id: u32,
+}
+
+impl<S: EntropySource> ReseedingChaChaRng<S> {
+    /// Returns a new instance of ReseedingChaChaRng seeded from `source`
+    ///
+    /// An `interval` of 0 reseeds before every call.
+    pub fn new(mut source: S, interval: u64) -> ReseedingChaChaRng<S> {
+        let mut seed = [0u8; 32];
+        source.fill_seed(&mut seed);
+        let rng = ChaCha20Rng::from_seed(seed);
+        wipe(&mut seed);
+
+        ReseedingChaChaRng {
+            rng,
+            source,
+            interval,
+            generated: 0,
+            #[cfg(feature = "std")]
+            process_id: std::process::id(),
+        }
+    }
+
+    /// Replaces the state with a fresh seed from the source
+    pub fn reseed(&mut self) {
+        let mut seed = [0u8; 32];
+        self.source.fill_seed(&mut seed);
+        self.rng = ChaCha20Rng::from_seed(seed);
+        wipe(&mut seed);
+
+        self.generated = 0;
+        #[cfg(feature = "std")]
+        {
+            self.process_id = std::process::id();
+        }
+    }
+
+    /// Returns the next random `u32`, see `ChaChaRng::next_u32`
+    pub fn next_u32(&mut self) -> u32 {
+        self.prepare(4);
+        self.rng.next_u32()
+    }
+
+    /// Returns the next random `u64`, see `ChaChaRng::next_u64`
+    pub fn next_u64(&mut self) -> u64 {
+        self.prepare(8);
+        self.rng.next_u64()
+    }
+
+    /// Fills `dest` with random bytes, see `ChaChaRng::fill_bytes`
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.prepare(dest.len() as u64);
+        self.rng.fill_bytes(dest);
+    }
+
+    /// Reseeds if needed before `len` bytes are produced
+    fn prepare(&mut self, len: u64) {
+        #[cfg(feature = "std")]
+        let forked = self.process_id != std::process::id();
+        #[cfg(not(feature = "std"))]
+        let forked = false;
+
+        if forked || self.generated >= self.interval {
+            self.reseed();
+        }
+        self.generated = self.generated.saturating_add(len);
+    }
+}
+
+/// Returns `len` random bytes from the thread-local generator
+///
+/// Each thread gets its own `ReseedingChaChaRng`, seeded from `OsEntropy`
+/// on first use and reseeded every 64 KiB and after a `fork`. Panics if the
+/// operating system's generator cannot be read.
+#[cfg(feature = "getrandom")]
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    THREAD_RNG.with(|rng| rng.borrow_mut().fill_bytes(&mut bytes));
+
+    bytes
+}
+
+/// Returns a random `u64` from the thread-local generator, see `random_bytes`
+#[cfg(feature = "getrandom")]
+pub fn random_u64() -> u64 {
+    THREAD_RNG.with(|rng| rng.borrow_mut().next_u64())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chacha20::ChaCha8;
+    use crate::chacha20_legacy::ChaCha20Legacy;
+
+    #[test]
+    fn it_matches_rand_chacha() {
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+        let mut words = [0u32; 32];
+        for word in words.iter_mut() {
+            *word = rng.next_u32();
+        }
+
+        assert_eq!(&words[0..4], &[0xade0b876, 0x903df1a0, 0xe56a5d40, 0x28bd8653]);
+        assert_eq!(&words[16..20], &[0xbee7079f, 0x7a385155, 0x7c97ba98, 0x0d082d73]);
+        assert_eq!(&words[28..32], &[0x281fed31, 0x45fb0a51, 0x1f0ae1ac, 0x6f4d794b]);
+
+        let mut seed = [0u8; 32];
+        seed[31] = 1;
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        for _ in 0..16 {
+            rng.next_u32();
+        }
+        assert_eq!(rng.next_u32(), 0x2452eb3a);
+        assert_eq!(rng.next_u32(), 0x9249f8ec);
+    }
+
+    #[test]
+    fn it_reproduces_the_rand_chacha_test_vectors() {
+        let mut seed = [0u8; 32];
+        for (i, word) in seed.chunks_mut(4).enumerate() {
+            word[0] = i as u8;
+        }
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        let mut every_17th = [0u32; 16];
+        for word in every_17th.iter_mut() {
+            *word = rng.next_u32();
+            for _ in 0..16 {
+                rng.next_u32();
+            }
+        }
+        assert_eq!(every_17th, [
+            0xf225c81a, 0x6ab1be57, 0x04d42951, 0x70858036,
+            0x49884684, 0x64efec72, 0x4be2d186, 0x3615b384,
+            0x11cfa18e, 0xd3c50049, 0x75c775f6, 0x434c6530,
+            0x2c5bad8f, 0x898881dc, 0x5f1c86d9, 0xc1f8e7f4
+        ]);
+
+        let mut seed = [0u8; 32];
+        for (i, word) in seed.chunks_mut(8).enumerate() {
+            word[0] = i as u8;
+        }
+        assert_eq!(ChaCha20Rng::from_seed(seed).next_u32(), 137206642);
+
+        let mut bytes = [0u8; 32];
+        ChaCha20Rng::from_seed([0u8; 32]).fill_bytes(&mut bytes);
+        assert_eq!(bytes, [
+            118, 184, 224, 173, 160, 241, 61, 144, 64, 93, 106, 229, 83, 134, 189, 40,
+            189, 210, 25, 184, 160, 141, 237, 26, 168, 54, 239, 204, 139, 119, 13, 199
+        ]);
+    }
+
+    #[test]
+    fn it_reproduces_runs_after_seeking_and_switching_streams() {
+        let seed = [0x5a; 32];
+
+        let mut recorded = ChaCha20Rng::from_seed(seed);
+        let mut skipped = [0u8; 7];
+        for _ in 0..21 {
+            recorded.next_u64();
+        }
+        recorded.fill_bytes(&mut skipped);
+        recorded.set_stream(9);
+        let expected = [recorded.next_u32(), recorded.next_u32(), recorded.next_u32()];
+
+        let mut replayed = ChaCha20Rng::from_seed(seed).stream(9);
+        replayed.set_word_pos(21 * 2 + 2);
+        assert_eq!([replayed.next_u32(), replayed.next_u32(), replayed.next_u32()], expected);
+    }
+
+    #[test]
+    fn it_reads_words_like_rand_core() {
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+        assert_eq!(rng.next_u64(), 0x903df1a0_ade0b876);
+
+        let mut bytes = [0u8; 6];
+        rng.fill_bytes(&mut bytes);
+        assert_eq!(bytes, [0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86]);
+        assert_eq!(rng.next_u32(), 0xb819d2bd);
+
+        for _ in 0..10 {
+            rng.next_u32();
+        }
+        assert_eq!(rng.next_u64(), 0xbee7079f_8665eeb2);
+    }
+
+    #[test]
+    fn it_moves_to_word_positions() {
+        let mut keystream = [0u8; 256];
+        ChaCha20Legacy::new(&[0u8; 32], &[0u8; 8], 0).apply_keystream(&mut keystream);
+        let word = |i: usize| LittleEndian::read_u32(&keystream[(i * 4)..(i * 4 + 4)]);
+
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+        assert_eq!(rng.get_word_pos(), 0);
+        for _ in 0..17 {
+            rng.next_u32();
+        }
+        assert_eq!(rng.get_word_pos(), 17);
+
+        rng.set_word_pos(43);
+        assert_eq!(rng.get_word_pos(), 43);
+        assert_eq!(rng.next_u32(), word(43));
+        assert_eq!(rng.get_word_pos(), 44);
+
+        rng.set_word_pos(16);
+        assert_eq!(rng.next_u64(), (word(17) as u64) << 32 | word(16) as u64);
+
+        rng.set_word_pos(3);
+        assert_eq!(rng.next_u32(), word(3));
+    }
+
+    #[test]
+    fn it_wraps_word_positions_at_the_end_of_the_stream() {
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+
+        rng.set_word_pos(WORD_POS_MASK);
+        assert_eq!(rng.get_word_pos(), WORD_POS_MASK);
+        rng.next_u32();
+        assert_eq!(rng.get_word_pos(), 0);
+        assert_eq!(rng.next_u32(), 0xade0b876);
+
+        rng.set_word_pos(WORD_POS_MASK + 1 + 5);
+        assert_eq!(rng.get_word_pos(), 5);
+    }
+
+    #[test]
+    fn it_switches_streams() {
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]).stream(2 << 56);
+        assert_eq!(rng.get_stream(), 2 << 56);
+        assert_eq!(rng.next_u32(), 0x374dc6c2);
+        assert_eq!(rng.next_u32(), 0x3736d58c);
+
+        let mut keystream = [0u8; 128];
+        ChaCha20Legacy::new(&[0u8; 32], &7u64.to_le_bytes(), 0).apply_keystream(&mut keystream);
+
+        rng.set_stream(7);
+        assert_eq!(rng.get_word_pos(), 2);
+        assert_eq!(rng.next_u32(), LittleEndian::read_u32(&keystream[8..12]));
+
+        rng.set_word_pos(16);
+        assert_eq!(rng.next_u32(), LittleEndian::read_u32(&keystream[64..68]));
+    }
+
+    #[test]
+    fn it_splits_independent_children() {
+        let mut parent = ChaCha20Rng::from_seed([0u8; 32]);
+        let mut first = parent.split();
+        let mut second = parent.split();
+        assert_eq!(parent.get_word_pos(), 16);
+
+        let mut seeds = [0u8; 64];
+        ChaCha20Rng::from_seed([0u8; 32]).fill_bytes(&mut seeds);
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&seeds[0..32]);
+        assert_eq!(first.next_u64(), ChaCha20Rng::from_seed(seed).next_u64());
+
+        assert_ne!(first.next_u64(), second.next_u64());
+        assert_ne!(second.next_u64(), parent.next_u64());
+    }
+
+    #[test]
+    fn it_erases_the_key_on_every_refill() {
+        let mut first = [0u8; ERASE_BUFFER_LEN];
+        ChaCha20::new_with_raw_nonce(&[0x11; 32], &[0u8; 12], 0).apply_keystream(&mut first);
+        let mut second = [0u8; ERASE_BUFFER_LEN];
+        ChaCha20::new_with_raw_nonce(&first[0..32], &[0u8; 12], 0).apply_keystream(&mut second);
+
+        let mut rng = FastEraseRng::from_seed([0x11; 32]);
+        assert_eq!(&rng.key[..], &first[0..32]);
+
+        let mut output = [0u8; ERASE_BUFFER_LEN - 32 + 10];
+        rng.fill_bytes(&mut output);
+        assert_eq!(&output[..(ERASE_BUFFER_LEN - 32)], &first[32..]);
+        assert_eq!(&output[(ERASE_BUFFER_LEN - 32)..], &second[32..42]);
+        assert_eq!(&rng.key[..], &second[0..32]);
+        assert_eq!(u32::from_le_bytes([second[42], second[43], second[44], second[45]]), rng.next_u32());
+    }
+
+    #[test]
+    fn it_wipes_output_once_returned() {
+        let mut rng = FastEraseRng::from_seed([0x11; 32]);
+        rng.next_u64();
+
+        assert!(rng.buffer[0..40].iter().all(|byte| *byte == 0));
+        assert!(rng.buffer[40..].iter().any(|byte| *byte != 0));
+    }
+
+    /// Hands out seeds filled with 1, then 2, and so on
+    struct CountingSource(u8);
+
+    impl EntropySource for CountingSource {
+        fn fill_seed(&mut self, seed: &mut [u8; 32]) {
+            self.0 += 1;
+            *seed = [self.0; 32];
+        }
+    }
+
+    #[test]
+    fn it_reseeds_after_the_interval() {
+        let mut rng = ReseedingChaChaRng::new(CountingSource(0), 16);
+        let mut first = ChaCha20Rng::from_seed([1u8; 32]);
+        let mut second = ChaCha20Rng::from_seed([2u8; 32]);
+
+        for _ in 0..4 {
+            assert_eq!(rng.next_u32(), first.next_u32());
+        }
+        assert_eq!(rng.next_u64(), second.next_u64());
+
+        let mut bytes = [0u8; 20];
+        let mut expected = [0u8; 20];
+        rng.fill_bytes(&mut bytes);
+        second.fill_bytes(&mut expected);
+        assert_eq!(bytes, expected);
+
+        assert_eq!(rng.next_u32(), ChaCha20Rng::from_seed([3u8; 32]).next_u32());
+    }
+
+    #[test]
+    fn it_reseeds_on_request() {
+        let mut rng = ReseedingChaChaRng::new(CountingSource(0), u64::MAX);
+        rng.next_u32();
+        rng.reseed();
+
+        assert_eq!(rng.next_u32(), ChaCha20Rng::from_seed([2u8; 32]).next_u32());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_reseeds_in_a_forked_child() {
+        let mut rng = ReseedingChaChaRng::new(CountingSource(0), u64::MAX);
+        rng.process_id = rng.process_id.wrapping_add(1);
+
+        assert_eq!(rng.next_u32(), ChaCha20Rng::from_seed([2u8; 32]).next_u32());
+        assert_eq!(rng.process_id, std::process::id());
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn it_seeds_from_the_operating_system() {
+        let mut first = ChaCha20Rng::from_os_entropy().unwrap();
+        let mut second = ChaCha20Rng::from_os_entropy().unwrap();
+        assert_ne!(first.next_u64(), second.next_u64());
+
+        let mut rng = ReseedingChaChaRng::new(OsEntropy, 1024);
+        assert_ne!(rng.next_u64(), first.next_u64());
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn it_serves_random_values_from_every_thread() {
+        assert_eq!(random_bytes(100).len(), 100);
+        assert_ne!(random_bytes(16), random_bytes(16));
+        assert_ne!(random_u64(), random_u64());
+
+        let here = random_u64();
+        let there = std::thread::spawn(random_u64).join().unwrap();
+        assert_ne!(here, there);
+    }
+
+    #[test]
+    fn it_applies_the_round_count() {
+        let expected = ChaCha8::new_with_raw_nonce(&[0u8; 32], &[0u8; 12], 0).next();
+
+        let mut rng = ChaCha8Rng::from_seed([0u8; 32]);
+        for word in expected.iter() {
+            assert_eq!(rng.next_u32(), *word);
+        }
+    }
+}
@@ -0,0 +1,409 @@
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+use crate::arx::{add_input, Arx};
+use crate::byte_manipulation::{wipe, xor_in_place, Endian, LittleEndian};
+use crate::chacha20::ChaCha20;
+
+/// "expand 32-byte k", placed on the diagonal of the Salsa20 state
+const SIGMA: &[u8; 16] = b"expand 32-byte k";
+
+/// Number of rounds used by Salsa20/20
+const ROUNDS: usize = 20;
+
+/// Salsa20 stream cipher with an 8-byte nonce and a 64-bit block counter
+///
+/// The sibling of ChaCha20 by the same author, built from the same add, rotate
+/// and XOR operations but with a different quarter round and state layout:
+/// the constants sit on the diagonal, the key around them and the nonce and
+/// counter in words 6 to 9. Successive `apply_keystream` calls consume one
+/// contiguous keystream.
+pub struct Salsa20 {
+    state: [u32; 16],
+    buffer: [u8; 64],
+    used: usize,
+}
+
+impl Salsa20 {
+    /// Returns a new instance of Salsa20 starting at block `counter`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `nonce` is not of size 8
+    pub fn new(key: &[u8], nonce: &[u8], counter: u64) -> Salsa20 {
+        assert_eq!(key.len(), 32);
+        assert_eq!(nonce.len(), 8);
+
+        let mut constants = [0u32; 4];
+        LittleEndian::read_u32_into(SIGMA, &mut constants);
+
+        let mut state = [0u32; 16];
+        state[0] = constants[0];
+        state[5] = constants[1];
+        state[10] = constants[2];
+        state[15] = constants[3];
+
+        LittleEndian::read_u32_into(&key[0..16], &mut state[1..5]);
+        LittleEndian::read_u32_into(&key[16..32], &mut state[11..15]);
+        LittleEndian::read_u32_into(nonce, &mut state[6..8]);
+
+        let mut salsa = Salsa20 { state, buffer: [0u8; 64], used: 64 };
+        salsa.set_counter(counter);
+
+        salsa
+    }
+
+    /// Encrypts or decrypts `buf` in place
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+        let mut done = 0;
+
+        while done < buf.len() {
+            if self.used == 64 {
+                self.refill();
+            }
+
+            let take = (buf.len() - done).min(64 - self.used);
+            xor_in_place(&mut buf[done..(done + take)], &self.buffer[self.used..(self.used + take)]);
+
+            self.used += take;
+            done += take;
+        }
+    }
+
+    /// Moves to byte `offset` of the keystream, counted from block counter 0
+    pub fn seek(&mut self, offset: u64) {
+        wipe(&mut self.buffer);
+        self.used = 64;
+        self.set_counter(offset / 64);
+
+        let skip = (offset % 64) as usize;
+        if skip > 0 {
+            self.refill();
+            self.used = skip;
+        }
+    }
+
+    /// Returns the absolute keystream byte position, truncated to 64 bits
+    pub fn position(&self) -> u64 {
+        self.counter().wrapping_mul(64).wrapping_sub((64 - self.used) as u64)
+    }
+
+    /// Returns the block counter of the next block to generate
+    fn counter(&self) -> u64 {
+        (self.state[9] as u64) << 32 | self.state[8] as u64
+    }
+
+    /// Stores `counter` in words 8 and 9
+    fn set_counter(&mut self, counter: u64) {
+        self.state[8] = counter as u32;
+        self.state[9] = (counter >> 32) as u32;
+    }
+
+    /// Generates the next block into the keystream buffer
+    fn refill(&mut self) {
+        let mut working_state = self.state;
+        Salsa20::apply_rounds(&mut working_state, ROUNDS);
+        add_input(&mut working_state, &self.state);
+
+        LittleEndian::write_u32_into(&working_state, &mut self.buffer);
+        wipe(&mut working_state);
+
+        self.set_counter(self.counter().wrapping_add(1));
+        self.used = 0;
+    }
+
+    /// Returns the Salsa20 state for `key` with `input` in words 6 to 9
+    fn input_state(key: &[u8], input: &[u8]) -> [u32; 16] {
+        let mut salsa = Salsa20::new(key, &input[0..8], 0);
+        LittleEndian::read_u32_into(&input[8..16], &mut salsa.state[8..10]);
+
+        salsa.state
+    }
+}
+
+impl Arx for Salsa20 {
+    const DOUBLE_ROUND: [(usize, usize, usize, usize); 8] = [
+        (0, 4, 8, 12),    // col 0
+        (5, 9, 13, 1),    // col 1
+        (10, 14, 2, 6),   // col 2
+        (15, 3, 7, 11),   // col 3
+        (0, 1, 2, 3),     // row 0
+        (5, 6, 7, 4),     // row 1
+        (10, 11, 8, 9),   // row 2
+        (15, 12, 13, 14)  // row 3
+    ];
+
+    /// Single Salsa20 quarter round
+    fn quarter_round(state: &mut [u32; 16], vector: (usize, usize, usize, usize)) {
+        let (a, b, c, d) = vector;
+
+        state[b] ^= state[a].wrapping_add(state[d]).rotate_left(7);
+        state[c] ^= state[b].wrapping_add(state[a]).rotate_left(9);
+        state[d] ^= state[c].wrapping_add(state[b]).rotate_left(13);
+        state[a] ^= state[d].wrapping_add(state[c]).rotate_left(18);
+    }
+}
+
+/// XSalsa20, Salsa20 with a 24-byte nonce
+///
+/// HSalsa20 turns the key and the first 16 nonce bytes into a subkey and
+/// Salsa20 runs under it with the last 8 nonce bytes, as in NaCl's
+/// `crypto_stream`.
+#[derive(Debug)]
+pub struct XSalsa20 {
+    salsa: Salsa20,
+}
+
+impl XSalsa20 {
+    /// Returns a new instance of XSalsa20 starting at block `counter`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `nonce` is not of size 24
+    pub fn new(key: &[u8], nonce: &[u8], counter: u64) -> XSalsa20 {
+        assert_eq!(key.len(), 32);
+        assert_eq!(nonce.len(), 24);
+
+        let mut owned_key = [0u8; 32];
+        owned_key.copy_from_slice(key);
+
+        let mut input = [0u8; 16];
+        input.copy_from_slice(&nonce[0..16]);
+
+        let mut subkey = hsalsa20(&owned_key, &input);
+        let salsa = Salsa20::new(&subkey, &nonce[16..24], counter);
+        wipe(&mut subkey);
+        wipe(&mut owned_key);
+
+        XSalsa20 { salsa }
+    }
+
+    /// Encrypts or decrypts `buf` in place
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+        self.salsa.apply_keystream(buf);
+    }
+
+    /// Moves to byte `offset` of the keystream, counted from block counter 0
+    pub fn seek(&mut self, offset: u64) {
+        self.salsa.seek(offset);
+    }
+
+    /// Returns the absolute keystream byte position, truncated to 64 bits
+    pub fn position(&self) -> u64 {
+        self.salsa.position()
+    }
+}
+
+/// HSalsa20 over a 32-byte key and a 16-byte input
+///
+/// Returns words 0, 5, 10, 15 and 6 to 9 of the Salsa20 permutation without
+/// the final addition. NaCl uses it to derive XSalsa20 subkeys and to turn an
+/// X25519 shared secret into a `crypto_box` key.
+pub fn hsalsa20(key: &[u8; 32], input: &[u8; 16]) -> [u8; 32] {
+    let mut state = Salsa20::input_state(key, input);
+    Salsa20::apply_rounds(&mut state, ROUNDS);
+
+    let mut output = [0u8; 32];
+    for (i, word) in [0, 5, 10, 15, 6, 7, 8, 9].iter().enumerate() {
+        LittleEndian::write_u32(state[*word], &mut output[(i * 4)..(i * 4 + 4)]);
+    }
+    wipe(&mut state);
+
+    output
+}
+
+impl fmt::Debug for Salsa20 {
+    /// Shows the key fingerprint and the block of the next keystream byte, never the state
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut key = [0u8; 32];
+        LittleEndian::write_u32_into(&self.state[1..5], &mut key[0..16]);
+        LittleEndian::write_u32_into(&self.state[11..15], &mut key[16..32]);
+        let fingerprint = ChaCha20::fingerprint(&key);
+        wipe(&mut key);
+
+        f.debug_struct("Salsa20").field("fingerprint", &fingerprint).field("counter", &(self.position() / 64)).finish()
+    }
+}
+
+impl Drop for Salsa20 {
+    /// Wipes the key, counter, nonce and buffered keystream from memory
+    fn drop(&mut self) {
+        wipe(&mut self.state);
+        wipe(&mut self.buffer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_matches_ecrypt_set_1_vector_0() {
+        let mut key = [0u8; 32];
+        key[0] = 0x80;
+
+        let expected_0: [u8; 64] = [
+            0xe3, 0xbe, 0x8f, 0xdd, 0x8b, 0xec, 0xa2, 0xe3,
+            0xea, 0x8e, 0xf9, 0x47, 0x5b, 0x29, 0xa6, 0xe7,
+            0x00, 0x39, 0x51, 0xe1, 0x09, 0x7a, 0x5c, 0x38,
+            0xd2, 0x3b, 0x7a, 0x5f, 0xad, 0x9f, 0x68, 0x44,
+            0xb2, 0x2c, 0x97, 0x55, 0x9e, 0x27, 0x23, 0xc7,
+            0xcb, 0xbd, 0x3f, 0xe4, 0xfc, 0x8d, 0x9a, 0x07,
+            0x44, 0x65, 0x2a, 0x83, 0xe7, 0x2a, 0x9c, 0x46,
+            0x18, 0x76, 0xaf, 0x4d, 0x7e, 0xf1, 0xa1, 0x17
+        ];
+
+        let expected_192: [u8; 64] = [
+            0x57, 0xbe, 0x81, 0xf4, 0x7b, 0x17, 0xd9, 0xae,
+            0x7c, 0x4f, 0xf1, 0x54, 0x29, 0xa7, 0x3e, 0x10,
+            0xac, 0xf2, 0x50, 0xed, 0x3a, 0x90, 0xa9, 0x3c,
+            0x71, 0x13, 0x08, 0xa7, 0x4c, 0x62, 0x16, 0xa9,
+            0xed, 0x84, 0xcd, 0x12, 0x6d, 0xa7, 0xf2, 0x8e,
+            0x8a, 0xbf, 0x8b, 0xb6, 0x35, 0x17, 0xe1, 0xca,
+            0x98, 0xe7, 0x12, 0xf4, 0xfb, 0x2e, 0x1a, 0x6a,
+            0xed, 0x9f, 0xdc, 0x73, 0x29, 0x1f, 0xaa, 0x17
+        ];
+
+        let expected_448: [u8; 64] = [
+            0x69, 0x6a, 0xfc, 0xfd, 0x0c, 0xdd, 0xcc, 0x83,
+            0xc7, 0xe7, 0x7f, 0x11, 0xa6, 0x49, 0xd7, 0x9a,
+            0xcd, 0xc3, 0x35, 0x4e, 0x96, 0x35, 0xff, 0x13,
+            0x7e, 0x92, 0x99, 0x33, 0xa0, 0xbd, 0x6f, 0x53,
+            0x77, 0xef, 0xa1, 0x05, 0xa3, 0xa4, 0x26, 0x6b,
+            0x7c, 0x0d, 0x08, 0x9d, 0x08, 0xf1, 0xe8, 0x55,
+            0xcc, 0x32, 0xb1, 0x5b, 0x93, 0x78, 0x4a, 0x36,
+            0xe5, 0x6a, 0x76, 0xcc, 0x64, 0xbc, 0x84, 0x77
+        ];
+
+        let mut keystream = [0u8; 512];
+        Salsa20::new(&key, &[0u8; 8], 0).apply_keystream(&mut keystream);
+
+        assert_eq!(&keystream[0..64], &expected_0[..]);
+        assert_eq!(&keystream[192..256], &expected_192[..]);
+        assert_eq!(&keystream[448..512], &expected_448[..]);
+    }
+
+    #[test]
+    fn it_carries_into_the_upper_counter_word() {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+
+        let nonce = [0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+
+        let expected: [u8; 128] = [
+            0x4a, 0x37, 0x6c, 0x5b, 0x7a, 0x5a, 0x69, 0xaf,
+            0x04, 0x8a, 0x2d, 0xc1, 0xa1, 0xd5, 0xee, 0xdb,
+            0xd9, 0x99, 0xa9, 0x83, 0xef, 0x9c, 0xbb, 0xa9,
+            0xa0, 0x89, 0x86, 0x48, 0x79, 0x35, 0x7b, 0xff,
+            0x82, 0xd7, 0xcc, 0xc1, 0xfb, 0x8e, 0x93, 0x99,
+            0x31, 0x62, 0xf9, 0x1b, 0xd2, 0x38, 0x3b, 0x7c,
+            0x89, 0x6f, 0x6e, 0x5b, 0xa1, 0x8c, 0xfc, 0xa6,
+            0x0d, 0xb7, 0xbe, 0xb6, 0xf0, 0x47, 0x60, 0x91,
+            0xa5, 0x00, 0xcb, 0xae, 0xc1, 0x04, 0x91, 0x47,
+            0xaf, 0xcf, 0xeb, 0x35, 0xd6, 0xb8, 0xbe, 0x42,
+            0x9e, 0x30, 0x78, 0x1b, 0xf0, 0xd6, 0x01, 0x4d,
+            0x8d, 0xa8, 0x62, 0x5a, 0x81, 0x5f, 0x81, 0x8e,
+            0xb3, 0xa0, 0xbf, 0x42, 0xd7, 0xaa, 0x92, 0x16,
+            0x0a, 0x9b, 0x9d, 0x93, 0x94, 0xba, 0xa0, 0x6b,
+            0x7a, 0xe7, 0xe4, 0xc4, 0xb2, 0x1c, 0x25, 0xbe,
+            0xb3, 0x1c, 0x8e, 0x45, 0x7e, 0xd3, 0x61, 0x94
+        ];
+
+        let mut keystream = [0u8; 128];
+        let mut salsa20 = Salsa20::new(&key, &nonce, u32::MAX as u64);
+        salsa20.apply_keystream(&mut keystream[..100]);
+        salsa20.apply_keystream(&mut keystream[100..]);
+
+        assert_eq!(&keystream[..], &expected[..]);
+        assert_eq!(salsa20.position(), (u32::MAX as u64 + 2) * 64);
+    }
+
+    #[test]
+    fn it_derives_the_nacl_box_key_with_hsalsa20() {
+        let shared: [u8; 32] = [
+            0x4a, 0x5d, 0x9d, 0x5b, 0xa4, 0xce, 0x2d, 0xe1,
+            0x72, 0x8e, 0x3b, 0xf4, 0x80, 0x35, 0x0f, 0x25,
+            0xe0, 0x7e, 0x21, 0xc9, 0x47, 0xd1, 0x9e, 0x33,
+            0x76, 0xf0, 0x9b, 0x3c, 0x1e, 0x16, 0x17, 0x42
+        ];
+
+        let expected: [u8; 32] = [
+            0x1b, 0x27, 0x55, 0x64, 0x73, 0xe9, 0x85, 0xd4,
+            0x62, 0xcd, 0x51, 0x19, 0x7a, 0x9a, 0x46, 0xc7,
+            0x60, 0x09, 0x54, 0x9e, 0xac, 0x64, 0x74, 0xf2,
+            0x06, 0xc4, 0xee, 0x08, 0x44, 0xf6, 0x83, 0x89
+        ];
+
+        assert_eq!(hsalsa20(&shared, &[0u8; 16]), expected);
+    }
+
+    #[test]
+    fn it_matches_the_nacl_xsalsa20_stream() {
+        let key: [u8; 32] = [
+            0x1b, 0x27, 0x55, 0x64, 0x73, 0xe9, 0x85, 0xd4,
+            0x62, 0xcd, 0x51, 0x19, 0x7a, 0x9a, 0x46, 0xc7,
+            0x60, 0x09, 0x54, 0x9e, 0xac, 0x64, 0x74, 0xf2,
+            0x06, 0xc4, 0xee, 0x08, 0x44, 0xf6, 0x83, 0x89
+        ];
+
+        let nonce: [u8; 24] = [
+            0x69, 0x69, 0x6e, 0xe9, 0x55, 0xb6, 0x2b, 0x73,
+            0xcd, 0x62, 0xbd, 0xa8, 0x75, 0xfc, 0x73, 0xd6,
+            0x82, 0x19, 0xe0, 0x03, 0x6b, 0x7a, 0x0b, 0x37
+        ];
+
+        let expected: [u8; 100] = [
+            0xee, 0xa6, 0xa7, 0x25, 0x1c, 0x1e, 0x72, 0x91,
+            0x6d, 0x11, 0xc2, 0xcb, 0x21, 0x4d, 0x3c, 0x25,
+            0x25, 0x39, 0x12, 0x1d, 0x8e, 0x23, 0x4e, 0x65,
+            0x2d, 0x65, 0x1f, 0xa4, 0xc8, 0xcf, 0xf8, 0x80,
+            0x30, 0x9e, 0x64, 0x5a, 0x74, 0xe9, 0xe0, 0xa6,
+            0x0d, 0x82, 0x43, 0xac, 0xd9, 0x17, 0x7a, 0xb5,
+            0x1a, 0x1b, 0xeb, 0x8d, 0x5a, 0x2f, 0x5d, 0x70,
+            0x0c, 0x09, 0x3c, 0x5e, 0x55, 0x85, 0x57, 0x96,
+            0x25, 0x33, 0x7b, 0xd3, 0xab, 0x61, 0x9d, 0x61,
+            0x57, 0x60, 0xd8, 0xc5, 0xb2, 0x24, 0xa8, 0x5b,
+            0x1d, 0x0e, 0xfe, 0x0e, 0xb8, 0xa7, 0xee, 0x16,
+            0x3a, 0xbb, 0x03, 0x76, 0x52, 0x9f, 0xcc, 0x09,
+            0xba, 0xb5, 0x06, 0xc6
+        ];
+
+        let mut keystream = [0u8; 100];
+        let mut xsalsa20 = XSalsa20::new(&key, &nonce, 0);
+        xsalsa20.apply_keystream(&mut keystream[..33]);
+        xsalsa20.apply_keystream(&mut keystream[33..]);
+
+        assert_eq!(&keystream[..], &expected[..]);
+        assert_eq!(xsalsa20.position(), 100);
+    }
+
+    #[test]
+    fn it_seeks() {
+        let mut whole = [0x33u8; 300];
+        Salsa20::new(&[7u8; 32], &[9u8; 8], 0).apply_keystream(&mut whole);
+
+        let mut salsa20 = Salsa20::new(&[7u8; 32], &[9u8; 8], 0);
+        salsa20.seek(130);
+        assert_eq!(salsa20.position(), 130);
+
+        let mut tail = [0x33u8; 170];
+        salsa20.apply_keystream(&mut tail);
+        assert_eq!(&tail[..], &whole[130..]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_keeps_the_key_out_of_debug_output() {
+        let salsa20 = Salsa20::new(&[0xAB; 32], &[0u8; 8], 5);
+        let debug = format!("{:?}", salsa20);
+
+        assert!(debug.starts_with("Salsa20 { fingerprint: ["));
+        assert!(debug.ends_with(", counter: 5 }"));
+        assert!(!debug.contains("171, 171"));
+    }
+}
@@ -0,0 +1,258 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::blake2b::blake2b;
+use crate::byte_manipulation::{constant_time_eq, wipe};
+use crate::chacha20::hchacha20;
+use crate::error::Error;
+use crate::poly1305::{Poly1305, TAG_LEN};
+#[cfg(feature = "getrandom")]
+use crate::rng::random_bytes;
+use crate::x25519::{x25519, x25519_base};
+use crate::xchacha20::XChaCha20;
+
+/// Bytes a sealed box adds to the message: the ephemeral public key and the tag
+pub const OVERHEAD: usize = 32 + TAG_LEN;
+
+/// Nonce of a sealed box: BLAKE2b-192 of the ephemeral and the recipient public keys
+fn nonce(ephemeral_public: &[u8; 32], recipient_public: &[u8]) -> [u8; 24] {
+    let mut keys = [0u8; 64];
+    keys[0..32].copy_from_slice(ephemeral_public);
+    keys[32..64].copy_from_slice(recipient_public);
+
+    let mut nonce = [0u8; 24];
+    blake2b(&keys, &mut nonce);
+
+    nonce
+}
+
+/// Derives the XChaCha20-Poly1305 box key shared by `secret_key` and `public_key`
+///
+/// Like `box_::precompute`, but the X25519 shared secret goes through
+/// `hchacha20` with a zero input instead of `hsalsa20`.
+fn shared_key(public_key: &[u8], secret_key: &[u8]) -> Result<[u8; 32], Error> {
+    let mut point = [0u8; 32];
+    point.copy_from_slice(public_key);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(secret_key);
+
+    let mut shared = x25519(&scalar, &point);
+    wipe(&mut scalar);
+
+    if constant_time_eq(&shared, &[0u8; 32]) {
+        return Err(Error::InvalidPublicKey);
+    }
+
+    let key = hchacha20(&shared, &[0u8; 16]);
+    wipe(&mut shared);
+
+    Ok(key)
+}
+
+/// Encrypts a message to the owner of `public_key` without identifying the sender
+///
+/// The output matches libsodium's `crypto_box_curve25519xchacha20poly1305_seal`:
+/// the ephemeral public key, the 16-byte Poly1305 tag and the ciphertext. The
+/// box is sealed with XChaCha20-Poly1305, keyed from X25519 between the
+/// ephemeral key and `public_key`, under a nonce derived from both public
+/// keys. Only the recipient can open it, and the sender cannot either once
+/// the ephemeral secret is gone.
+///
+/// `ephemeral_secret` must be 32 fresh bytes from a secure random source
+/// for every message, to be wiped by the caller once the box is sealed. With
+/// the `getrandom` feature, `seal_random` draws it itself.
+///
+/// Returns `Error::InvalidPublicKey` if `public_key` is of small order.
+///
+/// # Panics
+///
+/// The function will panic if `public_key` is not of size 32
+/// The function will panic if `ephemeral_secret` is not of size 32
+pub fn seal(public_key: &[u8], ephemeral_secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    assert_eq!(public_key.len(), 32);
+    assert_eq!(ephemeral_secret.len(), 32);
+
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(ephemeral_secret);
+    let ephemeral_public = x25519_base(&scalar);
+    wipe(&mut scalar);
+
+    let mut key = shared_key(public_key, ephemeral_secret)?;
+    let mut xchacha20 = XChaCha20::new(&key, &nonce(&ephemeral_public, public_key), 0);
+    wipe(&mut key);
+
+    let mut mac_key = [0u8; 32];
+    xchacha20.apply_keystream(&mut mac_key);
+
+    let mut sealed = Vec::with_capacity(OVERHEAD + plaintext.len());
+    sealed.extend_from_slice(&ephemeral_public);
+    sealed.extend_from_slice(&[0u8; TAG_LEN]);
+    sealed.extend_from_slice(plaintext);
+    xchacha20.apply_keystream(&mut sealed[OVERHEAD..]);
+
+    let mut mac = Poly1305::new(&mac_key);
+    mac.update(&sealed[OVERHEAD..]);
+    sealed[32..OVERHEAD].copy_from_slice(&mac.finalize());
+    wipe(&mut mac_key);
+
+    Ok(sealed)
+}
+
+/// Encrypts a message to the owner of `public_key` under a fresh ephemeral key
+///
+/// Same as `seal` with the ephemeral secret drawn from `random_bytes` and
+/// wiped before returning.
+///
+/// Returns `Error::InvalidPublicKey` if `public_key` is of small order.
+///
+/// # Panics
+///
+/// The function will panic if `public_key` is not of size 32
+#[cfg(feature = "getrandom")]
+pub fn seal_random(public_key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut ephemeral_secret = random_bytes(32);
+    let sealed = seal(public_key, &ephemeral_secret, plaintext);
+    wipe(&mut ephemeral_secret);
+
+    sealed
+}
+
+/// Verifies and decrypts a box sealed to the owner of `public_key` and `secret_key`
+///
+/// Accepts the output of `seal` and of libsodium's
+/// `crypto_box_curve25519xchacha20poly1305_seal`. Returns
+/// `Error::InvalidTag` without decrypting anything if the box was sealed to
+/// another key, has been modified or is too short, and
+/// `Error::InvalidPublicKey` if its ephemeral key is of small order.
+///
+/// # Panics
+///
+/// The function will panic if `public_key` is not of size 32
+/// The function will panic if `secret_key` is not of size 32
+pub fn open(public_key: &[u8], secret_key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    assert_eq!(public_key.len(), 32);
+    assert_eq!(secret_key.len(), 32);
+    if sealed.len() < OVERHEAD {
+        return Err(Error::InvalidTag);
+    }
+
+    let mut ephemeral_public = [0u8; 32];
+    ephemeral_public.copy_from_slice(&sealed[0..32]);
+
+    let mut key = shared_key(&ephemeral_public, secret_key)?;
+    let mut xchacha20 = XChaCha20::new(&key, &nonce(&ephemeral_public, public_key), 0);
+    wipe(&mut key);
+
+    let mut mac_key = [0u8; 32];
+    xchacha20.apply_keystream(&mut mac_key);
+
+    let mut mac = Poly1305::new(&mac_key);
+    mac.update(&sealed[OVERHEAD..]);
+    let tag = mac.finalize();
+    wipe(&mut mac_key);
+
+    if !constant_time_eq(&tag, &sealed[32..OVERHEAD]) {
+        return Err(Error::InvalidTag);
+    }
+
+    let mut plaintext = sealed[OVERHEAD..].to_vec();
+    xchacha20.apply_keystream(&mut plaintext);
+
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::box_::public_key;
+
+    const RECIPIENT_SECRET: [u8; 32] = [
+        0x5d, 0xab, 0x08, 0x7e, 0x62, 0x4a, 0x8a, 0x4b,
+        0x79, 0xe1, 0x7f, 0x8b, 0x83, 0x80, 0x0e, 0xe6,
+        0x6f, 0x3b, 0xb1, 0x29, 0x26, 0x18, 0xb6, 0xfd,
+        0x1c, 0x2f, 0x8b, 0x27, 0xff, 0x88, 0xe0, 0xeb
+    ];
+
+    const EPHEMERAL_SECRET: [u8; 32] = [0x33; 32];
+
+    const PLAINTEXT: &[u8] = b"credentials for the ops vault";
+
+    #[test]
+    fn it_matches_libsodium() {
+        let expected: [u8; 77] = [
+            0x7b, 0x0d, 0x47, 0xd9, 0x34, 0x27, 0xf8, 0x31,
+            0x11, 0x60, 0x78, 0x1c, 0x7c, 0x73, 0x3f, 0xd8,
+            0x9f, 0x88, 0x97, 0x0a, 0xef, 0x49, 0x0d, 0x8a,
+            0xa0, 0xee, 0x19, 0xa4, 0xcb, 0x8a, 0x1b, 0x14,
+            0x25, 0xe1, 0x5d, 0xb5, 0x5b, 0x65, 0xe2, 0x7c,
+            0xe7, 0xfb, 0xaf, 0x5a, 0xe9, 0xc7, 0x30, 0x30,
+            0x37, 0xf5, 0xbd, 0xfd, 0x51, 0xaa, 0xe5, 0xa8,
+            0x14, 0x9a, 0xe2, 0x5e, 0x6d, 0x42, 0x45, 0x0a,
+            0xce, 0x1c, 0x3b, 0xa5, 0xb6, 0x66, 0xd9, 0xaf,
+            0x34, 0x01, 0x57, 0x03, 0x5d
+        ];
+
+        let recipient_public = public_key(&RECIPIENT_SECRET);
+        let sealed = seal(&recipient_public, &EPHEMERAL_SECRET, PLAINTEXT).unwrap();
+
+        assert_eq!(&sealed[..], &expected[..]);
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &sealed), Ok(PLAINTEXT.to_vec()));
+    }
+
+    #[test]
+    fn it_opens_boxes_sealed_by_libsodium() {
+        let sealed: [u8; 77] = [
+            0xfa, 0x1c, 0xfd, 0xaa, 0xd5, 0x73, 0x64, 0xc6,
+            0x20, 0x0a, 0xc9, 0x94, 0xac, 0x9c, 0x04, 0x91,
+            0x44, 0xba, 0xce, 0xe1, 0x71, 0x87, 0xdf, 0xd9,
+            0x77, 0x96, 0x9a, 0x49, 0x0e, 0x0b, 0x11, 0x18,
+            0xe4, 0x4f, 0x50, 0x70, 0x90, 0xd4, 0xfc, 0xbd,
+            0x96, 0xd2, 0x01, 0xcc, 0xd8, 0x21, 0x90, 0x66,
+            0xec, 0x46, 0x94, 0x06, 0xe9, 0x3d, 0x2a, 0xc7,
+            0x4d, 0xca, 0x33, 0x36, 0xd8, 0xaa, 0xda, 0x7c,
+            0x77, 0x28, 0xba, 0xd6, 0x55, 0x63, 0x0c, 0x60,
+            0xce, 0x02, 0x52, 0x68, 0xd9
+        ];
+
+        let recipient_public = public_key(&RECIPIENT_SECRET);
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &sealed), Ok(PLAINTEXT.to_vec()));
+    }
+
+    #[test]
+    fn it_rejects_other_recipients_and_tampering() {
+        let recipient_public = public_key(&RECIPIENT_SECRET);
+        let mut sealed = seal(&recipient_public, &EPHEMERAL_SECRET, PLAINTEXT).unwrap();
+
+        let other_secret = [7u8; 32];
+        assert_eq!(open(&public_key(&other_secret), &other_secret, &sealed), Err(Error::InvalidTag));
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &sealed[..(OVERHEAD - 1)]), Err(Error::InvalidTag));
+
+        sealed[0] ^= 1;
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &sealed), Err(Error::InvalidTag));
+
+        sealed[0] ^= 1;
+        sealed[OVERHEAD] ^= 1;
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &sealed), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    #[cfg(feature = "getrandom")]
+    fn it_seals_under_fresh_ephemeral_keys() {
+        let recipient_public = public_key(&RECIPIENT_SECRET);
+        let first = seal_random(&recipient_public, PLAINTEXT).unwrap();
+        let second = seal_random(&recipient_public, PLAINTEXT).unwrap();
+
+        assert_ne!(first[0..32], second[0..32]);
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &first), Ok(PLAINTEXT.to_vec()));
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &second), Ok(PLAINTEXT.to_vec()));
+    }
+
+    #[test]
+    fn it_rejects_small_order_public_keys() {
+        assert_eq!(seal(&[0u8; 32], &EPHEMERAL_SECRET, PLAINTEXT), Err(Error::InvalidPublicKey));
+
+        let recipient_public = public_key(&RECIPIENT_SECRET);
+        assert_eq!(open(&recipient_public, &RECIPIENT_SECRET, &[0u8; OVERHEAD]), Err(Error::InvalidPublicKey));
+    }
+}
@@ -0,0 +1,168 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{constant_time_eq, wipe};
+use crate::error::Error;
+use crate::poly1305::Poly1305;
+use crate::salsa20::XSalsa20;
+
+/// Size of the authentication tag placed in front of the ciphertext
+pub const TAG_LEN: usize = 16;
+
+/// Encrypts and authenticates `plaintext` like libsodium's `crypto_secretbox_easy`
+///
+/// The result is the 16-byte Poly1305 tag followed by the XSalsa20
+/// ciphertext. The Poly1305 key is the first 32 bytes of the keystream and
+/// the message is encrypted with the keystream that follows, so the output is
+/// byte-for-byte what libsodium produces.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+/// The function will panic if `nonce` is not of size 24
+pub fn seal(key: &[u8], nonce: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut xsalsa20 = XSalsa20::new(key, nonce, 0);
+    let mut mac_key = [0u8; 32];
+    xsalsa20.apply_keystream(&mut mac_key);
+
+    let mut sealed = Vec::with_capacity(TAG_LEN + plaintext.len());
+    sealed.extend_from_slice(&[0u8; TAG_LEN]);
+    sealed.extend_from_slice(plaintext);
+    xsalsa20.apply_keystream(&mut sealed[TAG_LEN..]);
+
+    let mut mac = Poly1305::new(&mac_key);
+    mac.update(&sealed[TAG_LEN..]);
+    sealed[..TAG_LEN].copy_from_slice(&mac.finalize());
+    wipe(&mut mac_key);
+
+    sealed
+}
+
+/// Verifies and decrypts a box produced by `seal` or libsodium's `crypto_secretbox_easy`
+///
+/// Returns `Error::InvalidTag` without decrypting anything if the tag does
+/// not match or `sealed` is too short to hold one.
+///
+/// # Panics
+///
+/// The function will panic if `key` is not of size 32
+/// The function will panic if `nonce` is not of size 24
+pub fn open(key: &[u8], nonce: &[u8], sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut xsalsa20 = XSalsa20::new(key, nonce, 0);
+    if sealed.len() < TAG_LEN {
+        return Err(Error::InvalidTag);
+    }
+
+    let mut mac_key = [0u8; 32];
+    xsalsa20.apply_keystream(&mut mac_key);
+
+    let mut mac = Poly1305::new(&mac_key);
+    mac.update(&sealed[TAG_LEN..]);
+    let tag = mac.finalize();
+    wipe(&mut mac_key);
+
+    if !constant_time_eq(&tag, &sealed[..TAG_LEN]) {
+        return Err(Error::InvalidTag);
+    }
+
+    let mut plaintext = sealed[TAG_LEN..].to_vec();
+    xsalsa20.apply_keystream(&mut plaintext);
+
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: [u8; 32] = [
+        0x1b, 0x27, 0x55, 0x64, 0x73, 0xe9, 0x85, 0xd4,
+        0x62, 0xcd, 0x51, 0x19, 0x7a, 0x9a, 0x46, 0xc7,
+        0x60, 0x09, 0x54, 0x9e, 0xac, 0x64, 0x74, 0xf2,
+        0x06, 0xc4, 0xee, 0x08, 0x44, 0xf6, 0x83, 0x89
+    ];
+
+    const NONCE: [u8; 24] = [
+        0x69, 0x69, 0x6e, 0xe9, 0x55, 0xb6, 0x2b, 0x73,
+        0xcd, 0x62, 0xbd, 0xa8, 0x75, 0xfc, 0x73, 0xd6,
+        0x82, 0x19, 0xe0, 0x03, 0x6b, 0x7a, 0x0b, 0x37
+    ];
+
+    #[test]
+    fn it_matches_libsodium() {
+        let plaintext: [u8; 131] = [
+            0xbe, 0x07, 0x5f, 0xc5, 0x3c, 0x81, 0xf2, 0xd5,
+            0xcf, 0x14, 0x13, 0x16, 0xeb, 0xeb, 0x0c, 0x7b,
+            0x52, 0x28, 0xc5, 0x2a, 0x4c, 0x62, 0xcb, 0xd4,
+            0x4b, 0x66, 0x84, 0x9b, 0x64, 0x24, 0x4f, 0xfc,
+            0xe5, 0xec, 0xba, 0xaf, 0x33, 0xbd, 0x75, 0x1a,
+            0x1a, 0xc7, 0x28, 0xd4, 0x5e, 0x6c, 0x61, 0x29,
+            0x6c, 0xdc, 0x3c, 0x01, 0x23, 0x35, 0x61, 0xf4,
+            0x1d, 0xb6, 0x6c, 0xce, 0x31, 0x4a, 0xdb, 0x31,
+            0x0e, 0x3b, 0xe8, 0x25, 0x0c, 0x46, 0xf0, 0x6d,
+            0xce, 0xea, 0x3a, 0x7f, 0xa1, 0x34, 0x80, 0x57,
+            0xe2, 0xf6, 0x55, 0x6a, 0xd6, 0xb1, 0x31, 0x8a,
+            0x02, 0x4a, 0x83, 0x8f, 0x21, 0xaf, 0x1f, 0xde,
+            0x04, 0x89, 0x77, 0xeb, 0x48, 0xf5, 0x9f, 0xfd,
+            0x49, 0x24, 0xca, 0x1c, 0x60, 0x90, 0x2e, 0x52,
+            0xf0, 0xa0, 0x89, 0xbc, 0x76, 0x89, 0x70, 0x40,
+            0xe0, 0x82, 0xf9, 0x37, 0x76, 0x38, 0x48, 0x64,
+            0x5e, 0x07, 0x05
+        ];
+
+        let expected: [u8; 147] = [
+            0xf3, 0xff, 0xc7, 0x70, 0x3f, 0x94, 0x00, 0xe5,
+            0x2a, 0x7d, 0xfb, 0x4b, 0x3d, 0x33, 0x05, 0xd9,
+            0x8e, 0x99, 0x3b, 0x9f, 0x48, 0x68, 0x12, 0x73,
+            0xc2, 0x96, 0x50, 0xba, 0x32, 0xfc, 0x76, 0xce,
+            0x48, 0x33, 0x2e, 0xa7, 0x16, 0x4d, 0x96, 0xa4,
+            0x47, 0x6f, 0xb8, 0xc5, 0x31, 0xa1, 0x18, 0x6a,
+            0xc0, 0xdf, 0xc1, 0x7c, 0x98, 0xdc, 0xe8, 0x7b,
+            0x4d, 0xa7, 0xf0, 0x11, 0xec, 0x48, 0xc9, 0x72,
+            0x71, 0xd2, 0xc2, 0x0f, 0x9b, 0x92, 0x8f, 0xe2,
+            0x27, 0x0d, 0x6f, 0xb8, 0x63, 0xd5, 0x17, 0x38,
+            0xb4, 0x8e, 0xee, 0xe3, 0x14, 0xa7, 0xcc, 0x8a,
+            0xb9, 0x32, 0x16, 0x45, 0x48, 0xe5, 0x26, 0xae,
+            0x90, 0x22, 0x43, 0x68, 0x51, 0x7a, 0xcf, 0xea,
+            0xbd, 0x6b, 0xb3, 0x73, 0x2b, 0xc0, 0xe9, 0xda,
+            0x99, 0x83, 0x2b, 0x61, 0xca, 0x01, 0xb6, 0xde,
+            0x56, 0x24, 0x4a, 0x9e, 0x88, 0xd5, 0xf9, 0xb3,
+            0x79, 0x73, 0xf6, 0x22, 0xa4, 0x3d, 0x14, 0xa6,
+            0x59, 0x9b, 0x1f, 0x65, 0x4c, 0xb4, 0x5a, 0x74,
+            0xe3, 0x55, 0xa5
+        ];
+
+        let sealed = seal(&KEY, &NONCE, &plaintext);
+        assert_eq!(&sealed[..], &expected[..]);
+        assert_eq!(open(&KEY, &NONCE, &sealed), Ok(plaintext.to_vec()));
+    }
+
+    #[test]
+    fn it_seals_empty_messages() {
+        let expected: [u8; 16] = [
+            0x25, 0x39, 0x12, 0x1d, 0x8e, 0x23, 0x4e, 0x65,
+            0x2d, 0x65, 0x1f, 0xa4, 0xc8, 0xcf, 0xf8, 0x80
+        ];
+
+        assert_eq!(&seal(&KEY, &NONCE, b"")[..], &expected[..]);
+        assert_eq!(open(&KEY, &NONCE, &expected), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn it_rejects_tampering() {
+        let mut sealed = seal(&KEY, &NONCE, b"attack at dawn");
+
+        let mut other_nonce = NONCE;
+        other_nonce[23] ^= 1;
+        assert_eq!(open(&KEY, &other_nonce, &sealed), Err(Error::InvalidTag));
+        assert_eq!(open(&KEY, &NONCE, &sealed[..15]), Err(Error::InvalidTag));
+
+        sealed[TAG_LEN] ^= 1;
+        assert_eq!(open(&KEY, &NONCE, &sealed), Err(Error::InvalidTag));
+
+        sealed[TAG_LEN] ^= 1;
+        sealed[0] ^= 1;
+        assert_eq!(open(&KEY, &NONCE, &sealed), Err(Error::InvalidTag));
+    }
+}
@@ -0,0 +1,371 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{constant_time_eq, wipe, xor_in_place, Endian, LittleEndian};
+use crate::chacha20::ChaCha20;
+use crate::error::Error;
+use crate::poly1305::{Poly1305, TAG_LEN};
+
+/// Size of the header sent ahead of a stream
+pub const HEADER_LEN: usize = 24;
+
+/// Bytes added to every message: the encrypted tag byte and the Poly1305 tag
+pub const ABYTES: usize = 1 + TAG_LEN;
+
+/// Role of a message within a stream, authenticated along with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    /// An ordinary message
+    Message,
+    /// The end of a set of messages, without ending the stream
+    Push,
+    /// The key is replaced after this message
+    Rekey,
+    /// The last message of the stream, the key is replaced as well
+    Final,
+}
+
+impl Tag {
+    fn to_byte(self) -> u8 {
+        match self {
+            Tag::Message => 0,
+            Tag::Push => 1,
+            Tag::Rekey => 2,
+            Tag::Final => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Tag> {
+        match byte {
+            0 => Some(Tag::Message),
+            1 => Some(Tag::Push),
+            2 => Some(Tag::Rekey),
+            3 => Some(Tag::Final),
+            _ => None,
+        }
+    }
+}
+
+/// Key and nonce shared by both ends, advanced after every message
+struct State {
+    key: [u8; 32],
+    /// 32-bit message counter followed by the 8-byte inner nonce
+    nonce: [u8; 12],
+}
+
+impl State {
+    /// Derives the stream key from `key` and `header` like `init_push` and `init_pull`
+    fn new(key: &[u8], header: &[u8]) -> State {
+        assert_eq!(key.len(), 32);
+        assert_eq!(header.len(), HEADER_LEN);
+
+        let mut state = State { key: ChaCha20::hchacha20(key, &header[0..16]), nonce: [0u8; 12] };
+        state.nonce[4..12].copy_from_slice(&header[16..24]);
+        state.reset_counter();
+
+        state
+    }
+
+    /// Returns the cipher for the current message and the Poly1305 instance keyed from its block 0
+    fn start(&self) -> (ChaCha20, Poly1305) {
+        let mut chacha20 = ChaCha20::new_with_raw_nonce(&self.key, &self.nonce, 0);
+
+        let mut block = chacha20.next_block_bytes();
+        let mac = Poly1305::new(&block[0..32]);
+        wipe(&mut block);
+
+        (chacha20, mac)
+    }
+
+    /// Feeds the AAD and the encrypted tag block, as libsodium lays them out
+    fn authenticate_header(mac: &mut Poly1305, aad: &[u8], tag_block: &[u8; 64]) {
+        mac.update(aad);
+        mac.pad_to_block();
+        mac.update(tag_block);
+    }
+
+    /// Feeds the ciphertext and both lengths and returns the tag
+    ///
+    /// libsodium pads the ciphertext by `len % 16` bytes rather than up to a
+    /// block boundary; this is kept for compatibility.
+    fn authenticate_body(mut mac: Poly1305, aad_len: usize, ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        mac.update(ciphertext);
+        mac.update(&[0u8; 16][..(ciphertext.len() % 16)]);
+
+        let mut lengths = [0u8; 16];
+        LittleEndian::write_u64(aad_len as u64, &mut lengths[0..8]);
+        LittleEndian::write_u64((64 + ciphertext.len()) as u64, &mut lengths[8..16]);
+        mac.update(&lengths);
+
+        mac.finalize()
+    }
+
+    /// Mixes the tag into the inner nonce, moves the counter on and rekeys when needed
+    fn advance(&mut self, tag: u8, mac: &[u8; TAG_LEN]) {
+        xor_in_place(&mut self.nonce[4..12], &mac[0..8]);
+
+        let counter = LittleEndian::read_u32(&self.nonce[0..4]).wrapping_add(1);
+        LittleEndian::write_u32(counter, &mut self.nonce[0..4]);
+
+        if tag & Tag::Rekey.to_byte() != 0 || counter == 0 {
+            self.rekey();
+        }
+    }
+
+    /// Replaces the key and inner nonce with keystream derived from them
+    fn rekey(&mut self) {
+        let mut next = [0u8; 40];
+        next[0..32].copy_from_slice(&self.key);
+        next[32..40].copy_from_slice(&self.nonce[4..12]);
+        ChaCha20::new_with_raw_nonce(&self.key, &self.nonce, 0).apply_keystream(&mut next);
+
+        self.key.copy_from_slice(&next[0..32]);
+        self.nonce[4..12].copy_from_slice(&next[32..40]);
+        self.reset_counter();
+
+        wipe(&mut next);
+    }
+
+    fn reset_counter(&mut self) {
+        LittleEndian::write_u32(1, &mut self.nonce[0..4]);
+    }
+}
+
+impl Drop for State {
+    /// Wipes the key and nonce from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+        wipe(&mut self.nonce);
+    }
+}
+
+/// Sending end of a libsodium `crypto_secretstream_xchacha20poly1305` stream
+///
+/// Every message is encrypted and authenticated on its own, and the state
+/// moves on after each one, so the receiver detects dropped, reordered or
+/// replayed messages. A stream should end with `Tag::Final` so truncation
+/// is detected too.
+pub struct PushStream {
+    state: State,
+}
+
+impl PushStream {
+    /// Returns a new instance of PushStream
+    ///
+    /// The header has to reach the receiver ahead of the messages. It must be
+    /// 24 bytes from a secure random source, as libsodium's `init_push` uses.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `header` is not of size 24
+    pub fn new(key: &[u8], header: &[u8]) -> PushStream {
+        PushStream { state: State::new(key, header) }
+    }
+
+    /// Encrypts the next message with `tag` and returns it, `ABYTES` longer than `message`
+    pub fn push(&mut self, message: &[u8], aad: &[u8], tag: Tag) -> Vec<u8> {
+        let (mut chacha20, mut mac) = self.state.start();
+
+        let mut tag_block = [0u8; 64];
+        tag_block[0] = tag.to_byte();
+        chacha20.apply_keystream(&mut tag_block);
+        State::authenticate_header(&mut mac, aad, &tag_block);
+
+        let mut sealed = Vec::with_capacity(message.len() + ABYTES);
+        sealed.push(tag_block[0]);
+        sealed.extend_from_slice(message);
+        chacha20.apply_keystream(&mut sealed[1..]);
+
+        let mac = State::authenticate_body(mac, aad.len(), &sealed[1..]);
+        sealed.extend_from_slice(&mac);
+
+        self.state.advance(tag.to_byte(), &mac);
+        wipe(&mut tag_block);
+
+        sealed
+    }
+
+    /// Replaces the key without sending a message, see `PullStream::rekey`
+    pub fn rekey(&mut self) {
+        self.state.rekey();
+    }
+}
+
+/// Receiving end of a libsodium `crypto_secretstream_xchacha20poly1305` stream
+pub struct PullStream {
+    state: State,
+}
+
+impl PullStream {
+    /// Returns a new instance of PullStream for the header sent by the other end
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `header` is not of size 24
+    pub fn new(key: &[u8], header: &[u8]) -> PullStream {
+        PullStream { state: State::new(key, header) }
+    }
+
+    /// Verifies and decrypts the next message and returns it with its tag
+    ///
+    /// Returns `Error::InvalidTag` if the message is not the next one of the
+    /// stream, has been modified or carries an unknown tag; the state does
+    /// not move on in that case.
+    pub fn pull(&mut self, sealed: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Tag), Error> {
+        if sealed.len() < ABYTES {
+            return Err(Error::InvalidTag);
+        }
+
+        let (mut chacha20, mut mac) = self.state.start();
+
+        let mut tag_block = [0u8; 64];
+        chacha20.apply_keystream(&mut tag_block);
+        let tag = tag_block[0] ^ sealed[0];
+        tag_block[0] = sealed[0];
+        State::authenticate_header(&mut mac, aad, &tag_block);
+        wipe(&mut tag_block);
+
+        let (ciphertext, expected) = sealed[1..].split_at(sealed.len() - ABYTES);
+        let mac = State::authenticate_body(mac, aad.len(), ciphertext);
+        if !constant_time_eq(&mac, expected) {
+            return Err(Error::InvalidTag);
+        }
+        let tag = Tag::from_byte(tag).ok_or(Error::InvalidTag)?;
+
+        let mut message = ciphertext.to_vec();
+        chacha20.apply_keystream(&mut message);
+
+        self.state.advance(tag.to_byte(), &mac);
+
+        Ok((message, tag))
+    }
+
+    /// Replaces the key without receiving a message
+    ///
+    /// Both ends have to call `rekey` at the same point of the stream.
+    pub fn rekey(&mut self) {
+        self.state.rekey();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEADER: [u8; 24] = [
+        0xdc, 0x94, 0x25, 0xaa, 0xe0, 0xdd, 0xa3, 0xc4,
+        0x46, 0x6e, 0x44, 0x92, 0x52, 0xb6, 0x9e, 0xaf,
+        0x42, 0x1d, 0x95, 0x69, 0xf3, 0x95, 0x29, 0xae
+    ];
+
+    const LONG_MESSAGE: &[u8] = b"a third, longer message that spans well over one block of keystream, so the counter moves on";
+
+    fn key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        key
+    }
+
+    fn libsodium_stream() -> [Vec<u8>; 4] {
+        let first: [u8; 30] = [
+            0x1c, 0x75, 0x72, 0x6e, 0x75, 0xa4, 0xa8, 0x59,
+            0x0d, 0x45, 0x1a, 0xf6, 0x0b, 0x63, 0x1f, 0x05,
+            0x03, 0x24, 0x88, 0xa8, 0xb6, 0x30, 0x1e, 0x29,
+            0x1e, 0x5d, 0x22, 0x29, 0xa0, 0x85
+        ];
+
+        let second: [u8; 37] = [
+            0xa1, 0x22, 0x0f, 0x51, 0x0c, 0x94, 0xdf, 0x01,
+            0x4c, 0x07, 0x22, 0x24, 0xfa, 0xf3, 0x6f, 0x44,
+            0x76, 0x44, 0x37, 0x5c, 0xb0, 0x68, 0x01, 0xc2,
+            0xfa, 0xed, 0xd1, 0x9f, 0x9d, 0x39, 0x32, 0x6f,
+            0xbc, 0x2b, 0x39, 0x12, 0x70
+        ];
+
+        let third: [u8; 109] = [
+            0xbb, 0xa7, 0x21, 0x05, 0xa1, 0xa4, 0xb9, 0x87,
+            0x83, 0xd0, 0x85, 0x4c, 0x1c, 0xfb, 0xbf, 0x91,
+            0x0e, 0x90, 0xa1, 0xa1, 0xde, 0x8f, 0x8f, 0x27,
+            0x4a, 0xa7, 0x7a, 0xc0, 0x66, 0x90, 0x25, 0x90,
+            0x82, 0x01, 0x0d, 0xc1, 0xc6, 0xb5, 0x30, 0xcb,
+            0x44, 0xcd, 0xed, 0x2c, 0x22, 0x13, 0xfb, 0x77,
+            0x4c, 0x78, 0x5d, 0x90, 0x59, 0xd3, 0x32, 0x16,
+            0x15, 0x70, 0xc3, 0xc5, 0x45, 0xac, 0xc4, 0x22,
+            0x7c, 0xf8, 0xf1, 0x6b, 0xb9, 0xe7, 0xe6, 0x1a,
+            0xc7, 0x8f, 0xa1, 0xc7, 0x4b, 0x46, 0x2e, 0x7b,
+            0x6c, 0x99, 0x06, 0xb7, 0xac, 0x3f, 0x1a, 0x19,
+            0xfe, 0x19, 0x18, 0x31, 0x79, 0x60, 0x4a, 0x03,
+            0xf3, 0x9c, 0xdb, 0x18, 0x98, 0x17, 0x48, 0x32,
+            0x34, 0x7c, 0xd2, 0xd4, 0xca
+        ];
+
+        let last: [u8; 17] = [
+            0x3c, 0x0d, 0x0d, 0x8d, 0x57, 0x4e, 0x07, 0x2c,
+            0x47, 0xd3, 0x21, 0xd1, 0xec, 0x61, 0xcb, 0x65,
+            0x62
+        ];
+
+        [first.to_vec(), second.to_vec(), third.to_vec(), last.to_vec()]
+    }
+
+    #[test]
+    fn it_pushes_like_libsodium() {
+        let mut stream = PushStream::new(&key(), &HEADER);
+
+        let pushed = [
+            stream.push(b"first message", b"header data", Tag::Message),
+            stream.push(b"rekey after this one", b"", Tag::Rekey),
+            stream.push(LONG_MESSAGE, b"", Tag::Message),
+            stream.push(b"", b"", Tag::Final),
+        ];
+
+        assert_eq!(pushed, libsodium_stream());
+    }
+
+    #[test]
+    fn it_pulls_a_libsodium_stream() {
+        let sealed = libsodium_stream();
+        let mut stream = PullStream::new(&key(), &HEADER);
+
+        assert_eq!(stream.pull(&sealed[0], b"header data"), Ok((b"first message".to_vec(), Tag::Message)));
+        assert_eq!(stream.pull(&sealed[1], b""), Ok((b"rekey after this one".to_vec(), Tag::Rekey)));
+        assert_eq!(stream.pull(&sealed[2], b""), Ok((LONG_MESSAGE.to_vec(), Tag::Message)));
+        assert_eq!(stream.pull(&sealed[3], b""), Ok((Vec::new(), Tag::Final)));
+    }
+
+    #[test]
+    fn it_rejects_out_of_order_and_modified_messages() {
+        let sealed = libsodium_stream();
+        let mut stream = PullStream::new(&key(), &HEADER);
+
+        assert_eq!(stream.pull(&sealed[1], b""), Err(Error::InvalidTag));
+        assert_eq!(stream.pull(&sealed[0], b""), Err(Error::InvalidTag));
+
+        let mut modified = sealed[0].clone();
+        modified[0] ^= 1;
+        assert_eq!(stream.pull(&modified, b"header data"), Err(Error::InvalidTag));
+        assert_eq!(stream.pull(&sealed[0][..16], b"header data"), Err(Error::InvalidTag));
+
+        assert!(stream.pull(&sealed[0], b"header data").is_ok());
+        assert_eq!(stream.pull(&sealed[0], b"header data"), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_rekeys_on_request() {
+        let mut sender = PushStream::new(&key(), &HEADER);
+        let mut receiver = PullStream::new(&key(), &HEADER);
+
+        sender.rekey();
+        let sealed = sender.push(b"after rekey", b"", Tag::Message);
+        assert_eq!(PullStream::new(&key(), &HEADER).pull(&sealed, b""), Err(Error::InvalidTag));
+
+        receiver.rekey();
+        assert_eq!(receiver.pull(&sealed, b""), Ok((b"after rekey".to_vec(), Tag::Message)));
+    }
+}
@@ -0,0 +1,189 @@
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20::ChaCha20;
+use crate::sha256::{hkdf_expand, hkdf_extract};
+
+/// HKDF salt separating session keys from other uses of the shared secret
+const SESSION_SALT: &[u8] = b"chacha20 session";
+
+/// Label used to derive the client to server key and base nonce
+const CLIENT_TO_SERVER_LABEL: &[u8; 12] = b"chacha20 c2s";
+
+/// Label used to derive the server to client key and base nonce
+const SERVER_TO_CLIENT_LABEL: &[u8; 12] = b"chacha20 s2c";
+
+/// Side of the connection the keys are derived for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+/// Key and base nonce used for a single direction of a connection
+///
+/// Neither `Clone` nor comparable, so the key is not copied around or
+/// compared in variable time. The `Debug` output only shows the key
+/// fingerprint.
+pub struct DirectionalKey {
+    key: [u8; 32],
+    nonce: [u8; 12],
+}
+
+impl DirectionalKey {
+    /// Returns the key bytes
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    /// Returns the base nonce
+    pub fn nonce(&self) -> &[u8; 12] {
+        &self.nonce
+    }
+}
+
+impl fmt::Debug for DirectionalKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirectionalKey").field("fingerprint", &ChaCha20::fingerprint(&self.key)).finish()
+    }
+}
+
+impl Drop for DirectionalKey {
+    /// Wipes the key and the base nonce from memory
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+        wipe(&mut self.nonce);
+    }
+}
+
+/// Independent keys for both directions of a connection
+///
+/// Each side encrypts with `send` and decrypts with `receive`, so the two
+/// directions never share a key and counter.
+#[derive(Debug)]
+pub struct SessionKeys {
+    pub send: DirectionalKey,
+    pub receive: DirectionalKey,
+}
+
+impl SessionKeys {
+    /// Derives the session keys for `role` from a shared secret and a protocol context
+    ///
+    /// Both sides must call this with the same secret and context and
+    /// opposite roles; the client's `send` key is then the server's `receive`
+    /// key and the other way around. `context` should name the protocol and
+    /// version, and may include a handshake transcript hash: protocols that
+    /// happen to share a secret then derive unrelated keys.
+    ///
+    /// The keys and nonces come from HKDF-SHA256 of the secret, expanded with
+    /// the direction label followed by `context`.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `shared_secret` is not of size 32
+    pub fn derive(shared_secret: &[u8], context: &[u8], role: Role) -> SessionKeys {
+        assert_eq!(shared_secret.len(), 32);
+
+        let mut prk = hkdf_extract(SESSION_SALT, &[shared_secret]);
+        let client_to_server = SessionKeys::derive_direction(&prk, CLIENT_TO_SERVER_LABEL, context);
+        let server_to_client = SessionKeys::derive_direction(&prk, SERVER_TO_CLIENT_LABEL, context);
+        wipe(&mut prk);
+
+        match role {
+            Role::Client => SessionKeys { send: client_to_server, receive: server_to_client },
+            Role::Server => SessionKeys { send: server_to_client, receive: client_to_server },
+        }
+    }
+
+    /// Expands the key and base nonce of one direction
+    fn derive_direction(prk: &[u8], label: &[u8; 12], context: &[u8]) -> DirectionalKey {
+        let mut bytes = [0u8; 44];
+        hkdf_expand(prk, &[label, context], &mut bytes);
+
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&bytes[0..32]);
+
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&bytes[32..44]);
+
+        wipe(&mut bytes);
+
+        DirectionalKey { key, nonce }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONTEXT: &[u8] = b"example protocol v1";
+
+    #[test]
+    fn it_derives_the_documented_keys() {
+        let expected_key: [u8; 32] = [
+            0x96, 0x69, 0xbd, 0x9f, 0x76, 0x3f, 0xd5, 0xc8,
+            0x2b, 0xa8, 0xae, 0xa5, 0x39, 0x15, 0x85, 0xc9,
+            0x76, 0x25, 0x43, 0x01, 0xfe, 0x1b, 0x26, 0x94,
+            0xca, 0xed, 0x9b, 0x18, 0x14, 0x59, 0x6e, 0x5f
+        ];
+        let expected_nonce: [u8; 12] = [
+            0x79, 0xa2, 0x2e, 0x8b, 0x84, 0x8f, 0xb1, 0x1b,
+            0x5b, 0xa0, 0xa2, 0x87
+        ];
+
+        let keys = SessionKeys::derive(&[0x42u8; 32], CONTEXT, Role::Client);
+
+        assert_eq!(keys.send.key(), &expected_key);
+        assert_eq!(keys.send.nonce(), &expected_nonce);
+    }
+
+    #[test]
+    fn it_pairs_client_and_server_keys() {
+        let secret = [0x42u8; 32];
+        let client = SessionKeys::derive(&secret, CONTEXT, Role::Client);
+        let server = SessionKeys::derive(&secret, CONTEXT, Role::Server);
+
+        assert_eq!(client.send.key(), server.receive.key());
+        assert_eq!(client.send.nonce(), server.receive.nonce());
+        assert_eq!(client.receive.key(), server.send.key());
+        assert_eq!(client.receive.nonce(), server.send.nonce());
+    }
+
+    #[test]
+    fn it_separates_directions() {
+        let keys = SessionKeys::derive(&[0u8; 32], CONTEXT, Role::Client);
+
+        assert_ne!(keys.send.key(), keys.receive.key());
+        assert_ne!(keys.send.nonce(), keys.receive.nonce());
+    }
+
+    #[test]
+    fn it_depends_on_the_secret() {
+        let a = SessionKeys::derive(&[0u8; 32], CONTEXT, Role::Client);
+        let b = SessionKeys::derive(&[1u8; 32], CONTEXT, Role::Client);
+
+        assert_ne!(a.send.key(), b.send.key());
+    }
+
+    #[test]
+    fn it_separates_protocols_sharing_a_secret() {
+        let a = SessionKeys::derive(&[0u8; 32], b"protocol a", Role::Client);
+        let b = SessionKeys::derive(&[0u8; 32], b"protocol b", Role::Client);
+
+        assert_ne!(a.send.key(), b.send.key());
+        assert_ne!(a.receive.key(), b.receive.key());
+        assert_ne!(a.send.nonce(), b.send.nonce());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_keeps_keys_out_of_debug_output() {
+        let keys = SessionKeys::derive(&[0x42u8; 32], CONTEXT, Role::Client);
+        let debug = format!("{:?}", keys);
+
+        assert!(debug.contains("DirectionalKey { fingerprint: ["));
+        assert!(!debug.contains("150, 105, 189"));
+    }
+}
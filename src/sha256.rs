@@ -0,0 +1,293 @@
+#![forbid(unsafe_code)]
+
+use crate::byte_manipulation::{wipe, BigEndian, Endian};
+
+/// Size of a SHA-256 digest, and of the HMAC-SHA256 and HKDF-SHA256 keys
+pub(crate) const DIGEST_LEN: usize = 32;
+
+/// Size of the blocks the message is compressed in
+const BLOCK_LEN: usize = 64;
+
+/// Initial chain value
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+];
+
+/// Round constants
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+];
+
+/// Incremental SHA-256 (FIPS 180-4)
+///
+/// Only used as the hash of HMAC and HKDF, for session keys and HPKE.
+pub(crate) struct Sha256 {
+    h: [u32; 8],
+    buffer: [u8; BLOCK_LEN],
+    buffered: usize,
+    length: u64,
+}
+
+impl Sha256 {
+    /// Returns a new instance of Sha256
+    pub(crate) fn new() -> Sha256 {
+        Sha256 { h: H0, buffer: [0u8; BLOCK_LEN], buffered: 0, length: 0 }
+    }
+
+    /// Feeds `data` to the hash
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.length += data.len() as u64;
+
+        for &byte in data {
+            self.buffer[self.buffered] = byte;
+            self.buffered += 1;
+            if self.buffered == BLOCK_LEN {
+                let block = self.buffer;
+                self.compress(&block);
+                self.buffered = 0;
+            }
+        }
+    }
+
+    /// Pads the message and returns its digest
+    pub(crate) fn finalize(mut self) -> [u8; DIGEST_LEN] {
+        let mut length = [0u8; 8];
+        BigEndian::write_u64(self.length * 8, &mut length);
+
+        self.update(&[0x80]);
+        while self.buffered != BLOCK_LEN - 8 {
+            self.update(&[0]);
+        }
+        self.update(&length);
+
+        let mut digest = [0u8; DIGEST_LEN];
+        BigEndian::write_u32_into(&self.h, &mut digest);
+
+        digest
+    }
+
+    /// Compresses one block into the chain value
+    fn compress(&mut self, block: &[u8; BLOCK_LEN]) {
+        let mut w = [0u32; 64];
+        BigEndian::read_u32_into(block, &mut w[0..16]);
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let mut v = self.h;
+        for (k, w) in K.iter().zip(w.iter()) {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(*w);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+
+            v.copy_within(0..7, 1);
+            v[4] = v[4].wrapping_add(t1);
+            v[0] = t1.wrapping_add(t2);
+        }
+
+        for (h, v) in self.h.iter_mut().zip(v.iter()) {
+            *h = h.wrapping_add(*v);
+        }
+        wipe(&mut w);
+        wipe(&mut v);
+    }
+}
+
+impl Drop for Sha256 {
+    /// Wipes the hashed data from memory
+    fn drop(&mut self) {
+        wipe(&mut self.h);
+        wipe(&mut self.buffer);
+    }
+}
+
+/// Incremental HMAC-SHA256 (RFC 2104)
+pub(crate) struct HmacSha256 {
+    inner: Sha256,
+    outer_key: [u8; BLOCK_LEN],
+}
+
+impl HmacSha256 {
+    /// Returns a new instance of HmacSha256; keys longer than a block are hashed first
+    pub(crate) fn new(key: &[u8]) -> HmacSha256 {
+        let mut block_key = [0u8; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            let mut hash = Sha256::new();
+            hash.update(key);
+            block_key[0..DIGEST_LEN].copy_from_slice(&hash.finalize());
+        } else {
+            block_key[0..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner_key = block_key;
+        let mut outer_key = block_key;
+        for (inner, outer) in inner_key.iter_mut().zip(outer_key.iter_mut()) {
+            *inner ^= 0x36;
+            *outer ^= 0x5c;
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&inner_key);
+        wipe(&mut inner_key);
+        wipe(&mut block_key);
+
+        HmacSha256 { inner, outer_key }
+    }
+
+    /// Feeds `data` to the MAC
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Returns the tag of everything fed so far
+    pub(crate) fn finalize(mut self) -> [u8; DIGEST_LEN] {
+        let mut inner_digest = core::mem::replace(&mut self.inner, Sha256::new()).finalize();
+
+        let mut outer = Sha256::new();
+        outer.update(&self.outer_key);
+        outer.update(&inner_digest);
+        wipe(&mut inner_digest);
+
+        outer.finalize()
+    }
+}
+
+impl Drop for HmacSha256 {
+    /// Wipes the key from memory
+    fn drop(&mut self) {
+        wipe(&mut self.outer_key);
+    }
+}
+
+/// HKDF-Extract (RFC 5869) of the concatenation of `ikm`
+///
+/// An empty `salt` stands for the default of 32 zero bytes, which HMAC
+/// pads to the same key.
+pub(crate) fn hkdf_extract(salt: &[u8], ikm: &[&[u8]]) -> [u8; DIGEST_LEN] {
+    let mut mac = HmacSha256::new(salt);
+    for part in ikm {
+        mac.update(part);
+    }
+
+    mac.finalize()
+}
+
+/// HKDF-Expand (RFC 5869) of `prk` and the concatenation of `info`, filling `out`
+///
+/// # Panics
+///
+/// The function will panic if `out` is longer than 255 * 32 bytes
+pub(crate) fn hkdf_expand(prk: &[u8], info: &[&[u8]], out: &mut [u8]) {
+    assert!(out.len() <= 255 * DIGEST_LEN);
+
+    let mut block = [0u8; DIGEST_LEN];
+    for (i, chunk) in out.chunks_mut(DIGEST_LEN).enumerate() {
+        let mut mac = HmacSha256::new(prk);
+        if i > 0 {
+            mac.update(&block);
+        }
+        for part in info {
+            mac.update(part);
+        }
+        mac.update(&[i as u8 + 1]);
+
+        block = mac.finalize();
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+    wipe(&mut block);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sha256(data: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut hash = Sha256::new();
+        hash.update(data);
+        hash.finalize()
+    }
+
+    #[test]
+    fn it_hashes_the_fips_180_vectors() {
+        let abc: [u8; 32] = [
+            0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea,
+            0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22, 0x23,
+            0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c,
+            0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad
+        ];
+        let thousand_a: [u8; 32] = [
+            0x41, 0xed, 0xec, 0xe4, 0x2d, 0x63, 0xe8, 0xd9,
+            0xbf, 0x51, 0x5a, 0x9b, 0xa6, 0x93, 0x2e, 0x1c,
+            0x20, 0xcb, 0xc9, 0xf5, 0xa5, 0xd1, 0x34, 0x64,
+            0x5a, 0xdb, 0x5d, 0xb1, 0xb9, 0x73, 0x7e, 0xa3
+        ];
+
+        assert_eq!(sha256(b"abc"), abc);
+        assert_eq!(sha256(&[b'a'; 1000]), thousand_a);
+    }
+
+    #[test]
+    fn it_authenticates_the_rfc_4231_vectors() {
+        let short_key: [u8; 32] = [
+            0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e,
+            0x6a, 0x04, 0x24, 0x26, 0x08, 0x95, 0x75, 0xc7,
+            0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83,
+            0x9d, 0xec, 0x58, 0xb9, 0x64, 0xec, 0x38, 0x43
+        ];
+        let long_key: [u8; 32] = [
+            0x60, 0xe4, 0x31, 0x59, 0x1e, 0xe0, 0xb6, 0x7f,
+            0x0d, 0x8a, 0x26, 0xaa, 0xcb, 0xf5, 0xb7, 0x7f,
+            0x8e, 0x0b, 0xc6, 0x21, 0x37, 0x28, 0xc5, 0x14,
+            0x05, 0x46, 0x04, 0x0f, 0x0e, 0xe3, 0x7f, 0x54
+        ];
+
+        let mut mac = HmacSha256::new(b"Jefe");
+        mac.update(b"what do ya ");
+        mac.update(b"want for nothing?");
+        assert_eq!(mac.finalize(), short_key);
+
+        let mut mac = HmacSha256::new(&[0xaa; 131]);
+        mac.update(b"Test Using Larger Than Block-Size Key - Hash Key First");
+        assert_eq!(mac.finalize(), long_key);
+    }
+
+    #[test]
+    fn it_derives_the_rfc_5869_vector() {
+        let expected_prk: [u8; 32] = [
+            0x07, 0x77, 0x09, 0x36, 0x2c, 0x2e, 0x32, 0xdf,
+            0x0d, 0xdc, 0x3f, 0x0d, 0xc4, 0x7b, 0xba, 0x63,
+            0x90, 0xb6, 0xc7, 0x3b, 0xb5, 0x0f, 0x9c, 0x31,
+            0x22, 0xec, 0x84, 0x4a, 0xd7, 0xc2, 0xb3, 0xe5
+        ];
+        let expected_okm: [u8; 42] = [
+            0x3c, 0xb2, 0x5f, 0x25, 0xfa, 0xac, 0xd5, 0x7a,
+            0x90, 0x43, 0x4f, 0x64, 0xd0, 0x36, 0x2f, 0x2a,
+            0x2d, 0x2d, 0x0a, 0x90, 0xcf, 0x1a, 0x5a, 0x4c,
+            0x5d, 0xb0, 0x2d, 0x56, 0xec, 0xc4, 0xc5, 0xbf,
+            0x34, 0x00, 0x72, 0x08, 0xd5, 0xb8, 0x87, 0x18,
+            0x58, 0x65
+        ];
+
+        let salt = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c];
+        let info = [0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9];
+
+        let prk = hkdf_extract(&salt, &[&[0x0b; 11], &[0x0b; 11]]);
+        assert_eq!(prk, expected_prk);
+
+        let mut okm = [0u8; 42];
+        hkdf_expand(&prk, &[&info[0..4], &info[4..]], &mut okm);
+        assert_eq!(okm, expected_okm);
+    }
+}
@@ -0,0 +1,202 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::byte_manipulation::{constant_time_eq, wipe, Endian, LittleEndian};
+use crate::chacha20::{hchacha20, ChaCha20};
+use crate::error::Error;
+use crate::poly1305::{Poly1305, TAG_LEN};
+use crate::xchacha20::XChaCha20;
+
+/// Nonce of the ChaCha20 block that the three subkeys are taken from
+const SUBKEY_NONCE: &[u8; 12] = b"chacha20-siv";
+
+/// Nonce-misuse-resistant authenticated encryption built from ChaCha20 and Poly1305
+///
+/// The tag is computed from the message before encrypting, then used as the
+/// nonce, in the style of SIV and Daence:
+///
+/// 1. the nonce, AAD and plaintext, each zero-padded to 16 bytes, and their
+///    lengths as 64-bit little endian values are hashed with Poly1305 under a
+///    fixed subkey
+/// 2. the tag is the first 16 bytes of HChaCha20 of that hash under a second
+///    subkey
+/// 3. the plaintext is encrypted with XChaCha20 under a third subkey, using
+///    `tag || 0u64` as the 24-byte nonce
+///
+/// The subkeys are the first 96 bytes of the ChaCha20 keystream for the key
+/// and the nonce `"chacha20-siv"`. The output is `tag || ciphertext`.
+///
+/// Reusing a nonce only reveals whether two messages with the same AAD are
+/// identical. Unique nonces are still preferable, and this mode is slower
+/// than `ChaCha20Poly1305` since it makes two passes over the data.
+pub struct ChaCha20Poly1305Siv {
+    hash_key: [u8; 32],
+    tag_key: [u8; 32],
+    cipher_key: [u8; 32],
+}
+
+impl ChaCha20Poly1305Siv {
+    /// Returns a new instance of ChaCha20Poly1305Siv
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    pub fn new(key: &[u8]) -> ChaCha20Poly1305Siv {
+        let mut subkeys = [0u8; 96];
+        ChaCha20::new_with_raw_nonce(key, SUBKEY_NONCE, 0).apply_keystream(&mut subkeys);
+
+        let mut siv = ChaCha20Poly1305Siv { hash_key: [0u8; 32], tag_key: [0u8; 32], cipher_key: [0u8; 32] };
+        siv.hash_key.copy_from_slice(&subkeys[0..32]);
+        siv.tag_key.copy_from_slice(&subkeys[32..64]);
+        siv.cipher_key.copy_from_slice(&subkeys[64..96]);
+        wipe(&mut subkeys);
+
+        siv
+    }
+
+    /// Encrypts `plaintext` and returns the 16-byte tag followed by the ciphertext
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    /// The function will panic if `plaintext` is longer than the 256 GiB keystream
+    pub fn encrypt(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let tag = self.tag(nonce, aad, plaintext);
+
+        let mut sealed = Vec::with_capacity(TAG_LEN + plaintext.len());
+        sealed.extend_from_slice(&tag);
+        sealed.extend_from_slice(plaintext);
+        self.cipher(&tag).apply_keystream(&mut sealed[TAG_LEN..]);
+
+        sealed
+    }
+
+    /// Decrypts `ciphertext` and returns the message if its tag matches
+    ///
+    /// Returns `Error::InvalidTag` if the key, nonce or AAD is wrong or the
+    /// ciphertext has been modified; the decrypted bytes are wiped first.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `nonce` is not of size 12
+    pub fn decrypt(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        assert_eq!(nonce.len(), 12);
+        if ciphertext.len() < TAG_LEN {
+            return Err(Error::InvalidTag);
+        }
+
+        let (tag, ciphertext) = ciphertext.split_at(TAG_LEN);
+        let mut plaintext = ciphertext.to_vec();
+        self.cipher(tag).apply_keystream(&mut plaintext);
+
+        if !constant_time_eq(&self.tag(nonce, aad, &plaintext), tag) {
+            wipe(&mut plaintext);
+            return Err(Error::InvalidTag);
+        }
+
+        Ok(plaintext)
+    }
+
+    /// Computes the synthetic tag of a message
+    fn tag(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> [u8; TAG_LEN] {
+        assert_eq!(nonce.len(), 12);
+
+        let mut lengths = [0u8; 16];
+        LittleEndian::write_u64(aad.len() as u64, &mut lengths[0..8]);
+        LittleEndian::write_u64(plaintext.len() as u64, &mut lengths[8..16]);
+
+        let mut mac = Poly1305::new(&self.hash_key);
+        mac.update(nonce);
+        mac.pad_to_block();
+        mac.update(aad);
+        mac.pad_to_block();
+        mac.update(plaintext);
+        mac.pad_to_block();
+        mac.update(&lengths);
+
+        let mut hash = mac.finalize();
+        let mut output = hchacha20(&self.tag_key, &hash);
+
+        let mut tag = [0u8; TAG_LEN];
+        tag.copy_from_slice(&output[0..TAG_LEN]);
+        wipe(&mut hash);
+        wipe(&mut output);
+
+        tag
+    }
+
+    /// Returns the XChaCha20 instance keyed for `tag`
+    fn cipher(&self, tag: &[u8]) -> XChaCha20 {
+        let mut nonce = [0u8; 24];
+        nonce[0..TAG_LEN].copy_from_slice(tag);
+
+        XChaCha20::new(&self.cipher_key, &nonce, 0)
+    }
+}
+
+impl Drop for ChaCha20Poly1305Siv {
+    /// Wipes the subkeys from memory
+    fn drop(&mut self) {
+        wipe(&mut self.hash_key);
+        wipe(&mut self.tag_key);
+        wipe(&mut self.cipher_key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: [u8; 32] = [0x24; 32];
+
+    const NONCE: [u8; 12] = [0u8; 12];
+
+    #[test]
+    fn it_matches_the_documented_construction() {
+        let expected: [u8; 30] = [
+            0x42, 0x4b, 0xf1, 0x34, 0x53, 0x1a, 0xa2, 0x44,
+            0xcc, 0xb3, 0xe0, 0x8f, 0x77, 0x50, 0x4d, 0x88,
+            0xba, 0x20, 0xd6, 0xd1, 0x02, 0x4f, 0x7a, 0xa8,
+            0x5d, 0xe2, 0x97, 0x7b, 0xbd, 0x63
+        ];
+
+        let siv = ChaCha20Poly1305Siv::new(&KEY);
+        let sealed = siv.encrypt(&NONCE, b"backup 42", b"attack at dawn");
+
+        assert_eq!(&sealed[..], &expected[..]);
+        assert_eq!(siv.decrypt(&NONCE, b"backup 42", &sealed), Ok(b"attack at dawn".to_vec()));
+    }
+
+    #[test]
+    fn it_only_reveals_equal_messages_under_a_reused_nonce() {
+        let siv = ChaCha20Poly1305Siv::new(&KEY);
+
+        let first = siv.encrypt(&NONCE, b"", b"attack at dawn");
+        assert_eq!(siv.encrypt(&NONCE, b"", b"attack at dawn"), first);
+
+        let other = siv.encrypt(&NONCE, b"", b"attack at dusk");
+        assert_ne!(&other[..TAG_LEN], &first[..TAG_LEN]);
+        assert_ne!(&other[TAG_LEN..(TAG_LEN + 10)], &first[TAG_LEN..(TAG_LEN + 10)]);
+
+        assert_ne!(siv.encrypt(&[1u8; 12], b"", b"attack at dawn"), first);
+    }
+
+    #[test]
+    fn it_rejects_tampering() {
+        let siv = ChaCha20Poly1305Siv::new(&KEY);
+        let mut sealed = siv.encrypt(&NONCE, b"aad", b"attack at dawn");
+
+        assert_eq!(siv.decrypt(&NONCE, b"dda", &sealed), Err(Error::InvalidTag));
+        assert_eq!(siv.decrypt(&[1u8; 12], b"aad", &sealed), Err(Error::InvalidTag));
+        assert_eq!(ChaCha20Poly1305Siv::new(&[0u8; 32]).decrypt(&NONCE, b"aad", &sealed), Err(Error::InvalidTag));
+        assert_eq!(siv.decrypt(&NONCE, b"aad", &sealed[..15]), Err(Error::InvalidTag));
+
+        sealed[TAG_LEN] ^= 1;
+        assert_eq!(siv.decrypt(&NONCE, b"aad", &sealed), Err(Error::InvalidTag));
+
+        sealed[TAG_LEN] ^= 1;
+        sealed[0] ^= 1;
+        assert_eq!(siv.decrypt(&NONCE, b"aad", &sealed), Err(Error::InvalidTag));
+    }
+}
@@ -0,0 +1,380 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+use crate::byte_manipulation::{BigEndian, Endian};
+use crate::chacha20_poly1305::ChaCha20Poly1305;
+use crate::error::Error;
+#[cfg(feature = "std")]
+use crate::poly1305::TAG_LEN;
+
+/// Size of the nonce prefix shared by every chunk of a stream
+pub const PREFIX_LEN: usize = 7;
+
+/// Plaintext bytes in every chunk but the last when `encrypt_reader` does the chunking
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Nonce of chunk `counter`: prefix, 32-bit big endian counter and a last-chunk flag
+fn chunk_nonce(prefix: &[u8; PREFIX_LEN], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[0..7].copy_from_slice(prefix);
+    BigEndian::write_u32(counter, &mut nonce[7..11]);
+    nonce[11] = last as u8;
+
+    nonce
+}
+
+/// Encrypting half of the STREAM construction over ChaCha20-Poly1305
+///
+/// A long message is cut into chunks, each sealed with a nonce made of a
+/// per-stream prefix, the chunk index and a flag set only on the last chunk.
+/// The receiver therefore detects reordered, dropped or duplicated chunks, and
+/// a stream cut short, because no chunk before the end opens as the last one.
+/// Chunks can have any size, including empty. A prefix must never be reused
+/// with the same key.
+pub struct StreamEncryptor {
+    aead: ChaCha20Poly1305,
+    prefix: [u8; PREFIX_LEN],
+    counter: u32,
+}
+
+impl StreamEncryptor {
+    /// Returns a new instance of StreamEncryptor
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `prefix` is not of size 7
+    pub fn new(key: &[u8], prefix: &[u8]) -> StreamEncryptor {
+        assert_eq!(prefix.len(), PREFIX_LEN);
+
+        let mut stream = StreamEncryptor { aead: ChaCha20Poly1305::new(key), prefix: [0u8; PREFIX_LEN], counter: 0 };
+        stream.prefix.copy_from_slice(prefix);
+
+        stream
+    }
+
+    /// Seals the next chunk, which must not be the last one
+    ///
+    /// Returns `Error::KeyExpired` once 2^32 - 1 chunks have been sealed,
+    /// leaving room for the last one.
+    pub fn encrypt_next(&mut self, aad: &[u8], chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.counter == u32::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        let sealed = self.aead.encrypt(&chunk_nonce(&self.prefix, self.counter, false), aad, chunk);
+        self.counter += 1;
+
+        Ok(sealed)
+    }
+
+    /// Seals the next chunk in place and writes its tag to `tag_out`
+    ///
+    /// Same as `encrypt_next` without allocating, for callers that keep the
+    /// tag next to the chunk themselves.
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `tag_out` is not of size 16
+    pub fn encrypt_next_in_place(&mut self, aad: &[u8], buffer: &mut [u8], tag_out: &mut [u8]) -> Result<(), Error> {
+        if self.counter == u32::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        self.aead.seal_in_place(&chunk_nonce(&self.prefix, self.counter, false), aad, buffer, tag_out);
+        self.counter += 1;
+
+        Ok(())
+    }
+
+    /// Seals the last chunk and ends the stream
+    pub fn encrypt_last(self, aad: &[u8], chunk: &[u8]) -> Vec<u8> {
+        self.aead.encrypt(&chunk_nonce(&self.prefix, self.counter, true), aad, chunk)
+    }
+
+    /// Seals everything `reader` yields into `writer` and ends the stream
+    ///
+    /// The input is cut into chunks of `CHUNK_SIZE` bytes, sealed without
+    /// AAD. The last chunk is the one reaching the end of the input, so it
+    /// holds between 0 and `CHUNK_SIZE` bytes; an empty input gives a single
+    /// empty last chunk. `StreamDecryptor::decrypt_reader` reverses it.
+    ///
+    /// Errors of `reader` and `writer` are passed on; running out of chunks
+    /// is reported as `InvalidData`.
+    #[cfg(feature = "std")]
+    pub fn encrypt_reader<R: Read, W: Write>(mut self, reader: &mut R, writer: &mut W) -> io::Result<()> {
+        let mut current = vec![0u8; CHUNK_SIZE];
+        let mut next = vec![0u8; CHUNK_SIZE];
+
+        let mut length = read_chunk(reader, &mut current)?;
+        while length == CHUNK_SIZE {
+            let next_length = read_chunk(reader, &mut next)?;
+            if next_length == 0 {
+                break;
+            }
+
+            writer.write_all(&self.encrypt_next(b"", &current)?)?;
+            core::mem::swap(&mut current, &mut next);
+            length = next_length;
+        }
+
+        writer.write_all(&self.encrypt_last(b"", &current[..length]))?;
+        writer.flush()
+    }
+}
+
+/// Decrypting half of the STREAM construction, see `StreamEncryptor`
+///
+/// The caller must finish with `decrypt_last`: a stream whose last chunk is
+/// missing has been truncated and must be rejected as a whole.
+pub struct StreamDecryptor {
+    aead: ChaCha20Poly1305,
+    prefix: [u8; PREFIX_LEN],
+    counter: u32,
+}
+
+impl StreamDecryptor {
+    /// Returns a new instance of StreamDecryptor
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `prefix` is not of size 7
+    pub fn new(key: &[u8], prefix: &[u8]) -> StreamDecryptor {
+        assert_eq!(prefix.len(), PREFIX_LEN);
+
+        let mut stream = StreamDecryptor { aead: ChaCha20Poly1305::new(key), prefix: [0u8; PREFIX_LEN], counter: 0 };
+        stream.prefix.copy_from_slice(prefix);
+
+        stream
+    }
+
+    /// Opens the next chunk, which must not be the last one
+    ///
+    /// Returns `Error::InvalidTag` if the chunk is not the next one of the
+    /// stream, and `Error::KeyExpired` past 2^32 - 1 chunks. The position
+    /// only advances on success.
+    pub fn decrypt_next(&mut self, aad: &[u8], chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.counter == u32::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        let plaintext = self.aead.decrypt(&chunk_nonce(&self.prefix, self.counter, false), aad, chunk)?;
+        self.counter += 1;
+
+        Ok(plaintext)
+    }
+
+    /// Opens the next chunk in place, see `encrypt_next_in_place`
+    ///
+    /// Returns the same errors as `decrypt_next` and leaves `buffer`
+    /// untouched on failure.
+    pub fn decrypt_next_in_place(&mut self, aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<(), Error> {
+        if self.counter == u32::MAX {
+            return Err(Error::KeyExpired);
+        }
+
+        self.aead.open_in_place(&chunk_nonce(&self.prefix, self.counter, false), aad, buffer, tag)?;
+        self.counter += 1;
+
+        Ok(())
+    }
+
+    /// Opens the last chunk and ends the stream
+    ///
+    /// Returns `Error::InvalidTag` if the chunk is not the last one of the stream.
+    pub fn decrypt_last(self, aad: &[u8], chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        self.aead.decrypt(&chunk_nonce(&self.prefix, self.counter, true), aad, chunk)
+    }
+
+    /// Opens a stream sealed by `StreamEncryptor::encrypt_reader` from `reader` into `writer`
+    ///
+    /// Every chunk is verified before it is written, but a stream is only
+    /// known to be complete once this returns `Ok`: on an `InvalidData`
+    /// error, which covers modified, reordered and truncated streams, the
+    /// output written so far must be discarded.
+    #[cfg(feature = "std")]
+    pub fn decrypt_reader<R: Read, W: Write>(mut self, reader: &mut R, writer: &mut W) -> io::Result<()> {
+        let mut current = vec![0u8; CHUNK_SIZE + TAG_LEN];
+        let mut next = vec![0u8; CHUNK_SIZE + TAG_LEN];
+
+        let mut length = read_chunk(reader, &mut current)?;
+        while length == CHUNK_SIZE + TAG_LEN {
+            let next_length = read_chunk(reader, &mut next)?;
+            if next_length == 0 {
+                break;
+            }
+
+            writer.write_all(&self.decrypt_next(b"", &current)?)?;
+            core::mem::swap(&mut current, &mut next);
+            length = next_length;
+        }
+
+        writer.write_all(&self.decrypt_last(b"", &current[..length])?)?;
+        writer.flush()
+    }
+}
+
+/// Reads until `buffer` is full or the input ends and returns the number of bytes read
+#[cfg(feature = "std")]
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::poly1305::TAG_LEN;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    const PREFIX: [u8; 7] = [1, 2, 3, 4, 5, 6, 7];
+
+    fn sealed_stream() -> [Vec<u8>; 3] {
+        let mut encryptor = StreamEncryptor::new(&KEY, &PREFIX);
+        let first = encryptor.encrypt_next(b"", b"first chunk").unwrap();
+        let second = encryptor.encrypt_next(b"", b"second chunk").unwrap();
+        let last = encryptor.encrypt_last(b"", b"last chunk");
+
+        [first, second, last]
+    }
+
+    #[test]
+    fn it_round_trips_a_stream() {
+        let chunks = sealed_stream();
+
+        let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+        assert_eq!(decryptor.decrypt_next(b"", &chunks[0]), Ok(b"first chunk".to_vec()));
+        assert_eq!(decryptor.decrypt_next(b"", &chunks[1]), Ok(b"second chunk".to_vec()));
+        assert_eq!(decryptor.decrypt_last(b"", &chunks[2]), Ok(b"last chunk".to_vec()));
+    }
+
+    #[test]
+    fn it_uses_the_stream_nonce_layout() {
+        let chunks = sealed_stream();
+        let aead = ChaCha20Poly1305::new(&KEY);
+
+        assert_eq!(aead.decrypt(&[1, 2, 3, 4, 5, 6, 7, 0, 0, 0, 1, 0], b"", &chunks[1]), Ok(b"second chunk".to_vec()));
+        assert_eq!(aead.decrypt(&[1, 2, 3, 4, 5, 6, 7, 0, 0, 0, 2, 1], b"", &chunks[2]), Ok(b"last chunk".to_vec()));
+    }
+
+    #[test]
+    fn it_seals_chunks_in_place() {
+        let chunks = sealed_stream();
+
+        let mut buffer = *b"first chunk";
+        let mut tag = [0u8; TAG_LEN];
+        let mut encryptor = StreamEncryptor::new(&KEY, &PREFIX);
+        assert_eq!(encryptor.encrypt_next_in_place(b"", &mut buffer, &mut tag), Ok(()));
+
+        assert_eq!(&buffer[..], &chunks[0][..11]);
+        assert_eq!(&tag[..], &chunks[0][11..]);
+
+        let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+        assert_eq!(decryptor.decrypt_next_in_place(b"", &mut buffer, &tag), Ok(()));
+        assert_eq!(&buffer, b"first chunk");
+        assert_eq!(decryptor.decrypt_next(b"", &chunks[1]), Ok(b"second chunk".to_vec()));
+    }
+
+    #[test]
+    fn it_detects_reordering() {
+        let chunks = sealed_stream();
+
+        let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+        assert_eq!(decryptor.decrypt_next(b"", &chunks[1]), Err(Error::InvalidTag));
+        assert_eq!(decryptor.decrypt_next(b"", &chunks[0]), Ok(b"first chunk".to_vec()));
+        assert_eq!(decryptor.decrypt_next(b"", &chunks[0]), Err(Error::InvalidTag));
+    }
+
+    #[test]
+    fn it_detects_truncation() {
+        let chunks = sealed_stream();
+
+        let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+        assert!(decryptor.decrypt_next(b"", &chunks[0]).is_ok());
+        assert_eq!(decryptor.decrypt_last(b"", &chunks[1]), Err(Error::InvalidTag));
+
+        let mut decryptor = StreamDecryptor::new(&KEY, &PREFIX);
+        assert!(decryptor.decrypt_next(b"", &chunks[0]).is_ok());
+        assert!(decryptor.decrypt_next(b"", &chunks[1]).is_ok());
+        assert_eq!(decryptor.decrypt_next(b"", &chunks[2]), Err(Error::InvalidTag));
+    }
+
+    #[cfg(feature = "std")]
+    fn encrypt_reader(plaintext: &[u8]) -> Vec<u8> {
+        let mut sealed = Vec::new();
+        StreamEncryptor::new(&KEY, &PREFIX).encrypt_reader(&mut &plaintext[..], &mut sealed).unwrap();
+
+        sealed
+    }
+
+    #[cfg(feature = "std")]
+    fn decrypt_reader(sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        StreamDecryptor::new(&KEY, &PREFIX).decrypt_reader(&mut &sealed[..], &mut plaintext)?;
+
+        Ok(plaintext)
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_chunks_readers() {
+        let plaintext: Vec<u8> = (0..2 * CHUNK_SIZE + 100).map(|i| i as u8).collect();
+        let sealed = encrypt_reader(&plaintext);
+
+        let mut encryptor = StreamEncryptor::new(&KEY, &PREFIX);
+        let mut expected = encryptor.encrypt_next(b"", &plaintext[..CHUNK_SIZE]).unwrap();
+        expected.extend_from_slice(&encryptor.encrypt_next(b"", &plaintext[CHUNK_SIZE..2 * CHUNK_SIZE]).unwrap());
+        expected.extend_from_slice(&encryptor.encrypt_last(b"", &plaintext[2 * CHUNK_SIZE..]));
+
+        assert_eq!(sealed, expected);
+        assert_eq!(decrypt_reader(&sealed).unwrap(), plaintext);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_seals_a_full_last_chunk_as_the_last_one() {
+        let plaintext = vec![0x61u8; 2 * CHUNK_SIZE];
+        let sealed = encrypt_reader(&plaintext);
+
+        assert_eq!(sealed.len(), 2 * (CHUNK_SIZE + TAG_LEN));
+        assert_eq!(decrypt_reader(&sealed).unwrap(), plaintext);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_seals_an_empty_reader_as_one_empty_chunk() {
+        let sealed = encrypt_reader(b"");
+
+        assert_eq!(sealed, StreamEncryptor::new(&KEY, &PREFIX).encrypt_last(b"", b""));
+        assert_eq!(decrypt_reader(&sealed).unwrap(), b"");
+        assert_eq!(decrypt_reader(b"").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_rejects_truncated_and_modified_readers() {
+        let plaintext = vec![0x61u8; CHUNK_SIZE + 10];
+        let sealed = encrypt_reader(&plaintext);
+
+        let truncated = decrypt_reader(&sealed[..CHUNK_SIZE + TAG_LEN]);
+        assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut modified = sealed;
+        modified[CHUNK_SIZE + TAG_LEN + 1] ^= 1;
+        assert_eq!(decrypt_reader(&modified).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,272 @@
+#![forbid(unsafe_code)]
+
+use crate::byte_manipulation::wipe;
+
+/// Element of the field modulo 2^255 - 19 as sixteen 16-bit limbs
+///
+/// Limbs are signed and may temporarily exceed 16 bits; `carry` brings them
+/// back into range.
+type Fe = [i64; 16];
+
+/// The curve constant (A - 2) / 4 = 121665
+const A24: Fe = [0xdb41, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// The u-coordinate of the Curve25519 base point
+const BASE_POINT: [u8; 32] = [
+    9, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0
+];
+
+/// Computes the X25519 function of RFC 7748 on `scalar` and the u-coordinate `u`
+///
+/// The ladder runs in constant time: conditional swaps are done with masks
+/// and the scalar bits never choose a branch or a memory address.
+pub(crate) fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut clamped = *scalar;
+    clamped[0] &= 248;
+    clamped[31] = (clamped[31] & 127) | 64;
+
+    let x1 = unpack(u);
+    let mut x2 = one();
+    let mut z2 = [0i64; 16];
+    let mut x3 = x1;
+    let mut z3 = one();
+
+    for i in (0..255).rev() {
+        let bit = ((clamped[i >> 3] >> (i & 7)) & 1) as i64;
+        swap(&mut x2, &mut x3, bit);
+        swap(&mut z2, &mut z3, bit);
+
+        let a = add(&x2, &z2);
+        let b = sub(&x2, &z2);
+        let c = add(&x3, &z3);
+        let d = sub(&x3, &z3);
+        let aa = square(&a);
+        let bb = square(&b);
+        let e = sub(&aa, &bb);
+        let da = mul(&d, &a);
+        let cb = mul(&c, &b);
+
+        x3 = square(&add(&da, &cb));
+        z3 = mul(&x1, &square(&sub(&da, &cb)));
+        x2 = mul(&aa, &bb);
+        z2 = mul(&e, &add(&aa, &mul(&A24, &e)));
+
+        swap(&mut x2, &mut x3, bit);
+        swap(&mut z2, &mut z3, bit);
+    }
+
+    let shared = pack(&mul(&x2, &invert(&z2)));
+
+    wipe(&mut clamped);
+    wipe(&mut x2);
+    wipe(&mut z2);
+    wipe(&mut x3);
+    wipe(&mut z3);
+
+    shared
+}
+
+/// Computes the public key belonging to `scalar`
+pub(crate) fn x25519_base(scalar: &[u8; 32]) -> [u8; 32] {
+    x25519(scalar, &BASE_POINT)
+}
+
+fn one() -> Fe {
+    let mut fe = [0i64; 16];
+    fe[0] = 1;
+
+    fe
+}
+
+fn unpack(bytes: &[u8; 32]) -> Fe {
+    let mut fe = [0i64; 16];
+    for (limb, pair) in fe.iter_mut().zip(bytes.chunks_exact(2)) {
+        *limb = pair[0] as i64 | ((pair[1] as i64) << 8);
+    }
+    fe[15] &= 0x7fff;
+
+    fe
+}
+
+/// Returns the fully reduced little-endian encoding of `fe`
+fn pack(fe: &Fe) -> [u8; 32] {
+    let mut t = *fe;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+
+    // Subtracting p at most twice leaves the canonical representative
+    let mut m = [0i64; 16];
+    for _ in 0..2 {
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let borrow = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        swap(&mut t, &mut m, 1 - borrow);
+    }
+
+    let mut bytes = [0u8; 32];
+    for (pair, limb) in bytes.chunks_exact_mut(2).zip(t.iter()) {
+        pair[0] = *limb as u8;
+        pair[1] = (*limb >> 8) as u8;
+    }
+
+    wipe(&mut t);
+    wipe(&mut m);
+
+    bytes
+}
+
+/// Propagates carries so every limb fits in 16 bits, folding the top one back times 38
+fn carry(fe: &mut Fe) {
+    for i in 0..16 {
+        fe[i] += 1 << 16;
+        let c = fe[i] >> 16;
+        if i < 15 {
+            fe[i + 1] += c - 1;
+        } else {
+            fe[0] += 38 * (c - 1);
+        }
+        fe[i] -= c << 16;
+    }
+}
+
+/// Swaps `a` and `b` if `bit` is 1 and leaves them alone if it is 0
+fn swap(a: &mut Fe, b: &mut Fe, bit: i64) {
+    let mask = !(bit - 1);
+    for (x, y) in a.iter_mut().zip(b.iter_mut()) {
+        let t = mask & (*x ^ *y);
+        *x ^= t;
+        *y ^= t;
+    }
+}
+
+fn add(a: &Fe, b: &Fe) -> Fe {
+    let mut sum = *a;
+    for (x, y) in sum.iter_mut().zip(b.iter()) {
+        *x += y;
+    }
+
+    sum
+}
+
+fn sub(a: &Fe, b: &Fe) -> Fe {
+    let mut difference = *a;
+    for (x, y) in difference.iter_mut().zip(b.iter()) {
+        *x -= y;
+    }
+
+    difference
+}
+
+fn mul(a: &Fe, b: &Fe) -> Fe {
+    let mut wide = [0i64; 31];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            wide[i + j] += x * y;
+        }
+    }
+
+    // 2^256 is 38 modulo p
+    let mut product = [0i64; 16];
+    product.copy_from_slice(&wide[..16]);
+    for (limb, high) in product[..15].iter_mut().zip(wide[16..].iter()) {
+        *limb += 38 * high;
+    }
+    carry(&mut product);
+    carry(&mut product);
+
+    wipe(&mut wide);
+
+    product
+}
+
+fn square(a: &Fe) -> Fe {
+    mul(a, a)
+}
+
+/// Returns `a^(p - 2)`, the inverse of `a` by Fermat's little theorem
+fn invert(a: &Fe) -> Fe {
+    let mut c = *a;
+    for i in (0..254).rev() {
+        c = square(&c);
+        if i != 2 && i != 4 {
+            c = mul(&c, a);
+        }
+    }
+
+    c
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_matches_the_rfc_7748_vector() {
+        let scalar: [u8; 32] = [
+            0xa5, 0x46, 0xe3, 0x6b, 0xf0, 0x52, 0x7c, 0x9d,
+            0x3b, 0x16, 0x15, 0x4b, 0x82, 0x46, 0x5e, 0xdd,
+            0x62, 0x14, 0x4c, 0x0a, 0xc1, 0xfc, 0x5a, 0x18,
+            0x50, 0x6a, 0x22, 0x44, 0xba, 0x44, 0x9a, 0xc4
+        ];
+
+        let u: [u8; 32] = [
+            0xe6, 0xdb, 0x68, 0x67, 0x58, 0x30, 0x30, 0xdb,
+            0x35, 0x94, 0xc1, 0xa4, 0x24, 0xb1, 0x5f, 0x7c,
+            0x72, 0x66, 0x24, 0xec, 0x26, 0xb3, 0x35, 0x3b,
+            0x10, 0xa9, 0x03, 0xa6, 0xd0, 0xab, 0x1c, 0x4c
+        ];
+
+        let expected: [u8; 32] = [
+            0xc3, 0xda, 0x55, 0x37, 0x9d, 0xe9, 0xc6, 0x90,
+            0x8e, 0x94, 0xea, 0x4d, 0xf2, 0x8d, 0x08, 0x4f,
+            0x32, 0xec, 0xcf, 0x03, 0x49, 0x1c, 0x71, 0xf7,
+            0x54, 0xb4, 0x07, 0x55, 0x77, 0xa2, 0x85, 0x52
+        ];
+
+        assert_eq!(x25519(&scalar, &u), expected);
+    }
+
+    #[test]
+    fn it_agrees_on_a_shared_secret() {
+        let alice: [u8; 32] = [
+            0x77, 0x07, 0x6d, 0x0a, 0x73, 0x18, 0xa5, 0x7d,
+            0x3c, 0x16, 0xc1, 0x72, 0x51, 0xb2, 0x66, 0x45,
+            0xdf, 0x4c, 0x2f, 0x87, 0xeb, 0xc0, 0x99, 0x2a,
+            0xb1, 0x77, 0xfb, 0xa5, 0x1d, 0xb9, 0x2c, 0x2a
+        ];
+
+        let bob: [u8; 32] = [
+            0x5d, 0xab, 0x08, 0x7e, 0x62, 0x4a, 0x8a, 0x4b,
+            0x79, 0xe1, 0x7f, 0x8b, 0x83, 0x80, 0x0e, 0xe6,
+            0x6f, 0x3b, 0xb1, 0x29, 0x26, 0x18, 0xb6, 0xfd,
+            0x1c, 0x2f, 0x8b, 0x27, 0xff, 0x88, 0xe0, 0xeb
+        ];
+
+        let alice_public: [u8; 32] = [
+            0x85, 0x20, 0xf0, 0x09, 0x89, 0x30, 0xa7, 0x54,
+            0x74, 0x8b, 0x7d, 0xdc, 0xb4, 0x3e, 0xf7, 0x5a,
+            0x0d, 0xbf, 0x3a, 0x0d, 0x26, 0x38, 0x1a, 0xf4,
+            0xeb, 0xa4, 0xa9, 0x8e, 0xaa, 0x9b, 0x4e, 0x6a
+        ];
+
+        let shared: [u8; 32] = [
+            0x4a, 0x5d, 0x9d, 0x5b, 0xa4, 0xce, 0x2d, 0xe1,
+            0x72, 0x8e, 0x3b, 0xf4, 0x80, 0x35, 0x0f, 0x25,
+            0xe0, 0x7e, 0x21, 0xc9, 0x47, 0xd1, 0x9e, 0x33,
+            0x76, 0xf0, 0x9b, 0x3c, 0x1e, 0x16, 0x17, 0x42
+        ];
+
+        assert_eq!(x25519_base(&alice), alice_public);
+        assert_eq!(x25519(&alice, &x25519_base(&bob)), shared);
+        assert_eq!(x25519(&bob, &alice_public), shared);
+    }
+}
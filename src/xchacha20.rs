@@ -0,0 +1,155 @@
+#![forbid(unsafe_code)]
+
+use crate::byte_manipulation::wipe;
+use crate::chacha20::ChaCha20;
+use crate::error::Error;
+
+/// XChaCha20, ChaCha20 with a 24-byte nonce
+///
+/// Follows draft-irtf-cfrg-xchacha: HChaCha20 turns the key and the first 16
+/// nonce bytes into a subkey, and ChaCha20 runs under that subkey with the
+/// nonce `0u32 || nonce[16..24]`. The 192-bit nonce is large enough to be
+/// picked at random for every message without tracking reuse.
+#[derive(Debug)]
+pub struct XChaCha20 {
+    chacha: ChaCha20,
+}
+
+impl XChaCha20 {
+    /// Returns a new instance of XChaCha20
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `key` is not of size 32
+    /// The function will panic if `nonce` is not of size 24
+    pub fn new(key: &[u8], nonce: &[u8], counter: u32) -> XChaCha20 {
+        assert_eq!(nonce.len(), 24);
+
+        let mut subkey = ChaCha20::hchacha20(key, &nonce[0..16]);
+
+        let mut chacha_nonce = [0u8; 12];
+        chacha_nonce[4..12].copy_from_slice(&nonce[16..24]);
+
+        let chacha = ChaCha20::new_with_raw_nonce(&subkey, &chacha_nonce, counter);
+        wipe(&mut subkey);
+
+        XChaCha20 { chacha }
+    }
+
+    /// Encrypts or decrypts `buf` in place, see `ChaCha20::apply_keystream`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `buf` is longer than `remaining_keystream`
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+        self.chacha.apply_keystream(buf);
+    }
+
+    /// Encrypts or decrypts `buf` in place, see `ChaCha20::try_apply_keystream`
+    pub fn try_apply_keystream(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.chacha.try_apply_keystream(buf)
+    }
+
+    /// Moves to byte `offset` of the keystream, see `ChaCha20::seek`
+    ///
+    /// # Panics
+    ///
+    /// The function will panic if `offset` is not below 256 GiB
+    pub fn seek(&mut self, offset: u64) {
+        self.chacha.seek(offset);
+    }
+
+    /// Returns the absolute keystream byte position
+    pub fn position(&self) -> u64 {
+        self.chacha.position()
+    }
+
+    /// Returns the number of keystream bytes left before the block counter wraps
+    pub fn remaining_keystream(&self) -> u64 {
+        self.chacha.remaining_keystream()
+    }
+
+    /// Returns the ChaCha20 instance running under the derived subkey
+    pub fn into_inner(self) -> ChaCha20 {
+        self.chacha
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+
+        key
+    }
+
+    fn nonce() -> [u8; 24] {
+        let mut nonce = [0u8; 24];
+        for (i, byte) in nonce.iter_mut().enumerate() {
+            *byte = 0x40 + i as u8;
+        }
+
+        nonce
+    }
+
+    #[test]
+    fn it_matches_the_draft_construction() {
+        let plaintext = b"The dhole (pronounced \"dole\") is also known as the Asiatic wild dog, red dog, and whistling dog.";
+
+        let expected: [u8; 96] = [
+            0xa5, 0x64, 0x16, 0xd4, 0x3f, 0x98, 0x9b, 0x36,
+            0x9e, 0x32, 0x5f, 0xa3, 0x84, 0xc1, 0xf3, 0x3a,
+            0x47, 0x29, 0x11, 0x0b, 0x61, 0x64, 0xbe, 0x88,
+            0xa5, 0xd6, 0x90, 0x2e, 0x6b, 0x75, 0x64, 0xbb,
+            0x20, 0x5d, 0x7c, 0x0e, 0x44, 0x4d, 0xf4, 0x13,
+            0x5e, 0xa4, 0x8f, 0x69, 0x0f, 0xe0, 0x7e, 0x2e,
+            0xa9, 0x74, 0x8a, 0x55, 0xdf, 0x52, 0xc5, 0x04,
+            0xc7, 0x87, 0xb7, 0x20, 0x14, 0x0a, 0xf0, 0x1d,
+            0x25, 0x8f, 0x85, 0xa8, 0x42, 0xa9, 0xd9, 0x01,
+            0xe5, 0x16, 0x76, 0xfe, 0xc8, 0x8e, 0xa8, 0xd0,
+            0x2f, 0x77, 0xbe, 0x63, 0xcc, 0x6f, 0x48, 0x96,
+            0x6d, 0xb9, 0x73, 0x35, 0x72, 0xc9, 0xf6, 0xa4
+        ];
+
+        let mut buf = *plaintext;
+        XChaCha20::new(&key(), &nonce(), 1).apply_keystream(&mut buf);
+        assert_eq!(&buf[..], &expected[..]);
+
+        XChaCha20::new(&key(), &nonce(), 1).apply_keystream(&mut buf);
+        assert_eq!(&buf[..], &plaintext[..]);
+    }
+
+    #[test]
+    fn it_uses_the_whole_nonce() {
+        let mut first = [0u8; 64];
+        XChaCha20::new(&key(), &nonce(), 0).apply_keystream(&mut first);
+
+        for position in &[0, 15, 16, 23] {
+            let mut nonce = nonce();
+            nonce[*position] ^= 1;
+
+            let mut other = [0u8; 64];
+            XChaCha20::new(&key(), &nonce, 0).apply_keystream(&mut other);
+            assert_ne!(&first[..], &other[..]);
+        }
+    }
+
+    #[test]
+    fn it_seeks_like_chacha20() {
+        let mut whole = [0u8; 100];
+        XChaCha20::new(&key(), &nonce(), 0).apply_keystream(&mut whole);
+
+        let mut xchacha20 = XChaCha20::new(&key(), &nonce(), 0);
+        xchacha20.seek(70);
+        let mut tail = [0u8; 30];
+        xchacha20.apply_keystream(&mut tail);
+
+        assert_eq!(&tail[..], &whole[70..]);
+        assert_eq!(xchacha20.position(), 100);
+    }
+}